@@ -0,0 +1,205 @@
+-- Plain-Postgres schema for twob-keepers (`STORAGE_BACKEND=postgres`).
+--
+-- Same tables as `timescale-schema.sql`, without `create_hypertable` or any
+-- other TimescaleDB extension, so the whole stack runs against a stock
+-- Postgres for local development and CI. Apply it with
+-- `STORAGE_BACKEND=postgres db-admin migrate`.
+--
+-- Notes:
+-- * The tables are ordinary heap tables; compression, retention and the hourly
+--   candle rollup are Timescale-only and are not available here.
+-- * read-api gap-fills candles in Rust instead of `time_bucket_gapfill`/`locf`.
+-- * Keep this file in step with `timescale-schema.sql`.
+
+-- ---------------------------------------------------------------------------
+-- Market configuration (token decimals / metadata)
+-- ---------------------------------------------------------------------------
+CREATE TABLE IF NOT EXISTS market_configs (
+    id             BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    market_id      BIGINT,
+    base_mint      TEXT,
+    quote_mint     TEXT,
+    base_decimals  SMALLINT,
+    quote_decimals SMALLINT,
+    base_ticker    TEXT,
+    quote_ticker   TEXT,
+    created_at     TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+CREATE UNIQUE INDEX IF NOT EXISTS market_configs_market_id_idx
+    ON market_configs (market_id);
+
+-- ---------------------------------------------------------------------------
+-- Raw market update events
+-- ---------------------------------------------------------------------------
+CREATE TABLE IF NOT EXISTS raw_market_update_events (
+    event_uid   TEXT NOT NULL,
+    signature   TEXT NOT NULL,
+    event_index INTEGER NOT NULL,
+    slot        BIGINT NOT NULL,
+    market_id   BIGINT NOT NULL,
+    base_flow   BIGINT NOT NULL,
+    quote_flow  BIGINT NOT NULL,
+    event_time  TIMESTAMPTZ NOT NULL,
+    ingested_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (event_time, event_uid),
+    UNIQUE (event_time, signature, event_index)
+);
+CREATE INDEX IF NOT EXISTS raw_market_update_events_market_time_idx
+    ON raw_market_update_events (market_id, event_time DESC);
+CREATE INDEX IF NOT EXISTS raw_market_update_events_slot_idx
+    ON raw_market_update_events (slot DESC);
+
+-- ---------------------------------------------------------------------------
+-- Raw close-position events
+-- ---------------------------------------------------------------------------
+CREATE TABLE IF NOT EXISTS raw_close_position_events (
+    event_uid          TEXT NOT NULL,
+    signature          TEXT NOT NULL,
+    event_index        INTEGER NOT NULL,
+    slot               BIGINT NOT NULL,
+    position_authority TEXT NOT NULL,
+    market_id          BIGINT NOT NULL,
+    start_slot         BIGINT NOT NULL,
+    end_slot           BIGINT NOT NULL,
+    deposit_amount     BIGINT NOT NULL,
+    swapped_amount     BIGINT NOT NULL,
+    remaining_amount   BIGINT NOT NULL,
+    fee_amount         BIGINT NOT NULL,
+    is_buy             BOOLEAN NOT NULL,
+    event_time         TIMESTAMPTZ NOT NULL,
+    ingested_at        TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (event_time, event_uid),
+    UNIQUE (event_time, signature, event_index)
+);
+CREATE INDEX IF NOT EXISTS raw_close_position_events_authority_time_idx
+    ON raw_close_position_events (position_authority, event_time DESC);
+CREATE INDEX IF NOT EXISTS raw_close_position_events_market_time_idx
+    ON raw_close_position_events (market_id, event_time DESC);
+CREATE INDEX IF NOT EXISTS raw_close_position_events_slot_idx
+    ON raw_close_position_events (slot DESC);
+
+-- ---------------------------------------------------------------------------
+-- 1-minute candles (true OHLC prices). Upserted by the keeper on every market
+-- update; the read-api gap-fills empty minutes by carrying the last close.
+-- ---------------------------------------------------------------------------
+CREATE TABLE IF NOT EXISTS market_candles_1m (
+    market_id    BIGINT NOT NULL,
+    bucket_start TIMESTAMPTZ NOT NULL,
+    open         NUMERIC NOT NULL,
+    high         NUMERIC NOT NULL,
+    low          NUMERIC NOT NULL,
+    close        NUMERIC NOT NULL,
+    updated_at   TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (market_id, bucket_start)
+);
+CREATE INDEX IF NOT EXISTS market_candles_1m_bucket_start_idx
+    ON market_candles_1m (bucket_start DESC);
+
+-- ---------------------------------------------------------------------------
+-- Daily fee revenue per market (UTC days). Upserted together with every new
+-- close-position event; `db-admin backfill-fees` rebuilds days from the raw
+-- events. A position pays its fee in the token it receives, so buys add to
+-- `base_fees` and sells to `quote_fees` (token atoms).
+-- ---------------------------------------------------------------------------
+CREATE TABLE IF NOT EXISTS market_fees_1d (
+    market_id    BIGINT NOT NULL,
+    bucket_start TIMESTAMPTZ NOT NULL,
+    base_fees    NUMERIC NOT NULL,
+    quote_fees   NUMERIC NOT NULL,
+    positions    BIGINT NOT NULL,
+    updated_at   TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (market_id, bucket_start)
+);
+CREATE INDEX IF NOT EXISTS market_fees_1d_bucket_start_idx
+    ON market_fees_1d (bucket_start DESC);
+
+-- ---------------------------------------------------------------------------
+-- Market state snapshots. Written periodically by `account-indexer` from the
+-- on-chain `Market` and `Bookkeeping` accounts. u64/u128 amounts are stored as
+-- NUMERIC so they never overflow.
+-- ---------------------------------------------------------------------------
+CREATE TABLE IF NOT EXISTS market_state_snapshots (
+    market_id                    BIGINT NOT NULL,
+    snapshot_time                TIMESTAMPTZ NOT NULL,
+    slot                         BIGINT NOT NULL,
+    is_paused                    BOOLEAN NOT NULL,
+    fee_bps                      SMALLINT NOT NULL,
+    unhealthy_liquidity_fee_bps  SMALLINT NOT NULL,
+    open_positions               BIGINT NOT NULL,
+    accumulated_base_fees        NUMERIC NOT NULL,
+    accumulated_quote_fees       NUMERIC NOT NULL,
+    base_flow                    NUMERIC NOT NULL,
+    quote_flow                   NUMERIC NOT NULL,
+    end_slot_interval            BIGINT NOT NULL,
+    base_per_quote               NUMERIC,
+    quote_per_base               NUMERIC,
+    slots_without_trade          BIGINT,
+    bookkeeping_last_update_slot BIGINT,
+    PRIMARY KEY (market_id, snapshot_time)
+);
+
+-- ---------------------------------------------------------------------------
+-- Open trade positions. Mirrors the live on-chain `TradePosition` accounts:
+-- `account-indexer` upserts them from `getProgramAccounts` and a program
+-- subscription, and deletes rows once the account is closed. `observed_slot`
+-- is the slot the row was read at; older reads never overwrite newer ones.
+-- ---------------------------------------------------------------------------
+CREATE TABLE IF NOT EXISTS trade_positions (
+    address        TEXT PRIMARY KEY,
+    market_id      BIGINT NOT NULL,
+    authority      TEXT NOT NULL,
+    position_id    NUMERIC NOT NULL,
+    is_buy         BOOLEAN NOT NULL,
+    amount         NUMERIC NOT NULL,
+    start_slot     BIGINT NOT NULL,
+    end_slot       BIGINT NOT NULL,
+    observed_slot  BIGINT NOT NULL,
+    updated_at     TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+CREATE INDEX IF NOT EXISTS trade_positions_authority_idx
+    ON trade_positions (authority, end_slot);
+CREATE INDEX IF NOT EXISTS trade_positions_market_idx
+    ON trade_positions (market_id, end_slot);
+
+-- ---------------------------------------------------------------------------
+-- Liquidity positions. Mirrors the live on-chain `LiquidityPosition` accounts,
+-- kept like `trade_positions`. Balances and bookkeeping snapshots carry the
+-- program's bookkeeping precision; flows and debts are token atoms.
+-- ---------------------------------------------------------------------------
+CREATE TABLE IF NOT EXISTS liquidity_positions (
+    address                      TEXT PRIMARY KEY,
+    market_id                    BIGINT NOT NULL,
+    authority                    TEXT NOT NULL,
+    base_balance                 NUMERIC NOT NULL,
+    quote_balance                NUMERIC NOT NULL,
+    base_per_quote_snapshot      NUMERIC NOT NULL,
+    quote_per_base_snapshot      NUMERIC NOT NULL,
+    slots_without_trade_snapshot BIGINT NOT NULL,
+    base_flow                    NUMERIC NOT NULL,
+    quote_flow                   NUMERIC NOT NULL,
+    base_debt                    NUMERIC NOT NULL,
+    quote_debt                   NUMERIC NOT NULL,
+    last_update_slot             BIGINT NOT NULL,
+    observed_slot                BIGINT NOT NULL,
+    updated_at                   TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+CREATE INDEX IF NOT EXISTS liquidity_positions_authority_idx
+    ON liquidity_positions (authority);
+CREATE INDEX IF NOT EXISTS liquidity_positions_market_idx
+    ON liquidity_positions (market_id);
+
+-- ---------------------------------------------------------------------------
+-- Upcoming market exits. Flow that leaves each market at a slot because trade
+-- positions end there, read by `account-indexer` from the `Exits` accounts
+-- ahead of the current slot. Each snapshot replaces the market's rows; flows
+-- are u128 and stored as NUMERIC.
+-- ---------------------------------------------------------------------------
+CREATE TABLE IF NOT EXISTS market_exits (
+    market_id        BIGINT NOT NULL,
+    exit_slot        BIGINT NOT NULL,
+    reference_index  BIGINT NOT NULL,
+    base_exit        NUMERIC NOT NULL,
+    quote_exit       NUMERIC NOT NULL,
+    observed_slot    BIGINT NOT NULL,
+    PRIMARY KEY (market_id, exit_slot)
+);
@@ -14,11 +14,16 @@ pub mod seeds {
     pub const TRADE_POSITION: &[u8] = b"trade_position";
     pub const EXITS: &[u8] = b"exits";
     pub const PRICES: &[u8] = b"prices";
+    pub const TOKEN_METADATA: &[u8] = b"metadata";
 }
 
 /// The Associated Token Program ID
 pub const ASSOCIATED_TOKEN_PROGRAM_ID: Pubkey = anchor_spl::associated_token::ID;
 
+/// The Metaplex Token Metadata Program ID
+pub const TOKEN_METADATA_PROGRAM_ID: Pubkey =
+    anchor_lang::pubkey!("metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s");
+
 /// Helper struct for resolving all program PDAs.
 ///
 /// # Example
@@ -125,6 +130,21 @@ impl AccountResolver {
         anchor_spl::associated_token::get_associated_token_address(wallet, mint)
     }
 
+    /// Derive the Metaplex token metadata account for a mint.
+    ///
+    /// Seeds: `["metadata", token_metadata_program, mint]` (owned by the
+    /// Token Metadata program, not TwoB).
+    pub fn token_metadata_pda(&self, mint: &Pubkey) -> PdaResult {
+        PdaResult::find(
+            &[
+                seeds::TOKEN_METADATA,
+                TOKEN_METADATA_PROGRAM_ID.as_ref(),
+                mint.as_ref(),
+            ],
+            &TOKEN_METADATA_PROGRAM_ID,
+        )
+    }
+
     /// Derive a token vault PDA for a market.
     ///
     /// This is an Associated Token Account owned by the market PDA.
@@ -229,6 +249,18 @@ mod tests {
         assert_ne!(pos1.address(), pos2.address());
     }
 
+    #[test]
+    fn test_token_metadata_pda_ignores_program_id() {
+        let mint = Pubkey::new_unique();
+        let resolver1 = AccountResolver::new(Pubkey::new_unique());
+        let resolver2 = AccountResolver::new(Pubkey::new_unique());
+
+        assert_eq!(
+            resolver1.token_metadata_pda(&mint),
+            resolver2.token_metadata_pda(&mint)
+        );
+    }
+
     #[test]
     fn test_pda_result_conversions() {
         let program_id = Pubkey::new_unique();
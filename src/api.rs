@@ -0,0 +1,808 @@
+//! Request and response types of the `read-api` HTTP API.
+//!
+//! `read-api` serves these types and describes them in its OpenAPI document
+//! (`/openapi.json`); [`crate::client::ReadApiClient`] decodes them. Keeping
+//! both sides on the same definitions means a change to the API shows up as a
+//! compile error in its callers rather than as a decoding failure at runtime.
+//!
+//! Timestamps are RFC 3339 strings. u64/u128 token amounts are decimal strings
+//! so they survive JSON number precision; prices are JSON numbers.
+
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::policies::HypertableStats;
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct ErrorResponse {
+    pub error: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct HealthResponse {
+    pub status: String,
+}
+
+/// Answer of `/readyz`, sent with `200` when ready and `503` otherwise.
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct ReadinessResponse {
+    /// `ready` or `not_ready`.
+    pub status: String,
+    /// Why the instance is not ready; empty when it is.
+    pub reasons: Vec<String>,
+    /// Round trip of the first query; `None` when the database was unreachable.
+    pub database_latency_ms: Option<u64>,
+    pub tables: Vec<TableReadiness>,
+    /// Staleness threshold; `0` when the ingestion check is off.
+    pub max_update_age_secs: u64,
+    pub markets: Vec<MarketReadiness>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct TableReadiness {
+    pub name: String,
+    pub present: bool,
+}
+
+/// Latest market update of one configured market.
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct MarketReadiness {
+    pub market_id: u64,
+    /// `None` when the market has no updates yet.
+    pub latest_update_slot: Option<u64>,
+    pub latest_update_time: Option<String>,
+    pub age_secs: Option<u64>,
+    pub stale: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct StorageStatusResponse {
+    /// `postgres` or `timescale`.
+    pub backend: String,
+    pub points: usize,
+    pub hypertables: Vec<HypertableStats>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SlotTimeQuery {
+    pub slot: u64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct TimeSlotQuery {
+    pub ts: DateTime<Utc>,
+}
+
+/// How a slot ⇄ time conversion was derived from the indexed events.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SlotEstimate {
+    /// An indexed event at exactly this slot or time.
+    Exact,
+    /// Between the nearest indexed events either side.
+    Interpolated,
+    /// From one side only, at the nominal 400ms slot time.
+    Extrapolated,
+}
+
+/// Answer of `/v1/time/slot` and `/v1/time/at`. Times are when the keeper
+/// ingested the events, not block times, so they trail the chain slightly.
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct SlotTimeResponse {
+    pub slot: u64,
+    pub time: String,
+    pub estimate: SlotEstimate,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct MarketConfig {
+    pub market_id: u64,
+    pub base_mint: Option<String>,
+    pub quote_mint: Option<String>,
+    pub base_decimals: Option<i16>,
+    pub quote_decimals: Option<i16>,
+    pub base_ticker: Option<String>,
+    pub quote_ticker: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct MarketConfigListResponse {
+    pub points: usize,
+    pub items: Vec<MarketConfig>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct LatestPriceResponse {
+    pub market_id: u64,
+    pub slot: u64,
+    pub event_time: String,
+    /// Server-side only: orders snapshots and builds SSE event ids. Zero when
+    /// decoded from a response.
+    #[serde(skip)]
+    pub event_time_ms: i64,
+    #[serde(skip)]
+    pub event_index: u16,
+    #[serde(skip)]
+    pub signature: String,
+    pub price: Decimal,
+}
+
+impl LatestPriceResponse {
+    /// SSE event id of the update this price comes from:
+    /// `<slot>:<event_index>:<signature>`. The event index is only unique
+    /// within a transaction, so the signature tells apart updates of several
+    /// transactions in one slot.
+    pub fn event_id(&self) -> String {
+        format!("{}:{}:{}", self.slot, self.event_index, self.signature)
+    }
+}
+
+/// `format` on the list endpoints selects a `csv` or `parquet` download
+/// instead of JSON; the typed client always asks for JSON.
+#[derive(Clone, Debug, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct CandleQuery {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    /// `1m`, `5m`, `15m`, `1h`, `4h` or `1d`; defaults to `1m`.
+    pub interval: Option<String>,
+    pub max_points: Option<usize>,
+    pub format: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct CandleResponse {
+    pub market_id: u64,
+    pub interval: String,
+    pub from: String,
+    pub to: String,
+    pub points: usize,
+    pub items: Vec<CandleItem>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct CandleItem {
+    /// Bucket start, Unix seconds.
+    pub time: u64,
+    pub open: Decimal,
+    pub high: Decimal,
+    pub low: Decimal,
+    pub close: Decimal,
+}
+
+/// The range is `start_slot`/`end_slot` or, mapped to slots, `from`/`to`;
+/// each bound takes one of the two.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct MarketHistoryQuery {
+    pub start_slot: Option<u64>,
+    pub end_slot: Option<u64>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    /// Page size.
+    pub max_rows: Option<usize>,
+    /// `next_cursor` of the previous page.
+    pub cursor: Option<String>,
+    pub format: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct MarketHistoryResponse {
+    pub market_id: u64,
+    pub start_slot: u64,
+    pub end_slot: u64,
+    pub has_more: bool,
+    /// Pass as `cursor` to fetch the rest of the range.
+    pub next_cursor: Option<String>,
+    pub points: usize,
+    pub items: Vec<MarketHistoryItem>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct MarketUpdatesQuery {
+    pub before_slot: Option<u64>,
+    pub limit: Option<usize>,
+    pub format: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct MarketUpdatesResponse {
+    pub market_id: u64,
+    pub before_slot: Option<u64>,
+    pub has_more: bool,
+    pub limit: usize,
+    pub points: usize,
+    pub items: Vec<MarketHistoryItem>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct MarketHistoryItem {
+    pub event_uid: String,
+    pub signature: String,
+    pub event_index: u16,
+    pub slot: u64,
+    pub market_id: u64,
+    pub base_flow: String,
+    pub quote_flow: String,
+    pub created_at: String,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct MarketStateQuery {
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct MarketStateResponse {
+    pub market_id: u64,
+    pub from: String,
+    pub to: String,
+    pub has_more: bool,
+    pub limit: usize,
+    pub current: MarketStateItem,
+    pub points: usize,
+    pub items: Vec<MarketStateItem>,
+}
+
+/// One `market_state_snapshots` row.
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct MarketStateItem {
+    pub snapshot_time: String,
+    pub slot: u64,
+    pub is_paused: bool,
+    pub fee_bps: u16,
+    pub unhealthy_liquidity_fee_bps: u16,
+    pub open_positions: u64,
+    pub accumulated_base_fees: String,
+    pub accumulated_quote_fees: String,
+    pub base_flow: String,
+    pub quote_flow: String,
+    pub end_slot_interval: u64,
+    pub base_per_quote: Option<String>,
+    pub quote_per_base: Option<String>,
+    pub slots_without_trade: Option<u64>,
+    pub bookkeeping_last_update_slot: Option<u64>,
+}
+
+/// Range as in [`MarketHistoryQuery`].
+#[derive(Clone, Debug, Default, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ClosedPositionMiniChartQuery {
+    pub start_slot: Option<u64>,
+    pub end_slot: Option<u64>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub max_points: Option<usize>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct ClosedPositionMiniChartResponse {
+    pub market_id: u64,
+    pub start_slot: u64,
+    pub end_slot: u64,
+    pub points: usize,
+    pub items: Vec<ClosedPositionMiniChartItem>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct ClosedPositionMiniChartItem {
+    pub slot: u64,
+    pub price: Decimal,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ClosedPositionsQuery {
+    pub market_id: Option<u64>,
+    pub before_slot: Option<u64>,
+    /// Only positions closed at or after this time.
+    pub from: Option<DateTime<Utc>>,
+    /// Only positions closed at or before this time.
+    pub to: Option<DateTime<Utc>>,
+    pub limit: Option<usize>,
+    pub format: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct ClosedPositionsResponse {
+    pub authority: String,
+    pub market_id: Option<u64>,
+    pub before_slot: Option<u64>,
+    /// `from` and `to` mapped to slots.
+    pub from_slot: Option<u64>,
+    pub to_slot: Option<u64>,
+    pub has_more: bool,
+    pub limit: usize,
+    pub points: usize,
+    pub items: Vec<ClosedPositionItem>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct ClosedPositionItem {
+    pub signature: String,
+    pub event_index: u16,
+    pub slot: u64,
+    pub market_id: u64,
+    pub start_slot: u64,
+    pub end_slot: u64,
+    pub deposit_amount: String,
+    pub swapped_amount: String,
+    pub remaining_amount: String,
+    pub fee_amount: String,
+    pub is_buy: bool,
+    pub event_time: String,
+}
+
+/// Rolling 24h statistics of one market.
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct TickerItem {
+    pub market_id: u64,
+    pub base_ticker: Option<String>,
+    pub quote_ticker: Option<String>,
+    /// Price at the start of the window: the last close before it, or the
+    /// first open inside it for markets younger than 24h.
+    pub open: Option<Decimal>,
+    pub high: Option<Decimal>,
+    pub low: Option<Decimal>,
+    pub last: Option<Decimal>,
+    pub change_percent: Option<Decimal>,
+    /// Amount traded in the window, in token units: each market update's
+    /// per-slot flow times the slots until the next update (or the newest
+    /// indexed slot). The flow already active when the window opens counts
+    /// for the share of its span inside the window. `None` when the market
+    /// config has no decimals.
+    pub base_volume: Option<Decimal>,
+    pub quote_volume: Option<Decimal>,
+    pub update_count: u64,
+    pub window_start: String,
+    pub as_of: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct TickerListResponse {
+    pub points: usize,
+    pub items: Vec<TickerItem>,
+}
+
+/// A listed pair of `/v1/aggregator/pairs`. `ticker_id` is
+/// `<base_mint>_<quote_mint>` and `pool_id` the market id.
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct AggregatorPairItem {
+    pub ticker_id: String,
+    pub base: String,
+    pub target: String,
+    pub pool_id: String,
+    pub base_symbol: Option<String>,
+    pub target_symbol: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct AggregatorTickerItem {
+    pub ticker_id: String,
+    pub base_currency: String,
+    pub target_currency: String,
+    pub pool_id: String,
+    pub last_price: Option<Decimal>,
+    pub base_volume: Option<Decimal>,
+    pub target_volume: Option<Decimal>,
+    /// Always `null`: read-api has no USD price source, and a TWAP market
+    /// holds no pooled reserves to value.
+    pub liquidity_in_usd: Option<Decimal>,
+    /// Always `null`: TWAP markets have no order book.
+    pub bid: Option<Decimal>,
+    pub ask: Option<Decimal>,
+    pub high: Option<Decimal>,
+    pub low: Option<Decimal>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct HistoricalTradesQuery {
+    pub ticker_id: String,
+    /// `buy` or `sell`; both when unset.
+    #[serde(rename = "type")]
+    pub trade_type: Option<String>,
+    pub limit: Option<usize>,
+    /// Unix milliseconds, inclusive.
+    pub start_time: Option<i64>,
+    /// Unix milliseconds, exclusive.
+    pub end_time: Option<i64>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct HistoricalTradesResponse {
+    pub buy: Vec<HistoricalTradeItem>,
+    pub sell: Vec<HistoricalTradeItem>,
+}
+
+/// A closed trade position, priced at its average fill.
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct HistoricalTradeItem {
+    pub trade_id: String,
+    pub pool_id: String,
+    pub price: Decimal,
+    pub base_volume: Decimal,
+    pub target_volume: Decimal,
+    /// Unix milliseconds.
+    pub trade_timestamp: i64,
+    /// `buy` or `sell`.
+    #[serde(rename = "type")]
+    pub trade_type: String,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AggregatorLiquidityQuery {
+    pub ticker_id: Option<String>,
+}
+
+/// Live flows of one pool; TWAP markets have no order book.
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct AggregatorLiquidityItem {
+    pub ticker_id: String,
+    pub pool_id: String,
+    pub snapshot_time: String,
+    pub slot: u64,
+    pub is_paused: bool,
+    /// Base sold into the market per slot, in token units.
+    pub base_flow_per_slot: Decimal,
+    /// Quote spent buying per slot, in token units.
+    pub target_flow_per_slot: Decimal,
+    pub open_positions: u64,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SummaryQuery {
+    pub market_id: Option<u64>,
+}
+
+/// A wallet's closed trade positions aggregated per market and side.
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct SummaryResponse {
+    pub authority: String,
+    pub market_id: Option<u64>,
+    pub position_count: usize,
+    pub buy_count: usize,
+    pub sell_count: usize,
+    /// Set when the wallet has more than the summarized positions; only the
+    /// newest ones are included.
+    pub truncated: bool,
+    pub markets: Vec<MarketSummary>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct MarketSummary {
+    pub market_id: u64,
+    pub base_ticker: Option<String>,
+    pub quote_ticker: Option<String>,
+    pub buy: SideSummary,
+    pub sell: SideSummary,
+}
+
+/// Totals of one side of one market. `deposited`/`remaining` are in the
+/// token spent (quote for buys, base for sells), `swapped` and `fees` in the
+/// token received.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct SideSummary {
+    pub count: usize,
+    pub deposited: Decimal,
+    pub swapped: Decimal,
+    pub remaining: Decimal,
+    pub fees: Decimal,
+    pub base_volume: Decimal,
+    pub quote_volume: Decimal,
+    /// Quote per base actually received or paid.
+    pub average_execution_price: Option<Decimal>,
+    /// Market TWAP over each position's window, weighted by its base volume.
+    pub market_average_price: Option<Decimal>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AuthorityExecutionQuery {
+    pub market_id: Option<u64>,
+    pub before_slot: Option<u64>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct AuthorityExecutionResponse {
+    pub authority: String,
+    pub market_id: Option<u64>,
+    pub before_slot: Option<u64>,
+    pub has_more: bool,
+    pub limit: usize,
+    pub points: usize,
+    pub items: Vec<ExecutionItem>,
+}
+
+/// A closed position's fill against the market TWAP over its window. Slippage
+/// is in basis points, positive when worse than the market.
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct ExecutionItem {
+    pub signature: String,
+    pub event_index: u16,
+    pub slot: u64,
+    pub market_id: u64,
+    pub start_slot: u64,
+    pub end_slot: u64,
+    pub is_buy: bool,
+    pub event_time: String,
+    pub base_amount: Option<Decimal>,
+    pub quote_amount: Option<Decimal>,
+    pub execution_price: Option<Decimal>,
+    pub market_price: Option<Decimal>,
+    pub slippage_bps: Option<Decimal>,
+}
+
+/// Window of `/execution-quality`; defaults to the last 7 days.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct MarketExecutionQuery {
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct MarketExecutionResponse {
+    pub market_id: u64,
+    pub from: String,
+    pub to: String,
+    /// Set when the window holds more positions than were evaluated; only
+    /// the newest ones are included.
+    pub truncated: bool,
+    pub all: ExecutionStats,
+    pub buy: ExecutionStats,
+    pub sell: ExecutionStats,
+}
+
+/// Slippage distribution over positions with a market price.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ExecutionStats {
+    pub position_count: usize,
+    pub quote_volume: Decimal,
+    /// Slippage weighted by each position's quote volume.
+    pub volume_weighted_slippage_bps: Option<Decimal>,
+    pub median_slippage_bps: Option<Decimal>,
+    pub p10_slippage_bps: Option<Decimal>,
+    pub p90_slippage_bps: Option<Decimal>,
+    /// Fraction of positions that did at least as well as the market.
+    pub at_or_better_than_market_share: Option<Decimal>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AuthorityOpenPositionsQuery {
+    pub market_id: Option<u64>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct MarketOpenPositionsQuery {
+    pub limit: Option<usize>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct OpenPositionsResponse {
+    pub authority: Option<String>,
+    pub market_id: Option<u64>,
+    /// Estimated current slot the progress figures are based on; `None` when
+    /// nothing has been indexed yet.
+    pub current_slot: Option<u64>,
+    pub has_more: bool,
+    pub limit: usize,
+    pub points: usize,
+    pub items: Vec<OpenPositionItem>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct OpenPositionItem {
+    pub address: String,
+    pub market_id: u64,
+    pub authority: String,
+    pub position_id: String,
+    pub is_buy: bool,
+    /// Amount to spend in atoms: quote for buys, base for sells.
+    pub amount: String,
+    /// `amount` in token units; `None` when the market config has no decimals.
+    pub amount_units: Option<Decimal>,
+    pub start_slot: u64,
+    pub end_slot: u64,
+    /// Slot the indexer last read the account at.
+    pub observed_slot: u64,
+    /// Missing when no slot is known yet.
+    #[serde(flatten)]
+    pub progress: Option<PositionProgress>,
+}
+
+/// Where a position stands at the estimated current slot.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct PositionProgress {
+    /// `pending` before `start_slot`, `active` while trading, `expired` once
+    /// `end_slot` has passed and the position waits to be closed. Positions
+    /// are dropped once their close-position event is indexed.
+    pub status: String,
+    /// Share of the `[start_slot, end_slot)` window elapsed, `0` to `1`.
+    pub progress: Decimal,
+    pub slots_remaining: u64,
+    pub seconds_to_expiry: u64,
+    pub estimated_end_time: String,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AuthorityLiquidityQuery {
+    pub market_id: Option<u64>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct MarketLiquidityQuery {
+    pub limit: Option<usize>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct LiquidityPositionsResponse {
+    pub authority: Option<String>,
+    pub market_id: Option<u64>,
+    /// Estimated current slot health is projected to; `None` when nothing has
+    /// been indexed yet.
+    pub current_slot: Option<u64>,
+    pub has_more: bool,
+    pub points: usize,
+    pub items: Vec<LiquidityPositionItem>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct LiquidityPositionItem {
+    pub address: String,
+    pub market_id: u64,
+    pub authority: String,
+    /// Balances at `last_update_slot`, in token units. Amounts are `None`
+    /// when the market config has no decimals or they do not fit a decimal.
+    pub base_balance: Option<Decimal>,
+    pub quote_balance: Option<Decimal>,
+    /// Tokens sold per slot, in token units.
+    pub base_flow: Option<Decimal>,
+    pub quote_flow: Option<Decimal>,
+    pub base_debt: Option<Decimal>,
+    pub quote_debt: Option<Decimal>,
+    pub last_update_slot: u64,
+    /// Slot the indexer last read the account at.
+    pub observed_slot: u64,
+    /// Market price (quote per base) the projection used.
+    pub market_price: Option<Decimal>,
+    /// `None` when the market config has no decimals, an amount does not fit
+    /// a decimal or no slot is known.
+    pub health: Option<LiquidityHealth>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct LiquidityHealth {
+    /// `in_debt` once debt was recorded, `unhealthy` when a projected balance
+    /// is exhausted, `at_risk` when one runs out within about an hour, `idle`
+    /// without flows, otherwise `healthy`.
+    pub status: String,
+    pub projected_base_balance: Decimal,
+    pub projected_quote_balance: Decimal,
+    /// Net change per slot: incoming at the market price minus the flow sold.
+    pub net_base_per_slot: Decimal,
+    pub net_quote_per_slot: Decimal,
+    /// The balance that runs out first (`base` or `quote`), if either does.
+    pub depleting: Option<String>,
+    pub slots_until_depleted: Option<u64>,
+    pub seconds_until_depleted: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub estimated_depletion_time: Option<String>,
+}
+
+/// How a market's flows wind down as trade positions end, from its latest
+/// state snapshot.
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct FlowScheduleResponse {
+    pub market_id: u64,
+    /// Slot and time of the state snapshot the schedule starts from.
+    pub slot: u64,
+    pub snapshot_time: String,
+    /// Flows per slot in token units; `None` when the market config has no
+    /// decimals.
+    pub base_flow: Option<Decimal>,
+    pub quote_flow: Option<Decimal>,
+    /// Quote per base implied by the flows.
+    pub price: Option<Decimal>,
+    pub points: usize,
+    pub items: Vec<FlowScheduleItem>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct FlowScheduleItem {
+    pub slot: u64,
+    pub estimated_time: String,
+    pub base_exit: Option<Decimal>,
+    pub quote_exit: Option<Decimal>,
+    /// Flows left once this slot's exits are gone.
+    pub base_flow: Option<Decimal>,
+    pub quote_flow: Option<Decimal>,
+    pub price: Option<Decimal>,
+    /// Change of the implied price against the snapshot's.
+    pub price_change_percent: Option<Decimal>,
+}
+
+/// Defaults to the last 30 days in daily buckets.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct FeesQuery {
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub market_id: Option<u64>,
+    /// `day`, `week` or `month`.
+    pub interval: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct FeesResponse {
+    pub from: String,
+    pub to: String,
+    pub interval: String,
+    pub market_id: Option<u64>,
+    pub markets: Vec<MarketFees>,
+    pub mints: Vec<MintFees>,
+    pub points: usize,
+    pub items: Vec<FeeBucket>,
+}
+
+/// Fees of one market in one `interval` bucket, in token units; `None` when
+/// the market config has no decimals.
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct FeeBucket {
+    pub bucket_start: String,
+    pub market_id: u64,
+    pub positions: i64,
+    pub base_fees: Option<Decimal>,
+    pub quote_fees: Option<Decimal>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct MarketFees {
+    pub market_id: u64,
+    pub base_mint: Option<String>,
+    pub quote_mint: Option<String>,
+    pub base_ticker: Option<String>,
+    pub quote_ticker: Option<String>,
+    pub positions: i64,
+    pub base_fees: Option<Decimal>,
+    pub quote_fees: Option<Decimal>,
+    /// The `Market` account's fee counters at its latest state snapshot.
+    pub accumulated_base_fees: Option<Decimal>,
+    pub accumulated_quote_fees: Option<Decimal>,
+}
+
+/// Fees of one mint across markets.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct MintFees {
+    pub mint: String,
+    pub ticker: Option<String>,
+    pub fees: Decimal,
+}
+
+/// Counters of the calling API key since the process started.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, ToSchema)]
+pub struct UsageResponse {
+    pub key_name: String,
+    pub since: String,
+    pub requests: u64,
+    /// Tokens charged to the key.
+    pub cost: u64,
+    pub rate_limited: u64,
+    pub last_request_at: Option<String>,
+}
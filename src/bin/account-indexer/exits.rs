@@ -0,0 +1,153 @@
+//! Upcoming market exits: the flow each market loses at future slots because
+//! trade positions end there.
+//!
+//! `Exits` accounts are PDAs indexed by `reference_index = slot / ARRAY_LENGTH /
+//! end_slot_interval`; entry `i` of account `index` holds the flow that leaves at
+//! slot `(index * ARRAY_LENGTH + i) * end_slot_interval`. Every snapshot reads
+//! the current account and the next ones and replaces the market's rows in
+//! `market_exits`. A market with an account that fails to decode keeps its
+//! previous rows until a later snapshot reads all of them.
+
+use anchor_client::solana_sdk::commitment_config::CommitmentConfig;
+use anchor_lang::{AccountDeserialize, prelude::*};
+use anyhow::{Context, Result};
+use deadpool_postgres::Pool;
+use solana_rpc_client::nonblocking::rpc_client::RpcClient;
+use twob_keepers::{
+    ARRAY_LENGTH, AccountResolver,
+    database::{MarketExitRecord, replace_market_exits},
+};
+
+use crate::{
+    MAX_MULTIPLE_ACCOUNTS,
+    twob_anchor::accounts::{Exits, Market},
+};
+
+/// Read the next `lookahead` `Exits` accounts of every market at `slot` and
+/// store their non-empty entries. Returns the number of exits written.
+pub(crate) async fn snapshot_market_exits(
+    pool: &Pool,
+    rpc: &RpcClient,
+    resolver: &AccountResolver,
+    markets: &[(Pubkey, Market)],
+    slot: u64,
+    lookahead: u64,
+) -> Result<usize> {
+    // Markets without an interval cannot have positions.
+    let markets: Vec<&(Pubkey, Market)> = markets
+        .iter()
+        .filter(|(_, market)| market.end_slot_interval > 0)
+        .collect();
+    let keys: Vec<(usize, Pubkey)> = markets
+        .iter()
+        .enumerate()
+        .flat_map(|(position, (address, market))| {
+            let first_index = reference_index(slot, market.end_slot_interval);
+            (first_index..first_index + lookahead)
+                .map(move |index| (position, resolver.exits_pda(address, index).address()))
+        })
+        .collect();
+
+    // `None` once one of the market's accounts fails to decode.
+    let mut exits_by_market: Vec<Option<Vec<MarketExitRecord>>> =
+        vec![Some(Vec::new()); markets.len()];
+    for chunk in keys.chunks(MAX_MULTIPLE_ACCOUNTS) {
+        let addresses: Vec<Pubkey> = chunk.iter().map(|(_, address)| *address).collect();
+        let accounts = rpc
+            .get_multiple_accounts_with_commitment(&addresses, CommitmentConfig::confirmed())
+            .await
+            .context("getMultipleAccounts RPC failed for exits")?
+            .value;
+
+        for ((position, address), account) in chunk.iter().zip(accounts) {
+            // Exits accounts are created on demand; most future ones do not exist.
+            let Some(account) = account else {
+                continue;
+            };
+            let Some(records) = &mut exits_by_market[*position] else {
+                continue;
+            };
+            match Exits::try_deserialize(&mut account.data.as_slice()) {
+                Ok(exits) => records.extend(exit_records(
+                    &exits,
+                    markets[*position].1.end_slot_interval,
+                    slot,
+                )),
+                Err(error) => {
+                    eprintln!(
+                        "Failed to decode exits account {address}, keeping previous exits of market_id={}: {error}",
+                        markets[*position].1.id
+                    );
+                    exits_by_market[*position] = None;
+                }
+            }
+        }
+    }
+
+    let mut written = 0;
+    for ((_, market), exits) in markets.iter().zip(exits_by_market) {
+        let Some(exits) = exits else {
+            continue;
+        };
+        replace_market_exits(pool, market.id, slot, &exits)
+            .await
+            .with_context(|| format!("market_id={}", market.id))?;
+        written += exits.len();
+    }
+    Ok(written)
+}
+
+fn reference_index(slot: u64, end_slot_interval: u64) -> u64 {
+    slot / ARRAY_LENGTH / end_slot_interval
+}
+
+/// Entries of one `Exits` account that end after `slot` and move any flow.
+fn exit_records(exits: &Exits, end_slot_interval: u64, slot: u64) -> Vec<MarketExitRecord> {
+    exits
+        .base_exits
+        .iter()
+        .zip(exits.quote_exits.iter())
+        .enumerate()
+        .filter_map(|(offset, (&base_exit, &quote_exit))| {
+            let exit_slot = (exits.index * ARRAY_LENGTH + offset as u64) * end_slot_interval;
+            (exit_slot > slot && (base_exit > 0 || quote_exit > 0)).then_some(MarketExitRecord {
+                exit_slot,
+                reference_index: exits.index,
+                base_exit,
+                quote_exit,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exit_records_map_offsets_to_slots() {
+        let mut base_exits = [0u128; 10];
+        let mut quote_exits = [0u128; 10];
+        base_exits[1] = 5;
+        quote_exits[3] = 7;
+        base_exits[9] = 2;
+        let exits = Exits {
+            owner: Pubkey::default(),
+            base_exits,
+            quote_exits,
+            open_positions: 3,
+            index: 4,
+            bump: 255,
+        };
+
+        // Account 4 with an interval of 25 covers slots 1000..1250; the exit
+        // at slot 1025 is already behind slot 1030.
+        let records = exit_records(&exits, 25, 1_030);
+        let slots: Vec<(u64, u128, u128)> = records
+            .iter()
+            .map(|record| (record.exit_slot, record.base_exit, record.quote_exit))
+            .collect();
+        assert_eq!(slots, vec![(1_075, 0, 7), (1_225, 2, 0)]);
+        assert_eq!(reference_index(1_030, 25), 4);
+    }
+}
@@ -0,0 +1,576 @@
+use anchor_client::{
+    Client, Cluster, Program,
+    solana_sdk::{commitment_config::CommitmentConfig, signature::Keypair},
+};
+use anchor_lang::{AccountDeserialize, prelude::*};
+use anchor_spl::token_interface::Mint;
+use anyhow::{Context, Result, anyhow};
+use chrono::{DateTime, Utc};
+use deadpool_postgres::Pool;
+use solana_rpc_client::nonblocking::rpc_client::RpcClient;
+use std::{collections::HashMap, env, sync::Arc, time::Duration};
+use tokio::time::MissedTickBehavior;
+use twob_keepers::{
+    AccountResolver,
+    database::{
+        BookkeepingSnapshot, CandleBackfillWindow, MarketConfigRecord, MarketStateSnapshotRecord,
+        backfill_missing_candles, connect_pool, insert_market_state_snapshot, upsert_market_config,
+    },
+    policies::{candles_1m_retention_from_env, interval_env},
+};
+
+mod exits;
+mod positions;
+
+declare_program!(twob_anchor);
+use exits::snapshot_market_exits;
+use positions::{MarketDirectory, run_position_subscriptions, sync_positions};
+use twob_anchor::accounts::{Bookkeeping, Market};
+
+const DEFAULT_MARKET_SYNC_INTERVAL_SECS: u64 = 300;
+const DEFAULT_SNAPSHOT_INTERVAL_SECS: u64 = 60;
+const DEFAULT_POSITION_SYNC_INTERVAL_SECS: u64 = 60;
+/// Recent enough to stay in uncompressed chunks (see `MARKET_UPDATES_COMPRESS_AFTER`).
+const DEFAULT_CANDLE_BACKFILL_LOOKBACK: &str = "7 days";
+/// Later candle backfill passes re-check this much before the previous one:
+/// `event_time` is stamped when the keeper's insert starts, so a row can
+/// commit after a pass that began later than its timestamp.
+const CANDLE_BACKFILL_OVERLAP: chrono::Duration = chrono::Duration::minutes(5);
+const DEFAULT_EXITS_LOOKAHEAD: u64 = 10;
+const POOL_MAX_SIZE: usize = 4;
+/// `getMultipleAccounts` accepts at most 100 keys per request.
+pub(crate) const MAX_MULTIPLE_ACCOUNTS: usize = 100;
+/// Metaplex `Key::MetadataV1` discriminant.
+const METADATA_V1_KEY: u8 = 4;
+/// Metaplex caps symbols at 10 bytes; anything longer is not a valid account.
+const MAX_METADATA_SYMBOL_LEN: usize = 10;
+
+struct IndexerConfig {
+    market_sync_interval: Duration,
+    snapshot_interval: Duration,
+    position_sync_interval: Duration,
+    exits_lookahead: u64,
+    candle_backfill_window: CandleBackfillWindow,
+}
+
+impl IndexerConfig {
+    fn from_env() -> Result<Self> {
+        let market_sync_interval_secs = parse_u64_env(
+            "ACCOUNT_INDEXER_MARKET_SYNC_INTERVAL_SECS",
+            DEFAULT_MARKET_SYNC_INTERVAL_SECS,
+        )?;
+        if market_sync_interval_secs == 0 {
+            return Err(anyhow!(
+                "ACCOUNT_INDEXER_MARKET_SYNC_INTERVAL_SECS must be greater than 0"
+            ));
+        }
+
+        let snapshot_interval_secs = parse_u64_env(
+            "ACCOUNT_INDEXER_SNAPSHOT_INTERVAL_SECS",
+            DEFAULT_SNAPSHOT_INTERVAL_SECS,
+        )?;
+        if snapshot_interval_secs == 0 {
+            return Err(anyhow!(
+                "ACCOUNT_INDEXER_SNAPSHOT_INTERVAL_SECS must be greater than 0"
+            ));
+        }
+
+        let position_sync_interval_secs = parse_u64_env(
+            "ACCOUNT_INDEXER_POSITION_SYNC_INTERVAL_SECS",
+            DEFAULT_POSITION_SYNC_INTERVAL_SECS,
+        )?;
+        if position_sync_interval_secs == 0 {
+            return Err(anyhow!(
+                "ACCOUNT_INDEXER_POSITION_SYNC_INTERVAL_SECS must be greater than 0"
+            ));
+        }
+
+        let exits_lookahead =
+            parse_u64_env("ACCOUNT_INDEXER_EXITS_LOOKAHEAD", DEFAULT_EXITS_LOOKAHEAD)?;
+
+        let candle_backfill_window = CandleBackfillWindow {
+            candle_retention: candles_1m_retention_from_env()?,
+            lookback: interval_env(
+                "ACCOUNT_INDEXER_CANDLE_BACKFILL_LOOKBACK",
+                Some(DEFAULT_CANDLE_BACKFILL_LOOKBACK),
+            )?,
+            ..CandleBackfillWindow::default()
+        };
+
+        Ok(Self {
+            market_sync_interval: Duration::from_secs(market_sync_interval_secs),
+            snapshot_interval: Duration::from_secs(snapshot_interval_secs),
+            position_sync_interval: Duration::from_secs(position_sync_interval_secs),
+            exits_lookahead,
+            candle_backfill_window,
+        })
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    dotenv::dotenv().ok();
+
+    let database_url = env::var("DATABASE_URL")
+        .ok()
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+        .ok_or_else(|| anyhow!("DATABASE_URL must be set (Tiger Cloud connection string)"))?;
+    let rpc_url = env::var("CLUSTER_RPC_URL").expect("CLUSTER_RPC_URL must be set");
+    let ws_url = env::var("CLUSTER_WS_URL").expect("CLUSTER_WS_URL must be set");
+    let config = IndexerConfig::from_env()?;
+
+    let pool = connect_pool(&database_url, POOL_MAX_SIZE)?;
+
+    // The indexer only reads accounts; the client still needs a payer, so give
+    // it a throwaway keypair that never signs anything.
+    let client = Client::new_with_options(
+        Cluster::Custom(rpc_url, ws_url.clone()),
+        Arc::new(Keypair::new()),
+        CommitmentConfig::confirmed(),
+    );
+    let program = client.program(twob_anchor::ID)?;
+    let rpc = program.rpc();
+    let resolver = AccountResolver::new(twob_anchor::ID);
+
+    let directory = MarketDirectory::default();
+
+    println!(
+        "Account indexer started market_sync_interval={}s snapshot_interval={}s position_sync_interval={}s",
+        config.market_sync_interval.as_secs(),
+        config.snapshot_interval.as_secs(),
+        config.position_sync_interval.as_secs()
+    );
+
+    tokio::spawn(run_position_subscriptions(
+        ws_url,
+        pool.clone(),
+        resolver.clone(),
+        directory.clone(),
+    ));
+
+    let mut market_sync_ticker = tokio::time::interval(config.market_sync_interval);
+    market_sync_ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    let mut snapshot_ticker = tokio::time::interval(config.snapshot_interval);
+    snapshot_ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    let mut position_sync_ticker = tokio::time::interval(config.position_sync_interval);
+    position_sync_ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    let mut candle_backfill_mark = None;
+
+    loop {
+        tokio::select! {
+            _ = market_sync_ticker.tick() => {
+                run_market_sync(
+                    &pool,
+                    &program,
+                    &rpc,
+                    &resolver,
+                    &config.candle_backfill_window,
+                    &mut candle_backfill_mark,
+                )
+                .await;
+            }
+            _ = snapshot_ticker.tick() => {
+                match snapshot_market_states(&pool, &program, &rpc, &resolver, config.exits_lookahead).await {
+                    Ok(snapshot) => println!(
+                        "Market state snapshot - markets={} exits={}",
+                        snapshot.markets, snapshot.exits
+                    ),
+                    Err(error) => eprintln!("Market state snapshot failed: {error:#}"),
+                }
+            }
+            _ = position_sync_ticker.tick() => {
+                match sync_positions(&pool, &program, &rpc, &resolver, &directory).await {
+                    Ok(sync) => println!(
+                        "Position sync - trade_open={} liquidity_open={} unmatched={} pruned={}",
+                        sync.trade_open, sync.liquidity_open, sync.unmatched, sync.pruned
+                    ),
+                    Err(error) => eprintln!("Position sync failed: {error:#}"),
+                }
+            }
+        }
+    }
+}
+
+async fn run_market_sync(
+    pool: &Pool,
+    program: &Program<Arc<Keypair>>,
+    rpc: &RpcClient,
+    resolver: &AccountResolver,
+    candle_backfill_window: &CandleBackfillWindow,
+    candle_backfill_mark: &mut Option<DateTime<Utc>>,
+) {
+    let markets = match program.accounts::<Market>(vec![]).await {
+        Ok(markets) => markets,
+        Err(error) => {
+            eprintln!("Failed to list market accounts: {error}");
+            return;
+        }
+    };
+
+    let changed = match sync_market_configs(pool, rpc, resolver, &markets).await {
+        Ok(changed) => {
+            println!(
+                "Market config sync - markets={} changed={}",
+                markets.len(),
+                changed.len()
+            );
+            Some(changed)
+        }
+        Err(error) => {
+            eprintln!("Market config sync failed: {error:#}");
+            None
+        }
+    };
+
+    // Markets whose events arrived before their config have no candles for
+    // that stretch; rebuild them now that decimals may be available. After a
+    // full first pass, only events since the previous pass can open new holes,
+    // except in markets whose config just changed (or may have, when the sync
+    // failed midway), which are checked over the whole lookback again.
+    let pass_start = Utc::now();
+    let mut windows = Vec::with_capacity(2);
+    match (*candle_backfill_mark, changed) {
+        (Some(mark), Some(changed)) => {
+            windows.push(CandleBackfillWindow {
+                since: Some(mark - CANDLE_BACKFILL_OVERLAP),
+                ..candle_backfill_window.clone()
+            });
+            if !changed.is_empty() {
+                windows.push(CandleBackfillWindow {
+                    market_ids: Some(changed),
+                    ..candle_backfill_window.clone()
+                });
+            }
+        }
+        _ => windows.push(candle_backfill_window.clone()),
+    }
+
+    let mut complete = true;
+    for window in windows {
+        match backfill_missing_candles(pool, &window).await {
+            Ok(backfills) => {
+                for backfill in backfills {
+                    println!(
+                        "Candle backfill - market_id={} gap_start={} gap_end={} candles_written={}",
+                        backfill.market_id,
+                        backfill.gap_start,
+                        backfill.gap_end,
+                        backfill.candles_written
+                    );
+                }
+            }
+            Err(error) => {
+                eprintln!("Candle backfill failed: {error:#}");
+                complete = false;
+            }
+        }
+    }
+    if complete {
+        *candle_backfill_mark = Some(pass_start);
+    }
+}
+
+struct MarketSnapshot {
+    markets: usize,
+    exits: usize,
+}
+
+/// Record the current `Market` and `Bookkeeping` state of every market, and
+/// replace its upcoming exits (see [`exits`]).
+///
+/// Market keys come from `getProgramAccounts`; the snapshot itself is read with
+/// `getMultipleAccounts` so each market and its bookkeeping account share one
+/// context slot.
+async fn snapshot_market_states(
+    pool: &Pool,
+    program: &Program<Arc<Keypair>>,
+    rpc: &RpcClient,
+    resolver: &AccountResolver,
+    exits_lookahead: u64,
+) -> Result<MarketSnapshot> {
+    let market_addresses: Vec<Pubkey> = program
+        .accounts::<Market>(vec![])
+        .await
+        .context("Failed to list market accounts")?
+        .into_iter()
+        .map(|(address, _)| address)
+        .collect();
+
+    let snapshot_time = Utc::now();
+    let mut written = 0;
+    let mut exits_written = 0;
+
+    // Each market needs two keys (market + bookkeeping).
+    for market_chunk in market_addresses.chunks(MAX_MULTIPLE_ACCOUNTS / 2) {
+        let bookkeeping_addresses: Vec<Pubkey> = market_chunk
+            .iter()
+            .map(|market| resolver.bookkeeping_pda(market).address())
+            .collect();
+        let keys: Vec<Pubkey> = market_chunk
+            .iter()
+            .chain(bookkeeping_addresses.iter())
+            .copied()
+            .collect();
+
+        let response = rpc
+            .get_multiple_accounts_with_commitment(&keys, CommitmentConfig::confirmed())
+            .await
+            .context("getMultipleAccounts RPC failed for market state")?;
+        let slot = response.context.slot;
+        let (market_accounts, bookkeeping_accounts) = response.value.split_at(market_chunk.len());
+        let mut markets = Vec::with_capacity(market_chunk.len());
+
+        for ((address, market_account), bookkeeping_account) in market_chunk
+            .iter()
+            .zip(market_accounts)
+            .zip(bookkeeping_accounts)
+        {
+            let Some(market_account) = market_account else {
+                continue;
+            };
+            let market = match Market::try_deserialize(&mut market_account.data.as_slice()) {
+                Ok(market) => market,
+                Err(error) => {
+                    eprintln!("Failed to decode market {address}: {error}");
+                    continue;
+                }
+            };
+            let bookkeeping = bookkeeping_account.as_ref().and_then(|account| {
+                Bookkeeping::try_deserialize(&mut account.data.as_slice())
+                    .map_err(|error| {
+                        eprintln!("Failed to decode bookkeeping for market {address}: {error}")
+                    })
+                    .ok()
+            });
+
+            let record = MarketStateSnapshotRecord {
+                market_id: market.id,
+                snapshot_time,
+                slot,
+                is_paused: market.is_paused != 0,
+                fee_bps: market.fee_bps,
+                unhealthy_liquidity_fee_bps: market.unhealthy_liquidity_fee_bps,
+                open_positions: market.open_positions,
+                accumulated_base_fees: market.accumulated_base_fees,
+                accumulated_quote_fees: market.accumulated_quote_fees,
+                base_flow: market.base_flow,
+                quote_flow: market.quote_flow,
+                end_slot_interval: market.end_slot_interval,
+                bookkeeping: bookkeeping.map(|bookkeeping| BookkeepingSnapshot {
+                    base_per_quote: bookkeeping.base_per_quote,
+                    quote_per_base: bookkeeping.quote_per_base,
+                    slots_without_trade: bookkeeping.slots_without_trade,
+                    last_update_slot: bookkeeping.last_update_slot,
+                }),
+            };
+
+            insert_market_state_snapshot(pool, &record)
+                .await
+                .with_context(|| format!("market_id={}", market.id))?;
+            written += 1;
+            markets.push((*address, market));
+        }
+
+        exits_written +=
+            snapshot_market_exits(pool, rpc, resolver, &markets, slot, exits_lookahead).await?;
+    }
+
+    Ok(MarketSnapshot {
+        markets: written,
+        exits: exits_written,
+    })
+}
+
+/// Upsert a `market_configs` row for every on-chain market.
+///
+/// Decimals come from the SPL mint accounts (Token and Token-2022); tickers
+/// come from Metaplex metadata when the mint has it. Returns the ids of the
+/// markets whose row changed.
+async fn sync_market_configs(
+    pool: &Pool,
+    rpc: &RpcClient,
+    resolver: &AccountResolver,
+    markets: &[(Pubkey, Market)],
+) -> Result<Vec<u64>> {
+    let mut mints: Vec<Pubkey> = markets
+        .iter()
+        .flat_map(|(_, market)| [market.base_mint, market.quote_mint])
+        .collect();
+    mints.sort();
+    mints.dedup();
+
+    let decimals = fetch_mint_decimals(rpc, &mints).await?;
+    let tickers = fetch_metadata_symbols(rpc, resolver, &mints).await?;
+
+    let mut changed = Vec::new();
+    for (_, market) in markets {
+        let (Some(base_decimals), Some(quote_decimals)) = (
+            decimals.get(&market.base_mint).copied(),
+            decimals.get(&market.quote_mint).copied(),
+        ) else {
+            eprintln!(
+                "Skipping market_id={}: mint account missing or not an SPL mint (base={} quote={})",
+                market.id, market.base_mint, market.quote_mint
+            );
+            continue;
+        };
+
+        let record = MarketConfigRecord {
+            market_id: market.id,
+            base_mint: market.base_mint.to_string(),
+            quote_mint: market.quote_mint.to_string(),
+            base_decimals,
+            quote_decimals,
+            base_ticker: tickers.get(&market.base_mint).cloned(),
+            quote_ticker: tickers.get(&market.quote_mint).cloned(),
+        };
+
+        if upsert_market_config(pool, &record)
+            .await
+            .with_context(|| format!("market_id={}", market.id))?
+        {
+            println!(
+                "Market config updated - market_id={} base={} ({}) quote={} ({})",
+                record.market_id,
+                record.base_mint,
+                record.base_decimals,
+                record.quote_mint,
+                record.quote_decimals
+            );
+            changed.push(market.id);
+        }
+    }
+
+    Ok(changed)
+}
+
+async fn fetch_mint_decimals(rpc: &RpcClient, mints: &[Pubkey]) -> Result<HashMap<Pubkey, u8>> {
+    let mut decimals = HashMap::with_capacity(mints.len());
+
+    for chunk in mints.chunks(MAX_MULTIPLE_ACCOUNTS) {
+        let accounts = rpc
+            .get_multiple_accounts(chunk)
+            .await
+            .context("getMultipleAccounts RPC failed for mints")?;
+
+        for (mint, account) in chunk.iter().zip(accounts) {
+            let Some(account) = account else {
+                continue;
+            };
+            match Mint::try_deserialize(&mut account.data.as_slice()) {
+                Ok(parsed) => {
+                    decimals.insert(*mint, parsed.decimals);
+                }
+                Err(error) => eprintln!("Failed to decode mint {mint}: {error}"),
+            }
+        }
+    }
+
+    Ok(decimals)
+}
+
+async fn fetch_metadata_symbols(
+    rpc: &RpcClient,
+    resolver: &AccountResolver,
+    mints: &[Pubkey],
+) -> Result<HashMap<Pubkey, String>> {
+    let metadata_addresses: Vec<Pubkey> = mints
+        .iter()
+        .map(|mint| resolver.token_metadata_pda(mint).address())
+        .collect();
+    let mut symbols = HashMap::new();
+
+    for (mint_chunk, address_chunk) in mints
+        .chunks(MAX_MULTIPLE_ACCOUNTS)
+        .zip(metadata_addresses.chunks(MAX_MULTIPLE_ACCOUNTS))
+    {
+        let accounts = rpc
+            .get_multiple_accounts(address_chunk)
+            .await
+            .context("getMultipleAccounts RPC failed for token metadata")?;
+
+        for (mint, account) in mint_chunk.iter().zip(accounts) {
+            if let Some(symbol) = account.and_then(|account| parse_metadata_symbol(&account.data)) {
+                symbols.insert(*mint, symbol);
+            }
+        }
+    }
+
+    Ok(symbols)
+}
+
+/// Extract the symbol from a Metaplex `Metadata` account.
+///
+/// Layout: `key: u8, update_authority: Pubkey, mint: Pubkey, name: String,
+/// symbol: String, ...` with Borsh strings (`u32` length prefix). Metaplex
+/// pads strings with NULs, so those are trimmed.
+fn parse_metadata_symbol(data: &[u8]) -> Option<String> {
+    if data.first().copied() != Some(METADATA_V1_KEY) {
+        return None;
+    }
+
+    let mut offset = 1 + 32 + 32;
+    let (_, next) = read_borsh_str(data, offset)?;
+    offset = next;
+    let (symbol, _) = read_borsh_str(data, offset)?;
+
+    let symbol = symbol.trim_matches(char::from(0)).trim();
+    if symbol.is_empty() || symbol.len() > MAX_METADATA_SYMBOL_LEN {
+        return None;
+    }
+    Some(symbol.to_string())
+}
+
+fn read_borsh_str(data: &[u8], offset: usize) -> Option<(&str, usize)> {
+    let len_bytes: [u8; 4] = data.get(offset..offset + 4)?.try_into().ok()?;
+    let len = u32::from_le_bytes(len_bytes) as usize;
+    let start = offset + 4;
+    let bytes = data.get(start..start.checked_add(len)?)?;
+    Some((std::str::from_utf8(bytes).ok()?, start + len))
+}
+
+fn parse_u64_env(key: &str, default_value: u64) -> Result<u64> {
+    match env::var(key) {
+        Ok(raw) => raw
+            .parse::<u64>()
+            .with_context(|| format!("{key} must be a valid positive integer")),
+        Err(env::VarError::NotPresent) => Ok(default_value),
+        Err(error) => Err(anyhow!("Failed to read {key}: {error}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata_account(name: &str, symbol: &str) -> Vec<u8> {
+        let mut data = vec![METADATA_V1_KEY];
+        data.extend_from_slice(&[1; 32]);
+        data.extend_from_slice(&[2; 32]);
+        for value in [name, symbol, "https://example.com"] {
+            data.extend_from_slice(&(value.len() as u32).to_le_bytes());
+            data.extend_from_slice(value.as_bytes());
+        }
+        data
+    }
+
+    #[test]
+    fn parses_nul_padded_metadata_symbol() {
+        let data = metadata_account("Wrapped SOL\0\0\0\0\0", "SOL\0\0\0\0\0\0\0");
+        assert_eq!(parse_metadata_symbol(&data).as_deref(), Some("SOL"));
+    }
+
+    #[test]
+    fn rejects_truncated_or_foreign_metadata() {
+        let data = metadata_account("USD Coin", "USDC");
+        assert_eq!(parse_metadata_symbol(&data[..70]), None);
+
+        let mut wrong_key = data.clone();
+        wrong_key[0] = 0;
+        assert_eq!(parse_metadata_symbol(&wrong_key), None);
+
+        let blank = metadata_account("Unnamed", "\0\0\0\0");
+        assert_eq!(parse_metadata_symbol(&blank), None);
+    }
+}
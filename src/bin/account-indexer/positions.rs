@@ -0,0 +1,432 @@
+//! Open positions: mirrors live `TradePosition` and `LiquidityPosition`
+//! accounts into the `trade_positions` and `liquidity_positions` tables.
+//!
+//! A periodic `getProgramAccounts` scan is the source of truth: it upserts every
+//! open position and prunes rows whose account is gone. Between scans program
+//! subscriptions pick up new positions and, for liquidity positions, balance and
+//! flow changes within a slot or two. Closing an account hands it to the system
+//! program, which the subscriptions do not report, so closes land with the next
+//! scan; read-api hides positions whose close-position event is already
+//! indexed in the meantime.
+
+use anchor_client::{
+    Program,
+    solana_sdk::{commitment_config::CommitmentConfig, signature::Keypair},
+};
+use anchor_lang::{AccountDeserialize, Discriminator, prelude::*};
+use anyhow::{Context, Result, anyhow};
+use deadpool_postgres::Pool;
+use futures_util::StreamExt;
+use solana_account_decoder_client_types::UiAccountEncoding;
+use solana_pubsub_client::nonblocking::pubsub_client::PubsubClient;
+use solana_rpc_client::nonblocking::rpc_client::RpcClient;
+use solana_rpc_client_types::{
+    config::{RpcAccountInfoConfig, RpcProgramAccountsConfig},
+    filter::{Memcmp, RpcFilterType},
+    response::{Response as RpcResponse, RpcKeyedAccount},
+};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, RwLock},
+    time::Duration,
+};
+use twob_keepers::{
+    AccountResolver,
+    database::{
+        LiquidityPositionRecord, TradePositionRecord, prune_liquidity_positions,
+        prune_trade_positions, upsert_liquidity_position, upsert_trade_position,
+    },
+};
+
+use crate::twob_anchor::accounts::{LiquidityPosition, Market, TradePosition};
+
+/// Which market each position belongs to.
+///
+/// Neither position account stores its market; the PDA seeds do
+/// (`["trade_position", market, authority, id]` and
+/// `["liquidity_position", market, authority]`), so the market is found by
+/// re-deriving the address against every known market. Matches are cached
+/// since a position never moves.
+#[derive(Clone, Default)]
+pub(crate) struct MarketDirectory {
+    markets: Arc<RwLock<Vec<(Pubkey, u64)>>>,
+    positions: Arc<Mutex<HashMap<Pubkey, u64>>>,
+}
+
+impl MarketDirectory {
+    fn replace_markets(&self, markets: &[(Pubkey, Market)]) {
+        let markets = markets
+            .iter()
+            .map(|(address, market)| (*address, market.id))
+            .collect();
+        *self.markets.write().expect("market directory poisoned") = markets;
+    }
+
+    /// Market of the position at `address`; `derive` maps a market to the
+    /// position's PDA in that market.
+    fn market_id(&self, address: &Pubkey, derive: impl Fn(&Pubkey) -> Pubkey) -> Option<u64> {
+        if let Some(market_id) = self
+            .positions
+            .lock()
+            .expect("position cache poisoned")
+            .get(address)
+        {
+            return Some(*market_id);
+        }
+
+        let market_id = self
+            .markets
+            .read()
+            .expect("market directory poisoned")
+            .iter()
+            .find(|(market, _)| derive(market) == *address)
+            .map(|(_, market_id)| *market_id)?;
+        self.positions
+            .lock()
+            .expect("position cache poisoned")
+            .insert(*address, market_id);
+        Some(market_id)
+    }
+
+    fn trade_market_id(
+        &self,
+        resolver: &AccountResolver,
+        address: &Pubkey,
+        position: &TradePosition,
+    ) -> Option<u64> {
+        self.market_id(address, |market| {
+            resolver
+                .trade_position_pda(market, &position.authority, position.id)
+                .address()
+        })
+    }
+
+    fn liquidity_market_id(
+        &self,
+        resolver: &AccountResolver,
+        address: &Pubkey,
+        position: &LiquidityPosition,
+    ) -> Option<u64> {
+        self.market_id(address, |market| {
+            resolver
+                .liquidity_position_pda(market, &position.authority)
+                .address()
+        })
+    }
+
+    /// Drop cached positions that are no longer open. `open` must be sorted.
+    fn retain_open(&self, open: &[Pubkey]) {
+        self.positions
+            .lock()
+            .expect("position cache poisoned")
+            .retain(|address, _| open.binary_search(address).is_ok());
+    }
+}
+
+pub(crate) struct PositionSync {
+    pub(crate) trade_open: usize,
+    pub(crate) liquidity_open: usize,
+    pub(crate) unmatched: usize,
+    pub(crate) pruned: u64,
+}
+
+/// Upsert every open trade and liquidity position and delete rows for closed
+/// ones.
+///
+/// The slot is read before the scan, so any account the scan misses was closed
+/// by then; rows a subscription wrote at a later slot are left alone.
+pub(crate) async fn sync_positions(
+    pool: &Pool,
+    program: &Program<Arc<Keypair>>,
+    rpc: &RpcClient,
+    resolver: &AccountResolver,
+    directory: &MarketDirectory,
+) -> Result<PositionSync> {
+    let slot = rpc
+        .get_slot_with_commitment(CommitmentConfig::confirmed())
+        .await
+        .context("getSlot RPC failed")?;
+    let markets = program
+        .accounts::<Market>(vec![])
+        .await
+        .context("Failed to list market accounts")?;
+    directory.replace_markets(&markets);
+    let trade_positions = program
+        .accounts::<TradePosition>(vec![])
+        .await
+        .context("Failed to list trade position accounts")?;
+    let liquidity_positions = program
+        .accounts::<LiquidityPosition>(vec![])
+        .await
+        .context("Failed to list liquidity position accounts")?;
+
+    let mut unmatched = 0;
+
+    let mut trade_open = Vec::with_capacity(trade_positions.len());
+    for (address, position) in &trade_positions {
+        let Some(market_id) = directory.trade_market_id(resolver, address, position) else {
+            eprintln!("Trade position {address} does not belong to any known market");
+            unmatched += 1;
+            continue;
+        };
+        upsert_trade_position(
+            pool,
+            &trade_position_record(address, position, market_id, slot),
+        )
+        .await
+        .with_context(|| format!("trade position {address}"))?;
+        trade_open.push(address.to_string());
+    }
+
+    let mut liquidity_open = Vec::with_capacity(liquidity_positions.len());
+    for (address, position) in &liquidity_positions {
+        let Some(market_id) = directory.liquidity_market_id(resolver, address, position) else {
+            eprintln!("Liquidity position {address} does not belong to any known market");
+            unmatched += 1;
+            continue;
+        };
+        upsert_liquidity_position(
+            pool,
+            &liquidity_position_record(address, position, market_id, slot),
+        )
+        .await
+        .with_context(|| format!("liquidity position {address}"))?;
+        liquidity_open.push(address.to_string());
+    }
+
+    let pruned = prune_trade_positions(pool, &trade_open, slot).await?
+        + prune_liquidity_positions(pool, &liquidity_open, slot).await?;
+    let mut open: Vec<Pubkey> = trade_positions
+        .iter()
+        .map(|(address, _)| *address)
+        .chain(liquidity_positions.iter().map(|(address, _)| *address))
+        .collect();
+    open.sort();
+    directory.retain_open(&open);
+
+    Ok(PositionSync {
+        trade_open: trade_open.len(),
+        liquidity_open: liquidity_open.len(),
+        unmatched,
+        pruned,
+    })
+}
+
+/// Keep program subscriptions for position accounts open, reconnecting with
+/// backoff whenever they drop.
+pub(crate) async fn run_position_subscriptions(
+    ws_url: String,
+    pool: Pool,
+    resolver: AccountResolver,
+    directory: MarketDirectory,
+) {
+    let mut backoff = Duration::from_secs(1);
+
+    loop {
+        match subscribe_positions(&ws_url, &pool, &resolver, &directory).await {
+            Ok(()) => eprintln!("Position subscription ended, reconnecting"),
+            Err(error) => eprintln!("Position subscription failed: {error:#}"),
+        }
+
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(Duration::from_secs(30));
+    }
+}
+
+fn position_subscription_config(discriminator: &[u8]) -> RpcProgramAccountsConfig {
+    RpcProgramAccountsConfig {
+        filters: Some(vec![RpcFilterType::Memcmp(Memcmp::new_raw_bytes(
+            0,
+            discriminator.to_vec(),
+        ))]),
+        account_config: RpcAccountInfoConfig {
+            encoding: Some(UiAccountEncoding::Base64),
+            commitment: Some(CommitmentConfig::confirmed()),
+            ..RpcAccountInfoConfig::default()
+        },
+        ..RpcProgramAccountsConfig::default()
+    }
+}
+
+async fn subscribe_positions(
+    ws_url: &str,
+    pool: &Pool,
+    resolver: &AccountResolver,
+    directory: &MarketDirectory,
+) -> Result<()> {
+    let pubsub_client = PubsubClient::new(ws_url)
+        .await
+        .context("Failed to create pubsub websocket client")?;
+
+    let (mut trade_notifications, trade_unsubscribe) = pubsub_client
+        .program_subscribe(
+            resolver.program_id(),
+            Some(position_subscription_config(TradePosition::DISCRIMINATOR)),
+        )
+        .await
+        .context("Failed to subscribe to trade position accounts")?;
+    let (mut liquidity_notifications, liquidity_unsubscribe) = pubsub_client
+        .program_subscribe(
+            resolver.program_id(),
+            Some(position_subscription_config(
+                LiquidityPosition::DISCRIMINATOR,
+            )),
+        )
+        .await
+        .context("Failed to subscribe to liquidity position accounts")?;
+
+    println!("Position subscriptions established");
+
+    loop {
+        tokio::select! {
+            maybe_notification = trade_notifications.next() => {
+                let Some(notification) = maybe_notification else {
+                    eprintln!("Trade position notification stream closed by RPC node");
+                    break;
+                };
+                if let Err(error) =
+                    handle_trade_position_notification(pool, resolver, directory, notification).await
+                {
+                    eprintln!("Failed to handle trade position notification: {error:#}");
+                }
+            }
+            maybe_notification = liquidity_notifications.next() => {
+                let Some(notification) = maybe_notification else {
+                    eprintln!("Liquidity position notification stream closed by RPC node");
+                    break;
+                };
+                if let Err(error) =
+                    handle_liquidity_position_notification(pool, resolver, directory, notification).await
+                {
+                    eprintln!("Failed to handle liquidity position notification: {error:#}");
+                }
+            }
+        }
+    }
+
+    trade_unsubscribe().await;
+    liquidity_unsubscribe().await;
+    Ok(())
+}
+
+/// Address, slot and data of a position account reported by a program
+/// subscription.
+fn position_notification(
+    notification: RpcResponse<RpcKeyedAccount>,
+) -> Result<(Pubkey, u64, Vec<u8>)> {
+    let slot = notification.context.slot;
+    let address: Pubkey = notification
+        .value
+        .pubkey
+        .parse()
+        .context("Invalid account address in notification")?;
+    let data = notification
+        .value
+        .account
+        .data
+        .decode()
+        .ok_or_else(|| anyhow!("Notification data for {address} is not binary"))?;
+    Ok((address, slot, data))
+}
+
+async fn handle_trade_position_notification(
+    pool: &Pool,
+    resolver: &AccountResolver,
+    directory: &MarketDirectory,
+    notification: RpcResponse<RpcKeyedAccount>,
+) -> Result<()> {
+    let (address, slot, data) = position_notification(notification)?;
+
+    let position = TradePosition::try_deserialize(&mut data.as_slice())
+        .with_context(|| format!("Failed to decode trade position {address}"))?;
+    // Markets created since the last scan are unknown until the next one.
+    let Some(market_id) = directory.trade_market_id(resolver, &address, &position) else {
+        eprintln!("Trade position {address} does not belong to any known market yet");
+        return Ok(());
+    };
+
+    if upsert_trade_position(
+        pool,
+        &trade_position_record(&address, &position, market_id, slot),
+    )
+    .await?
+    {
+        println!(
+            "Trade position updated - address={address} market_id={market_id} authority={} end_slot={}",
+            position.authority, position.end_slot
+        );
+    }
+    Ok(())
+}
+
+async fn handle_liquidity_position_notification(
+    pool: &Pool,
+    resolver: &AccountResolver,
+    directory: &MarketDirectory,
+    notification: RpcResponse<RpcKeyedAccount>,
+) -> Result<()> {
+    let (address, slot, data) = position_notification(notification)?;
+
+    let position = LiquidityPosition::try_deserialize(&mut data.as_slice())
+        .with_context(|| format!("Failed to decode liquidity position {address}"))?;
+    let Some(market_id) = directory.liquidity_market_id(resolver, &address, &position) else {
+        eprintln!("Liquidity position {address} does not belong to any known market yet");
+        return Ok(());
+    };
+
+    if upsert_liquidity_position(
+        pool,
+        &liquidity_position_record(&address, &position, market_id, slot),
+    )
+    .await?
+    {
+        println!(
+            "Liquidity position updated - address={address} market_id={market_id} authority={} last_update_slot={}",
+            position.authority, position.last_update_slot
+        );
+    }
+    Ok(())
+}
+
+fn trade_position_record(
+    address: &Pubkey,
+    position: &TradePosition,
+    market_id: u64,
+    observed_slot: u64,
+) -> TradePositionRecord {
+    TradePositionRecord {
+        address: address.to_string(),
+        market_id,
+        authority: position.authority.to_string(),
+        position_id: position.id,
+        is_buy: position.is_buy != 0,
+        amount: position.amount,
+        start_slot: position.start_slot,
+        end_slot: position.end_slot,
+        observed_slot,
+    }
+}
+
+fn liquidity_position_record(
+    address: &Pubkey,
+    position: &LiquidityPosition,
+    market_id: u64,
+    observed_slot: u64,
+) -> LiquidityPositionRecord {
+    LiquidityPositionRecord {
+        address: address.to_string(),
+        market_id,
+        authority: position.authority.to_string(),
+        base_balance: position.base_balance,
+        quote_balance: position.quote_balance,
+        base_per_quote_snapshot: position.base_per_quote_snapshot,
+        quote_per_base_snapshot: position.quote_per_base_snapshot,
+        slots_without_trade_snapshot: position.slots_without_trade_snapshot,
+        base_flow: position.base_flow_u64,
+        quote_flow: position.quote_flow_u64,
+        base_debt: position.base_debt,
+        quote_debt: position.quote_debt,
+        last_update_slot: position.last_update_slot,
+        observed_slot,
+    }
+}
@@ -0,0 +1,121 @@
+use anyhow::{Context, Result, anyhow};
+use std::env;
+use twob_keepers::{
+    StorageBackend, StoragePolicies,
+    database::{CandleBackfillWindow, backfill_fee_rollup, backfill_missing_candles, connect_pool},
+    policies::{candles_1m_retention_from_env, storage_stats},
+};
+
+const USAGE: &str = "\
+Usage: db-admin <command>
+
+Commands:
+  migrate          Apply the schema for STORAGE_BACKEND (idempotent)
+  apply-policies   Reconcile compression, retention and rollup policies from env
+  status           Print chunk and compression stats for every hypertable
+  backfill-candles Rebuild candles for markets whose events predate their config
+  backfill-fees    Rebuild daily fee rollups from the raw close-position events
+
+STORAGE_BACKEND=timescale (default) uses docs/timescale-schema.sql;
+STORAGE_BACKEND=postgres uses docs/postgres-schema.sql and has no policies.";
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    dotenv::dotenv().ok();
+
+    let command = env::args().nth(1).unwrap_or_default();
+    if matches!(command.as_str(), "" | "-h" | "--help" | "help") {
+        println!("{USAGE}");
+        return Ok(());
+    }
+
+    let database_url = env::var("DATABASE_URL")
+        .ok()
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+        .ok_or_else(|| anyhow!("DATABASE_URL must be set (Tiger Cloud connection string)"))?;
+
+    let backend = StorageBackend::from_env()?;
+    if backend == StorageBackend::Postgres
+        && matches!(command.as_str(), "apply-policies" | "status")
+    {
+        return Err(anyhow!(
+            "'{command}' needs TimescaleDB; STORAGE_BACKEND=postgres has no hypertables or policies"
+        ));
+    }
+
+    // One connection for the admin client below, one for library helpers that
+    // take the pool.
+    let pool = connect_pool(&database_url, 2)?;
+    let client = pool
+        .get()
+        .await
+        .context("Failed to connect to Tiger Cloud")?;
+
+    match command.as_str() {
+        "migrate" => {
+            client
+                .batch_execute(backend.schema_sql())
+                .await
+                .with_context(|| format!("Failed to apply {} schema", backend.as_str()))?;
+            println!("Schema applied (backend={})", backend.as_str());
+        }
+        "apply-policies" => {
+            let policies = StoragePolicies::from_env()?;
+            for action in policies.apply(&client).await? {
+                println!("{action}");
+            }
+        }
+        "status" => {
+            for stats in storage_stats(&client).await? {
+                println!(
+                    "{}.{} chunks={} compressed={} total_bytes={} before_compression={} after_compression={} compress_after={} retain_for={}",
+                    stats.schema,
+                    stats.table,
+                    stats.total_chunks,
+                    stats.compressed_chunks,
+                    optional_as_string(stats.total_bytes),
+                    optional_as_string(stats.before_compression_bytes),
+                    optional_as_string(stats.after_compression_bytes),
+                    stats.compress_after.as_deref().unwrap_or("off"),
+                    stats.retain_for.as_deref().unwrap_or("off"),
+                );
+            }
+        }
+        "backfill-candles" => {
+            // Whole history, except what candle retention drops anyway.
+            let window = CandleBackfillWindow {
+                candle_retention: candles_1m_retention_from_env()?,
+                ..CandleBackfillWindow::default()
+            };
+            let backfills = backfill_missing_candles(&pool, &window).await?;
+            if backfills.is_empty() {
+                println!("No markets are missing candles");
+            }
+            for backfill in backfills {
+                println!(
+                    "market_id={} gap_start={} gap_end={} candles_written={}",
+                    backfill.market_id,
+                    backfill.gap_start,
+                    backfill.gap_end,
+                    backfill.candles_written
+                );
+            }
+        }
+        "backfill-fees" => {
+            let days_written = backfill_fee_rollup(&pool).await?;
+            println!("days_written={days_written}");
+        }
+        other => {
+            return Err(anyhow!("Unknown command '{other}'\n\n{USAGE}"));
+        }
+    }
+
+    Ok(())
+}
+
+fn optional_as_string(value: Option<i64>) -> String {
+    value
+        .map(|inner| inner.to_string())
+        .unwrap_or_else(|| "n/a".to_string())
+}
@@ -0,0 +1,594 @@
+//! API keys, rate limits and usage counters for read-api.
+//!
+//! Everything here is off by default, so an internal deployment keeps
+//! behaving as an open API. Configure it before exposing read-api publicly:
+//!
+//! - `READ_API_KEYS` lists `name:key` pairs. A request sends its key in
+//!   `X-API-Key`, `Authorization: Bearer`, or `api_key=` in the query string for
+//!   `EventSource` and WebSocket clients, which cannot set headers. An unknown
+//!   key is always rejected; a missing one only with `READ_API_REQUIRE_KEY`.
+//! - Requests with a key draw from that key's token bucket, anonymous requests
+//!   from a bucket per client IP. Each request costs tokens by endpoint (see
+//!   [`request_cost`]), so one `/history` call weighs as much as many price
+//!   lookups. Opening a price stream or `/v1/ws` costs as much as `/history`,
+//!   since a resumed stream replays up to a page of updates.
+//! - `READ_API_MAX_STREAMS_PER_CALLER` caps the price streams and WebSockets a
+//!   key or IP holds open at once; tokens only pay for opening them.
+//! - Usage per key is counted in memory and reported to the key's holder by
+//!   `/v1/usage`. Counters reset when the process restarts.
+//!
+//! `/healthz`, `/readyz` and `/openapi.json` are exempt so load balancers and
+//! tooling never need a key.
+
+use anyhow::{Context, Result, anyhow};
+use axum::{
+    Json,
+    extract::{ConnectInfo, Query, Request, State},
+    http::{HeaderMap, HeaderValue, StatusCode, Uri, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    env,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tower_http::cors::{AllowOrigin, CorsLayer};
+use twob_keepers::api::{ErrorResponse, UsageResponse};
+
+use crate::{ApiError, AppState, parse_bool_env, parse_u64_env};
+
+const API_KEY_HEADER: &str = "x-api-key";
+const EXEMPT_PATHS: [&str; 3] = ["/healthz", "/readyz", "/openapi.json"];
+/// Above this many per-IP buckets, full (idle) ones are dropped.
+const MAX_IDLE_IP_BUCKETS: usize = 10_000;
+
+/// Token cost of the most expensive endpoint; a bucket must hold at least this
+/// many tokens or those requests could never pass.
+const MAX_REQUEST_COST: u64 = 20;
+
+/// Requests that hold their connection open: SSE price streams and `/v1/ws`.
+fn is_stream(path: &str) -> bool {
+    path.ends_with("/stream") || path == "/v1/ws"
+}
+
+/// Tokens a request to `path` takes from its bucket. Large row ranges and
+/// streams, which may replay that many rows, cost the most, paged lists and
+/// aggregates less, point lookups one.
+pub(crate) fn request_cost(path: &str) -> u64 {
+    const PAGED: [&str; 10] = [
+        "/updates",
+        "/candles",
+        "/state",
+        "/closed-positions",
+        "/closed-position-mini-chart",
+        "/execution",
+        "/execution-quality",
+        "/summary",
+        "/historical_trades",
+        "/fees",
+    ];
+    if path.ends_with("/history") || is_stream(path) {
+        MAX_REQUEST_COST
+    } else if PAGED.iter().any(|suffix| path.ends_with(suffix)) {
+        5
+    } else {
+        1
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct RateLimit {
+    per_second: f64,
+    burst: f64,
+}
+
+impl RateLimit {
+    /// `None` when `rate_key` is unset or zero.
+    fn from_env(rate_key: &str, burst_key: &str) -> Result<Option<Self>> {
+        let per_second = parse_u64_env(rate_key, 0)?;
+        if per_second == 0 {
+            return Ok(None);
+        }
+        // A default burst of ten seconds' worth of tokens.
+        let burst = parse_u64_env(
+            burst_key,
+            per_second.saturating_mul(10).max(MAX_REQUEST_COST),
+        )?;
+        if burst < MAX_REQUEST_COST {
+            return Err(anyhow!(
+                "{burst_key} must be at least {MAX_REQUEST_COST}, the cost of a /history request"
+            ));
+        }
+        Ok(Some(Self {
+            per_second: per_second as f64,
+            burst: burst as f64,
+        }))
+    }
+}
+
+pub(crate) struct AccessConfig {
+    /// Key to key name; names are what usage is reported under.
+    keys: HashMap<String, String>,
+    require_key: bool,
+    key_limit: Option<RateLimit>,
+    ip_limit: Option<RateLimit>,
+    /// Open streams allowed per key or IP; `0` means no limit.
+    max_streams_per_caller: usize,
+    /// Take the client IP from the last `X-Forwarded-For` entry, which the
+    /// load balancer in front of read-api appends.
+    trust_forwarded_for: bool,
+    /// `None` allows any origin.
+    cors_origins: Option<Vec<HeaderValue>>,
+}
+
+impl AccessConfig {
+    pub(crate) fn from_env() -> Result<Self> {
+        let keys = parse_api_keys(&env::var("READ_API_KEYS").unwrap_or_default())?;
+        let require_key = parse_bool_env("READ_API_REQUIRE_KEY", false)?;
+        if require_key && keys.is_empty() {
+            return Err(anyhow!(
+                "READ_API_REQUIRE_KEY needs at least one READ_API_KEYS entry"
+            ));
+        }
+        let cors_origins = env::var("READ_API_CORS_ORIGINS")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|origin| !origin.is_empty())
+            .map(|origin| {
+                HeaderValue::from_str(origin)
+                    .with_context(|| format!("Invalid READ_API_CORS_ORIGINS entry '{origin}'"))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            keys,
+            require_key,
+            key_limit: RateLimit::from_env("READ_API_KEY_RATE_PER_SEC", "READ_API_KEY_BURST")?,
+            ip_limit: RateLimit::from_env("READ_API_IP_RATE_PER_SEC", "READ_API_IP_BURST")?,
+            max_streams_per_caller: parse_u64_env("READ_API_MAX_STREAMS_PER_CALLER", 0)? as usize,
+            trust_forwarded_for: parse_bool_env("READ_API_TRUST_FORWARDED_FOR", false)?,
+            cors_origins: (!cors_origins.is_empty()).then_some(cors_origins),
+        })
+    }
+
+    pub(crate) fn cors_layer(&self) -> CorsLayer {
+        match &self.cors_origins {
+            None => CorsLayer::permissive(),
+            Some(origins) => {
+                CorsLayer::permissive().allow_origin(AllowOrigin::list(origins.clone()))
+            }
+        }
+    }
+}
+
+/// `name:key` pairs separated by commas.
+fn parse_api_keys(raw: &str) -> Result<HashMap<String, String>> {
+    let mut keys = HashMap::new();
+    for entry in raw
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+    {
+        let (name, key) = entry
+            .split_once(':')
+            .map(|(name, key)| (name.trim(), key.trim()))
+            .filter(|(name, key)| !name.is_empty() && !key.is_empty())
+            .ok_or_else(|| anyhow!("READ_API_KEYS entries must be name:key"))?;
+        if keys.insert(key.to_string(), name.to_string()).is_some() {
+            return Err(anyhow!("READ_API_KEYS has a duplicate key for '{name}'"));
+        }
+    }
+    Ok(keys)
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum Caller {
+    Key(String),
+    Ip(IpAddr),
+}
+
+#[derive(Clone, Copy, Debug)]
+struct TokenBucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl TokenBucket {
+    fn full(limit: RateLimit, now: Instant) -> Self {
+        Self {
+            tokens: limit.burst,
+            updated_at: now,
+        }
+    }
+
+    fn refill(&mut self, limit: RateLimit, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * limit.per_second).min(limit.burst);
+        self.updated_at = now;
+    }
+
+    /// Take `cost` tokens, or return how long until they are available.
+    fn take(&mut self, limit: RateLimit, cost: f64, now: Instant) -> Result<(), Duration> {
+        self.refill(limit, now);
+        if self.tokens >= cost {
+            self.tokens -= cost;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (cost - self.tokens) / limit.per_second,
+            ))
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct KeyUsage {
+    requests: u64,
+    cost: u64,
+    rate_limited: u64,
+    last_request_at: Option<String>,
+}
+
+pub(crate) struct AccessControl {
+    config: AccessConfig,
+    started_at: DateTime<Utc>,
+    buckets: Mutex<HashMap<Caller, TokenBucket>>,
+    usage: Mutex<HashMap<String, KeyUsage>>,
+    /// Open streams per caller; only tracked with a stream limit.
+    streams: Mutex<HashMap<Caller, usize>>,
+}
+
+/// One open stream of a caller, released when the last clone drops. Stream
+/// handlers keep it alive for as long as they serve the connection.
+#[derive(Clone)]
+pub(crate) struct StreamPermit {
+    _slot: Arc<StreamSlot>,
+}
+
+struct StreamSlot {
+    access: Arc<AccessControl>,
+    caller: Caller,
+}
+
+impl Drop for StreamSlot {
+    fn drop(&mut self) {
+        let mut streams = self.access.streams.lock().expect("stream counts poisoned");
+        if let Some(open) = streams.get_mut(&self.caller) {
+            *open -= 1;
+            if *open == 0 {
+                streams.remove(&self.caller);
+            }
+        }
+    }
+}
+
+impl AccessControl {
+    pub(crate) fn new(config: AccessConfig) -> Self {
+        Self {
+            config,
+            started_at: Utc::now(),
+            buckets: Mutex::new(HashMap::new()),
+            usage: Mutex::new(HashMap::new()),
+            streams: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Count a new stream of `caller`; `Err` when it already has the most
+    /// allowed, `Ok(None)` without a limit.
+    fn open_stream(self: &Arc<Self>, caller: &Caller) -> Result<Option<StreamPermit>, ()> {
+        let max = self.config.max_streams_per_caller;
+        if max == 0 {
+            return Ok(None);
+        }
+        let mut streams = self.streams.lock().expect("stream counts poisoned");
+        let open = streams.entry(caller.clone()).or_default();
+        if *open >= max {
+            return Err(());
+        }
+        *open += 1;
+        Ok(Some(StreamPermit {
+            _slot: Arc::new(StreamSlot {
+                access: Arc::clone(self),
+                caller: caller.clone(),
+            }),
+        }))
+    }
+
+    /// Charge `caller` for a request, or return how long it has to wait.
+    fn admit(&self, caller: &Caller, cost: u64, now: Instant) -> Result<(), Duration> {
+        let limit = match caller {
+            Caller::Key(_) => self.config.key_limit,
+            Caller::Ip(_) => self.config.ip_limit,
+        };
+        let Some(limit) = limit else {
+            return Ok(());
+        };
+
+        let mut buckets = self.buckets.lock().expect("rate limit buckets poisoned");
+        if let (true, Some(ip_limit)) = (buckets.len() > MAX_IDLE_IP_BUCKETS, self.config.ip_limit)
+        {
+            buckets.retain(|caller, bucket| match caller {
+                Caller::Key(_) => true,
+                Caller::Ip(_) => {
+                    bucket.refill(ip_limit, now);
+                    bucket.tokens < ip_limit.burst
+                }
+            });
+        }
+        buckets
+            .entry(caller.clone())
+            .or_insert_with(|| TokenBucket::full(limit, now))
+            .take(limit, cost as f64, now)
+    }
+
+    fn record(&self, key_name: &str, cost: u64, admitted: bool) {
+        let mut usage = self.usage.lock().expect("usage counters poisoned");
+        let usage = usage.entry(key_name.to_string()).or_default();
+        if admitted {
+            usage.requests += 1;
+            usage.cost += cost;
+        } else {
+            usage.rate_limited += 1;
+        }
+        usage.last_request_at = Some(Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true));
+    }
+
+    fn client_ip(&self, headers: &HeaderMap, peer: SocketAddr) -> IpAddr {
+        if self.config.trust_forwarded_for {
+            let forwarded = headers
+                .get_all("x-forwarded-for")
+                .iter()
+                .next_back()
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.rsplit(',').next())
+                .and_then(|entry| entry.trim().parse::<IpAddr>().ok());
+            if let Some(ip) = forwarded {
+                return ip;
+            }
+        }
+        peer.ip()
+    }
+}
+
+#[derive(Deserialize)]
+struct KeyQuery {
+    api_key: Option<String>,
+}
+
+/// Key sent with the request, if any.
+fn request_key(headers: &HeaderMap, uri: &Uri) -> Option<String> {
+    if let Some(key) = headers
+        .get(API_KEY_HEADER)
+        .and_then(|value| value.to_str().ok())
+    {
+        return Some(key.trim().to_string());
+    }
+    if let Some(key) = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+    {
+        return Some(key.trim().to_string());
+    }
+    // Percent-decoded like any other query parameter.
+    Query::<KeyQuery>::try_from_uri(uri).ok()?.0.api_key
+}
+
+/// Key name of an authenticated request, for handlers behind [`enforce`].
+#[derive(Clone, Debug)]
+struct KeyName(String);
+
+/// Middleware: authenticate, rate limit and count every request.
+pub(crate) async fn enforce(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let path = request.uri().path();
+    if EXEMPT_PATHS.contains(&path) || request.method() == axum::http::Method::OPTIONS {
+        return next.run(request).await;
+    }
+    let access = &state.access;
+    let cost = request_cost(path);
+
+    let key_name = match request_key(request.headers(), request.uri()) {
+        Some(key) => match access.config.keys.get(&key) {
+            Some(name) => Some(name.clone()),
+            None => return ApiError::unauthorized("Unknown API key").into_response(),
+        },
+        None if access.config.require_key => {
+            return ApiError::unauthorized("An API key is required").into_response();
+        }
+        None => None,
+    };
+    let caller = match &key_name {
+        Some(name) => Caller::Key(name.clone()),
+        None => Caller::Ip(access.client_ip(request.headers(), peer)),
+    };
+
+    let stream_permit = if is_stream(path) {
+        match access.open_stream(&caller) {
+            Ok(permit) => permit,
+            Err(()) => {
+                if let Some(name) = &key_name {
+                    access.record(name, cost, false);
+                }
+                return (
+                    StatusCode::TOO_MANY_REQUESTS,
+                    Json(ErrorResponse {
+                        error: format!(
+                            "At most {} open streams per caller",
+                            access.config.max_streams_per_caller
+                        ),
+                    }),
+                )
+                    .into_response();
+            }
+        }
+    } else {
+        None
+    };
+
+    let admitted = access.admit(&caller, cost, Instant::now());
+    if let Some(name) = &key_name {
+        access.record(name, cost, admitted.is_ok());
+    }
+    if let Err(wait) = admitted {
+        let retry_after = wait.as_secs_f64().ceil().max(1.0) as u64;
+        return (
+            StatusCode::TOO_MANY_REQUESTS,
+            [(header::RETRY_AFTER, retry_after.to_string())],
+            Json(ErrorResponse {
+                error: format!("Rate limit exceeded, retry in {retry_after}s"),
+            }),
+        )
+            .into_response();
+    }
+
+    if let Some(name) = key_name {
+        request.extensions_mut().insert(KeyName(name));
+    }
+    if let Some(permit) = stream_permit {
+        request.extensions_mut().insert(permit);
+    }
+    next.run(request).await
+}
+
+/// `/v1/usage`: counters of the calling key since the process started.
+#[utoipa::path(
+    get,
+    path = "/v1/usage",
+    responses(
+        (status = 200, body = UsageResponse),
+        (status = 401, body = ErrorResponse),
+    )
+)]
+pub(crate) async fn get_usage(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+) -> Result<Json<UsageResponse>, ApiError> {
+    let KeyName(key_name) = request
+        .extensions()
+        .get::<KeyName>()
+        .cloned()
+        .ok_or_else(|| ApiError::unauthorized("Usage is reported per API key"))?;
+    let usage = state
+        .access
+        .usage
+        .lock()
+        .expect("usage counters poisoned")
+        .get(&key_name)
+        .cloned()
+        .unwrap_or_default();
+    Ok(Json(UsageResponse {
+        key_name,
+        since: state
+            .access
+            .started_at
+            .to_rfc3339_opts(SecondsFormat::Secs, true),
+        requests: usage.requests,
+        cost: usage.cost,
+        rate_limited: usage.rate_limited,
+        last_request_at: usage.last_request_at,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIMIT: RateLimit = RateLimit {
+        per_second: 2.0,
+        burst: 20.0,
+    };
+
+    #[test]
+    fn test_token_bucket_refills_at_rate() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::full(LIMIT, start);
+        assert_eq!(bucket.take(LIMIT, 20.0, start), Ok(()));
+        assert_eq!(
+            bucket.take(LIMIT, 5.0, start),
+            Err(Duration::from_millis(2_500))
+        );
+        assert_eq!(
+            bucket.take(LIMIT, 5.0, start + Duration::from_millis(2_500)),
+            Ok(())
+        );
+        // Never refills past the burst.
+        bucket.refill(LIMIT, start + Duration::from_secs(3_600));
+        assert_eq!(bucket.tokens, 20.0);
+    }
+
+    #[test]
+    fn test_request_cost_by_endpoint() {
+        assert_eq!(request_cost("/v1/markets/1/history"), MAX_REQUEST_COST);
+        assert_eq!(request_cost("/v1/authorities/abc/closed-positions"), 5);
+        assert_eq!(request_cost("/v1/markets/1/execution-quality"), 5);
+        assert_eq!(request_cost("/v1/markets/1/price"), 1);
+        assert_eq!(request_cost("/v1/markets/1/stream"), MAX_REQUEST_COST);
+        assert_eq!(request_cost("/v1/ws"), MAX_REQUEST_COST);
+    }
+
+    #[test]
+    fn test_stream_permits_cap_open_streams() {
+        let access = Arc::new(AccessControl::new(AccessConfig {
+            keys: HashMap::new(),
+            require_key: false,
+            key_limit: None,
+            ip_limit: None,
+            max_streams_per_caller: 2,
+            trust_forwarded_for: false,
+            cors_origins: None,
+        }));
+        let caller = Caller::Key("alice".to_string());
+        let first = access.open_stream(&caller).unwrap();
+        let second = access.open_stream(&caller).unwrap();
+        assert!(access.open_stream(&caller).is_err());
+        assert!(access.open_stream(&Caller::Key("bob".to_string())).is_ok());
+
+        // A clone held by the stream keeps the slot taken.
+        let held = first.clone();
+        drop(first);
+        assert!(access.open_stream(&caller).is_err());
+        drop((held, second));
+        assert!(access.open_stream(&caller).is_ok());
+        assert!(access.streams.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_parse_api_keys() {
+        let keys = parse_api_keys("alice:k1, bob:k2").unwrap();
+        assert_eq!(keys.get("k1").map(String::as_str), Some("alice"));
+        assert_eq!(keys.get("k2").map(String::as_str), Some("bob"));
+        assert!(parse_api_keys("").unwrap().is_empty());
+        assert!(parse_api_keys("alice").is_err());
+        assert!(parse_api_keys("alice:k1,bob:k1").is_err());
+    }
+
+    #[test]
+    fn test_request_key_sources() {
+        let uri = Uri::from_static("/v1/markets/1/stream");
+        let mut headers = HeaderMap::new();
+        assert_eq!(
+            request_key(
+                &headers,
+                &Uri::from_static("/v1/ws?market_id=1&api_key=k%2B3%3D")
+            )
+            .as_deref(),
+            Some("k+3=")
+        );
+        assert_eq!(request_key(&headers, &uri), None);
+        headers.insert(header::AUTHORIZATION, HeaderValue::from_static("Bearer k2"));
+        assert_eq!(request_key(&headers, &uri).as_deref(), Some("k2"));
+        headers.insert(API_KEY_HEADER, HeaderValue::from_static("k1"));
+        assert_eq!(request_key(&headers, &uri).as_deref(), Some("k1"));
+    }
+}
@@ -0,0 +1,382 @@
+//! Market-data aggregator endpoints (CoinGecko DEX spec) under
+//! `/v1/aggregator`.
+//!
+//! A pair's `ticker_id` is `<base_mint>_<quote_mint>` and `pool_id` is the
+//! market id, so several markets on the same mints list as pools of one
+//! ticker. Markets whose config lacks mints or decimals are not listed.
+//! Trades are closed trade positions, priced at their average fill; TWAP
+//! markets have no order book, so `/liquidity` reports the live per-slot
+//! flows instead.
+
+use anyhow::{Context, Result, anyhow};
+use axum::{
+    Json,
+    extract::{Query, State},
+    http::header,
+    response::IntoResponse,
+};
+use chrono::{DateTime, SecondsFormat, Utc};
+use deadpool_postgres::Pool;
+use std::sync::Arc;
+use twob_keepers::api::{
+    AggregatorLiquidityItem, AggregatorLiquidityQuery, AggregatorPairItem, AggregatorTickerItem,
+    ErrorResponse, HistoricalTradeItem, HistoricalTradesQuery, HistoricalTradesResponse,
+};
+
+use crate::{
+    ApiError, AppState, ClosedPositionRow, MarketConfig, atoms_to_units,
+    closed_position_row_from_pg, numeric_text, position_fill, query_market_configs,
+};
+
+const AGGREGATOR_CACHE_CONTROL: &str = "public, max-age=10, stale-while-revalidate=10";
+const DEFAULT_TRADES_LIMIT: usize = 500;
+const ABSOLUTE_MAX_TRADES_LIMIT: usize = 1000;
+
+/// A listed market with everything needed to scale its amounts.
+struct ListedPool {
+    ticker_id: String,
+    market_id: u64,
+    base_mint: String,
+    quote_mint: String,
+    base_symbol: Option<String>,
+    quote_symbol: Option<String>,
+    base_decimals: i16,
+    quote_decimals: i16,
+}
+
+impl ListedPool {
+    fn from_config(config: MarketConfig) -> Option<Self> {
+        let base_mint = config.base_mint?;
+        let quote_mint = config.quote_mint?;
+        Some(Self {
+            ticker_id: format!("{base_mint}_{quote_mint}"),
+            market_id: config.market_id,
+            base_mint,
+            quote_mint,
+            base_symbol: config.base_ticker,
+            quote_symbol: config.quote_ticker,
+            base_decimals: config.base_decimals?,
+            quote_decimals: config.quote_decimals?,
+        })
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/aggregator/pairs",
+    responses((status = 200, body = Vec<AggregatorPairItem>))
+)]
+pub(crate) async fn list_pairs(
+    State(state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, ApiError> {
+    let pools = listed_pools(&state.pool).await?;
+    let items: Vec<AggregatorPairItem> = pools
+        .into_iter()
+        .map(|pool| AggregatorPairItem {
+            pool_id: pool.market_id.to_string(),
+            ticker_id: pool.ticker_id,
+            base: pool.base_mint,
+            target: pool.quote_mint,
+            base_symbol: pool.base_symbol,
+            target_symbol: pool.quote_symbol,
+        })
+        .collect();
+
+    Ok((
+        [(header::CACHE_CONTROL, AGGREGATOR_CACHE_CONTROL)],
+        Json(items),
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/aggregator/tickers",
+    responses((status = 200, body = Vec<AggregatorTickerItem>))
+)]
+pub(crate) async fn list_tickers(
+    State(state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, ApiError> {
+    let pools = listed_pools(&state.pool).await?;
+    let tickers = state
+        .tickers
+        .get(&state)
+        .await
+        .map_err(|error| ApiError::internal(error.context("Failed to compute tickers")))?;
+
+    let items: Vec<AggregatorTickerItem> = pools
+        .into_iter()
+        .filter_map(|pool| {
+            let ticker = tickers
+                .iter()
+                .find(|ticker| ticker.market_id == pool.market_id)?;
+            Some(AggregatorTickerItem {
+                pool_id: pool.market_id.to_string(),
+                ticker_id: pool.ticker_id,
+                base_currency: pool.base_mint,
+                target_currency: pool.quote_mint,
+                last_price: ticker.last,
+                base_volume: ticker.base_volume,
+                target_volume: ticker.quote_volume,
+                liquidity_in_usd: None,
+                bid: None,
+                ask: None,
+                high: ticker.high,
+                low: ticker.low,
+            })
+        })
+        .collect();
+
+    Ok((
+        [(header::CACHE_CONTROL, AGGREGATOR_CACHE_CONTROL)],
+        Json(items),
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/aggregator/historical_trades",
+    params(HistoricalTradesQuery),
+    responses(
+        (status = 200, body = HistoricalTradesResponse),
+        (status = 400, body = ErrorResponse),
+        (status = 404, body = ErrorResponse),
+    )
+)]
+pub(crate) async fn get_historical_trades(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<HistoricalTradesQuery>,
+) -> Result<Json<HistoricalTradesResponse>, ApiError> {
+    let limit = query.limit.unwrap_or(DEFAULT_TRADES_LIMIT);
+    if limit == 0 || limit > ABSOLUTE_MAX_TRADES_LIMIT {
+        return Err(ApiError::bad_request(format!(
+            "limit must be between 1 and {ABSOLUTE_MAX_TRADES_LIMIT}"
+        )));
+    }
+    let is_buy = match query.trade_type.as_deref().map(str::trim) {
+        None | Some("") => None,
+        Some("buy") => Some(true),
+        Some("sell") => Some(false),
+        Some(other) => {
+            return Err(ApiError::bad_request(format!(
+                "Unsupported type '{other}'. Use 'buy' or 'sell'"
+            )));
+        }
+    };
+    let start_time = parse_millis(query.start_time, "start_time")?;
+    let end_time = parse_millis(query.end_time, "end_time")?;
+
+    let pools: Vec<ListedPool> = listed_pools(&state.pool)
+        .await?
+        .into_iter()
+        .filter(|pool| pool.ticker_id == query.ticker_id.trim())
+        .collect();
+    if pools.is_empty() {
+        return Err(ApiError::not_found(format!(
+            "Unknown ticker_id={}",
+            query.ticker_id
+        )));
+    }
+
+    let market_ids: Vec<i64> = pools
+        .iter()
+        .filter_map(|pool| i64::try_from(pool.market_id).ok())
+        .collect();
+    let rows = query_trade_rows(
+        &state.pool,
+        &state.config.close_position_events_table,
+        &market_ids,
+        is_buy,
+        start_time,
+        end_time,
+        limit,
+    )
+    .await
+    .map_err(|error| ApiError::internal(error.context("Failed to query trades")))?;
+
+    let mut response = HistoricalTradesResponse {
+        buy: Vec::new(),
+        sell: Vec::new(),
+    };
+    for row in rows {
+        let Some(pool) = pools
+            .iter()
+            .find(|pool| i64::try_from(pool.market_id).ok() == Some(row.market_id))
+        else {
+            continue;
+        };
+        let (base_atoms, quote_atoms) = position_fill(&row);
+        let (Some(base_volume), Some(target_volume)) = (
+            atoms_to_units(base_atoms, pool.base_decimals),
+            atoms_to_units(quote_atoms, pool.quote_decimals),
+        ) else {
+            continue;
+        };
+        // Nothing was filled.
+        if base_volume.is_zero() {
+            continue;
+        }
+
+        let trade = HistoricalTradeItem {
+            trade_id: format!("{}:{}", row.signature, row.event_index),
+            pool_id: pool.market_id.to_string(),
+            price: (target_volume / base_volume).round_dp(12).normalize(),
+            base_volume,
+            target_volume,
+            trade_timestamp: row.event_time_ms,
+            trade_type: if row.is_buy { "buy" } else { "sell" }.to_string(),
+        };
+        if row.is_buy {
+            response.buy.push(trade);
+        } else {
+            response.sell.push(trade);
+        }
+    }
+
+    Ok(Json(response))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/aggregator/liquidity",
+    params(AggregatorLiquidityQuery),
+    responses(
+        (status = 200, body = Vec<AggregatorLiquidityItem>),
+        (status = 404, body = ErrorResponse),
+    )
+)]
+pub(crate) async fn get_liquidity(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<AggregatorLiquidityQuery>,
+) -> Result<impl IntoResponse, ApiError> {
+    let ticker_id = query
+        .ticker_id
+        .as_deref()
+        .map(str::trim)
+        .filter(|ticker_id| !ticker_id.is_empty());
+    let pools: Vec<ListedPool> = listed_pools(&state.pool)
+        .await?
+        .into_iter()
+        .filter(|pool| ticker_id.is_none_or(|ticker_id| pool.ticker_id == ticker_id))
+        .collect();
+    if let (Some(ticker_id), true) = (ticker_id, pools.is_empty()) {
+        return Err(ApiError::not_found(format!(
+            "Unknown ticker_id={ticker_id}"
+        )));
+    }
+
+    let client = state.pool.get().await.map_err(|error| {
+        ApiError::internal(anyhow!(error).context("Failed to get DB connection"))
+    })?;
+    let market_ids: Vec<i64> = pools
+        .iter()
+        .filter_map(|pool| i64::try_from(pool.market_id).ok())
+        .collect();
+    let rows = client
+        .query(
+            "SELECT DISTINCT ON (s.market_id) s.market_id, s.snapshot_time, s.slot, \
+                    s.is_paused, s.open_positions, \
+                    (s.base_flow / power(10::numeric, mc.base_decimals::numeric))::text AS base_flow, \
+                    (s.quote_flow / power(10::numeric, mc.quote_decimals::numeric))::text AS quote_flow \
+             FROM market_state_snapshots s \
+             JOIN market_configs mc ON mc.market_id = s.market_id \
+             WHERE s.market_id = ANY($1) \
+             ORDER BY s.market_id, s.snapshot_time DESC",
+            &[&market_ids],
+        )
+        .await
+        .map_err(|error| {
+            ApiError::internal(anyhow!(error).context("Failed to query market state"))
+        })?;
+
+    let mut items = Vec::with_capacity(rows.len());
+    for row in rows {
+        let market_id: i64 = row.get("market_id");
+        let Some(pool) = pools
+            .iter()
+            .find(|pool| i64::try_from(pool.market_id).ok() == Some(market_id))
+        else {
+            continue;
+        };
+        let snapshot_time: DateTime<Utc> = row.get("snapshot_time");
+        let slot: i64 = row.get("slot");
+        // u128 flows can exceed `Decimal`; skip rather than fail the list.
+        let (Some(base_flow), Some(quote_flow)) = (
+            numeric_text(&row, "base_flow"),
+            numeric_text(&row, "quote_flow"),
+        ) else {
+            eprintln!("Skipping liquidity for market_id={market_id}: flow out of range");
+            continue;
+        };
+        let open_positions: i64 = row.get("open_positions");
+
+        items.push(AggregatorLiquidityItem {
+            ticker_id: pool.ticker_id.clone(),
+            pool_id: pool.market_id.to_string(),
+            snapshot_time: snapshot_time.to_rfc3339_opts(SecondsFormat::Secs, true),
+            slot: slot.max(0) as u64,
+            is_paused: row.get("is_paused"),
+            base_flow_per_slot: base_flow.normalize(),
+            target_flow_per_slot: quote_flow.normalize(),
+            open_positions: open_positions.max(0) as u64,
+        });
+    }
+
+    Ok((
+        [(header::CACHE_CONTROL, AGGREGATOR_CACHE_CONTROL)],
+        Json(items),
+    ))
+}
+
+async fn listed_pools(pool: &Pool) -> Result<Vec<ListedPool>, ApiError> {
+    let configs = query_market_configs(pool, None)
+        .await
+        .map_err(|error| ApiError::internal(error.context("Failed to query market configs")))?;
+    Ok(configs
+        .into_iter()
+        .filter_map(ListedPool::from_config)
+        .collect())
+}
+
+async fn query_trade_rows(
+    pool: &Pool,
+    close_position_events_table: &str,
+    market_ids: &[i64],
+    is_buy: Option<bool>,
+    start_time: Option<DateTime<Utc>>,
+    end_time: Option<DateTime<Utc>>,
+    limit: usize,
+) -> Result<Vec<ClosedPositionRow>> {
+    let limit_i64 = limit as i64;
+    let sql = format!(
+        "SELECT signature, event_index, slot, market_id, start_slot, \
+         end_slot, deposit_amount, swapped_amount, remaining_amount, fee_amount, is_buy, \
+         (extract(epoch from event_time) * 1000)::bigint AS event_time_ms \
+         FROM {close_position_events_table} \
+         WHERE market_id = ANY($1) \
+           AND ($2::boolean IS NULL OR is_buy = $2) \
+           AND ($3::timestamptz IS NULL OR event_time >= $3) \
+           AND ($4::timestamptz IS NULL OR event_time < $4) \
+         ORDER BY event_time DESC, slot DESC, event_index DESC \
+         LIMIT $5"
+    );
+
+    let client = pool.get().await.context("Failed to get DB connection")?;
+    let rows = client
+        .query(
+            &sql,
+            &[&market_ids, &is_buy, &start_time, &end_time, &limit_i64],
+        )
+        .await
+        .context("Failed to query close-position events")?;
+
+    Ok(rows.iter().map(closed_position_row_from_pg).collect())
+}
+
+fn parse_millis(value: Option<i64>, name: &str) -> Result<Option<DateTime<Utc>>, ApiError> {
+    value
+        .map(|millis| {
+            DateTime::<Utc>::from_timestamp_millis(millis)
+                .ok_or_else(|| ApiError::bad_request(format!("{name} is out of range")))
+        })
+        .transpose()
+}
@@ -0,0 +1,343 @@
+//! Execution quality of closed TWAP positions.
+//!
+//! A position's slippage compares its average fill price with the market TWAP
+//! over its `[start_slot, end_slot)` window (see
+//! [`portfolio::query_position_fills`](crate::portfolio::query_position_fills)),
+//! in basis points and signed so that positive is worse than the market:
+//! a buy paid more, a sell received less.
+
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+};
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use rust_decimal::Decimal;
+use std::sync::Arc;
+use twob_keepers::api::{
+    AuthorityExecutionQuery, AuthorityExecutionResponse, ErrorResponse, ExecutionItem,
+    ExecutionStats, MarketExecutionQuery, MarketExecutionResponse,
+};
+
+use crate::{
+    ABSOLUTE_MAX_UPDATES_LIMIT, ApiError, AppState, DEFAULT_UPDATES_LIMIT, MarketConfig,
+    atoms_to_units,
+    portfolio::{PositionFill, PositionFilter, atoms_price_to_units, query_position_fills},
+    position_fill, query_market_configs,
+};
+
+const DEFAULT_STATS_WINDOW_DAYS: i64 = 7;
+const MAX_STATS_WINDOW_DAYS: i64 = 90;
+/// Most recent positions folded into one market's stats.
+const MAX_STATS_POSITIONS: i64 = 10_000;
+const BPS: Decimal = Decimal::from_parts(10_000, 0, 0, false, 0);
+
+#[utoipa::path(
+    get,
+    path = "/v1/authorities/{authority}/execution",
+    params(
+        ("authority" = String, Path, description = "Wallet address"),
+        AuthorityExecutionQuery,
+    ),
+    responses(
+        (status = 200, body = AuthorityExecutionResponse),
+        (status = 400, body = ErrorResponse),
+    )
+)]
+pub(crate) async fn get_authority_execution(
+    State(state): State<Arc<AppState>>,
+    Path(authority): Path<String>,
+    Query(query): Query<AuthorityExecutionQuery>,
+) -> Result<Json<AuthorityExecutionResponse>, ApiError> {
+    let limit = query.limit.unwrap_or(DEFAULT_UPDATES_LIMIT);
+    if limit == 0 || limit > ABSOLUTE_MAX_UPDATES_LIMIT {
+        return Err(ApiError::bad_request(format!(
+            "limit must be between 1 and {ABSOLUTE_MAX_UPDATES_LIMIT}"
+        )));
+    }
+    let market_id = query
+        .market_id
+        .map(i64::try_from)
+        .transpose()
+        .map_err(|_| ApiError::bad_request("market_id out of range"))?;
+    let before_slot = query
+        .before_slot
+        .map(i64::try_from)
+        .transpose()
+        .map_err(|_| ApiError::bad_request("before_slot out of range"))?;
+
+    let mut fills = query_position_fills(
+        &state.pool,
+        &state.config.close_position_events_table,
+        &state.config.market_updates_table,
+        &PositionFilter {
+            authority: Some(&authority),
+            market_id,
+            before_slot,
+            ..PositionFilter::default()
+        },
+        limit as i64 + 1,
+    )
+    .await
+    .map_err(|error| ApiError::internal(error.context("Failed to query position fills")))?;
+    let has_more = fills.len() > limit;
+    fills.truncate(limit);
+
+    let configs = query_market_configs(&state.pool, None)
+        .await
+        .map_err(|error| ApiError::internal(error.context("Failed to query market configs")))?;
+
+    let items: Vec<ExecutionItem> = fills
+        .iter()
+        .map(|fill| {
+            let config = configs
+                .iter()
+                .find(|config| Some(config.market_id) == u64::try_from(fill.row.market_id).ok());
+            execution_item(fill, config)
+        })
+        .collect();
+
+    Ok(Json(AuthorityExecutionResponse {
+        authority,
+        market_id: query.market_id,
+        before_slot: query.before_slot,
+        has_more,
+        limit,
+        points: items.len(),
+        items,
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/markets/{market_id}/execution-quality",
+    params(("market_id" = u64, Path, description = "Market id"), MarketExecutionQuery),
+    responses(
+        (status = 200, body = MarketExecutionResponse),
+        (status = 400, body = ErrorResponse),
+        (status = 404, body = ErrorResponse),
+    )
+)]
+pub(crate) async fn get_market_execution(
+    State(state): State<Arc<AppState>>,
+    Path(market_id): Path<u64>,
+    Query(query): Query<MarketExecutionQuery>,
+) -> Result<Json<MarketExecutionResponse>, ApiError> {
+    let to = query.to.unwrap_or_else(Utc::now);
+    let from = query
+        .from
+        .unwrap_or(to - Duration::days(DEFAULT_STATS_WINDOW_DAYS));
+    if to <= from {
+        return Err(ApiError::bad_request("'to' must be later than 'from'"));
+    }
+    if to - from > Duration::days(MAX_STATS_WINDOW_DAYS) {
+        return Err(ApiError::bad_request(format!(
+            "The window may span at most {MAX_STATS_WINDOW_DAYS} days"
+        )));
+    }
+    let market_id_i64 =
+        i64::try_from(market_id).map_err(|_| ApiError::bad_request("market_id out of range"))?;
+
+    let config = query_market_configs(&state.pool, Some(market_id))
+        .await
+        .map_err(|error| ApiError::internal(error.context("Failed to query market config")))?
+        .into_iter()
+        .next()
+        .ok_or_else(|| ApiError::not_found(format!("No config for market_id={market_id}")))?;
+
+    let mut fills = query_position_fills(
+        &state.pool,
+        &state.config.close_position_events_table,
+        &state.config.market_updates_table,
+        &PositionFilter {
+            market_id: Some(market_id_i64),
+            from: Some(from),
+            to: Some(to),
+            ..PositionFilter::default()
+        },
+        MAX_STATS_POSITIONS + 1,
+    )
+    .await
+    .map_err(|error| ApiError::internal(error.context("Failed to query position fills")))?;
+    let truncated = fills.len() as i64 > MAX_STATS_POSITIONS;
+    fills.truncate(MAX_STATS_POSITIONS as usize);
+
+    let samples: Vec<(bool, Decimal, Decimal)> = fills
+        .iter()
+        .filter_map(|fill| {
+            let (_, quote_atoms) = position_fill(&fill.row);
+            let quote_volume = atoms_to_units(quote_atoms, config.quote_decimals?)?;
+            Some((fill.row.is_buy, fill_slippage_bps(fill)?, quote_volume))
+        })
+        .collect();
+    let side = |is_buy: bool| {
+        execution_stats(
+            samples
+                .iter()
+                .filter(|(buy, _, _)| *buy == is_buy)
+                .map(|(_, slippage, volume)| (*slippage, *volume)),
+        )
+    };
+
+    Ok(Json(MarketExecutionResponse {
+        market_id,
+        from: from.to_rfc3339_opts(SecondsFormat::Secs, true),
+        to: to.to_rfc3339_opts(SecondsFormat::Secs, true),
+        truncated,
+        all: execution_stats(
+            samples
+                .iter()
+                .map(|(_, slippage, volume)| (*slippage, *volume)),
+        ),
+        buy: side(true),
+        sell: side(false),
+    }))
+}
+
+fn execution_item(fill: &PositionFill, config: Option<&MarketConfig>) -> ExecutionItem {
+    let row = &fill.row;
+    let (base_atoms, quote_atoms) = position_fill(row);
+    let decimals = config.and_then(|config| Some((config.base_decimals?, config.quote_decimals?)));
+    let base_amount = decimals.and_then(|(base, _)| atoms_to_units(base_atoms, base));
+    let quote_amount = decimals.and_then(|(_, quote)| atoms_to_units(quote_atoms, quote));
+    let execution_price = match (base_amount, quote_amount) {
+        (Some(base), Some(quote)) if !base.is_zero() => Some(round_price(quote / base)),
+        _ => None,
+    };
+    let market_price = decimals.and_then(|(base, quote)| {
+        fill.market_twap
+            .map(|twap| round_price(atoms_price_to_units(twap, base, quote)))
+    });
+
+    ExecutionItem {
+        signature: row.signature.clone(),
+        event_index: u16::try_from(row.event_index).unwrap_or_default(),
+        slot: row.slot.max(0) as u64,
+        market_id: row.market_id.max(0) as u64,
+        start_slot: row.start_slot.max(0) as u64,
+        end_slot: row.end_slot.max(0) as u64,
+        is_buy: row.is_buy,
+        event_time: DateTime::<Utc>::from_timestamp_millis(row.event_time_ms)
+            .unwrap_or_default()
+            .to_rfc3339_opts(SecondsFormat::Millis, true),
+        base_amount,
+        quote_amount,
+        execution_price,
+        market_price,
+        slippage_bps: fill_slippage_bps(fill),
+    }
+}
+
+/// Slippage of one position, from atom amounts so decimals cancel out.
+fn fill_slippage_bps(fill: &PositionFill) -> Option<Decimal> {
+    let (base_atoms, quote_atoms) = position_fill(&fill.row);
+    if base_atoms == 0 {
+        return None;
+    }
+    let execution_price = Decimal::from(quote_atoms) / Decimal::from(base_atoms);
+    slippage_bps(fill.row.is_buy, execution_price, fill.market_twap?)
+}
+
+fn slippage_bps(is_buy: bool, execution_price: Decimal, market_price: Decimal) -> Option<Decimal> {
+    if market_price.is_zero() {
+        return None;
+    }
+    let relative = execution_price / market_price - Decimal::ONE;
+    let signed = if is_buy { relative } else { -relative };
+    Some((signed * BPS).round_dp(2).normalize())
+}
+
+/// Stats over `(slippage_bps, quote_volume)` samples.
+fn execution_stats(samples: impl Iterator<Item = (Decimal, Decimal)>) -> ExecutionStats {
+    let mut slippages = Vec::new();
+    let mut quote_volume = Decimal::ZERO;
+    let mut weighted = Decimal::ZERO;
+    for (slippage, volume) in samples {
+        slippages.push(slippage);
+        quote_volume += volume;
+        weighted += slippage * volume;
+    }
+    if slippages.is_empty() {
+        return ExecutionStats::default();
+    }
+    slippages.sort();
+
+    let count = Decimal::from(slippages.len());
+    let at_or_better = slippages
+        .iter()
+        .filter(|slippage| **slippage <= Decimal::ZERO)
+        .count();
+
+    ExecutionStats {
+        position_count: slippages.len(),
+        quote_volume: quote_volume.normalize(),
+        volume_weighted_slippage_bps: (!quote_volume.is_zero())
+            .then(|| (weighted / quote_volume).round_dp(2).normalize()),
+        median_slippage_bps: Some(percentile(&slippages, 50)),
+        p10_slippage_bps: Some(percentile(&slippages, 10)),
+        p90_slippage_bps: Some(percentile(&slippages, 90)),
+        at_or_better_than_market_share: Some(
+            (Decimal::from(at_or_better) / count)
+                .round_dp(4)
+                .normalize(),
+        ),
+    }
+}
+
+/// Nearest-rank percentile of a sorted, non-empty slice.
+fn percentile(sorted: &[Decimal], percent: usize) -> Decimal {
+    let rank = (percent * sorted.len()).div_ceil(100).max(1);
+    sorted[rank.min(sorted.len()) - 1]
+}
+
+fn round_price(price: Decimal) -> Decimal {
+    price.round_dp(12).normalize()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slippage_sign_follows_side() {
+        let market = Decimal::from(100);
+        // Buying at 101 is 100 bps worse; selling at 101 is 100 bps better.
+        assert_eq!(
+            slippage_bps(true, Decimal::from(101), market),
+            Some(Decimal::from(100))
+        );
+        assert_eq!(
+            slippage_bps(false, Decimal::from(101), market),
+            Some(Decimal::from(-100))
+        );
+        assert_eq!(slippage_bps(true, Decimal::ONE, Decimal::ZERO), None);
+    }
+
+    #[test]
+    fn test_execution_stats() {
+        let stats = execution_stats(
+            [(10, 1), (-5, 3), (0, 1), (20, 5)]
+                .into_iter()
+                .map(|(slippage, volume)| (Decimal::from(slippage), Decimal::from(volume))),
+        );
+
+        assert_eq!(stats.position_count, 4);
+        assert_eq!(stats.quote_volume, Decimal::from(10));
+        // (10 - 15 + 0 + 100) / 10
+        assert_eq!(
+            stats.volume_weighted_slippage_bps,
+            Some(Decimal::new(95, 1))
+        );
+        assert_eq!(stats.median_slippage_bps, Some(Decimal::ZERO));
+        assert_eq!(stats.p10_slippage_bps, Some(Decimal::from(-5)));
+        assert_eq!(stats.p90_slippage_bps, Some(Decimal::from(20)));
+        assert_eq!(
+            stats.at_or_better_than_market_share,
+            Some(Decimal::new(5, 1))
+        );
+
+        assert_eq!(
+            execution_stats(std::iter::empty()),
+            ExecutionStats::default()
+        );
+    }
+}
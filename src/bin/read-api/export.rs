@@ -0,0 +1,548 @@
+//! CSV and Parquet downloads of the list endpoints.
+//!
+//! `/history`, `/updates`, `/candles` and `/closed-positions` answer in JSON by
+//! default. `?format=csv|parquet`, or an `Accept` header naming `text/csv` or
+//! `application/vnd.apache.parquet`, turns them into a file download. Records
+//! are encoded as they arrive: CSV in blocks of lines, Parquet one row group at
+//! a time, so an export never holds more than a row group in memory.
+
+use anyhow::{Context, Result, anyhow};
+use arrow_array::{ArrayRef, BooleanArray, Decimal128Array, Int64Array, RecordBatch, StringArray};
+use arrow_schema::{DataType, Field, Schema};
+use axum::{
+    body::Body,
+    http::{HeaderMap, HeaderValue, header},
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, SecondsFormat, Utc};
+use futures_util::{Stream, StreamExt, stream};
+use parquet::{arrow::ArrowWriter, basic::Compression, file::properties::WriterProperties};
+use rust_decimal::Decimal;
+use std::sync::Arc;
+
+use crate::{ApiError, CandleItem, ClosedPositionRow, MarketHistoryRow};
+
+const CSV_CONTENT_TYPE: &str = "text/csv; charset=utf-8";
+const PARQUET_CONTENT_TYPE: &str = "application/vnd.apache.parquet";
+/// Records per CSV body chunk.
+const CSV_CHUNK_ROWS: usize = 1_000;
+/// Records per Parquet row group (and body chunk).
+const PARQUET_ROW_GROUP_ROWS: usize = 10_000;
+/// Parquet decimals are `decimal(38, 18)`: a file has one schema, written
+/// before the rows are seen, so every value is rescaled to a shared scale.
+const PARQUET_DECIMAL_PRECISION: u8 = 38;
+const PARQUET_DECIMAL_SCALE: i8 = 18;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ExportFormat {
+    Json,
+    Csv,
+    Parquet,
+}
+
+impl ExportFormat {
+    /// `format` wins over the `Accept` header; anything else stays JSON.
+    pub(crate) fn negotiate(format: Option<&str>, headers: &HeaderMap) -> Result<Self, ApiError> {
+        if let Some(format) = format {
+            return match format {
+                "json" => Ok(Self::Json),
+                "csv" => Ok(Self::Csv),
+                "parquet" => Ok(Self::Parquet),
+                other => Err(ApiError::bad_request(format!(
+                    "Unsupported format '{other}'. Use one of: json, csv, parquet"
+                ))),
+            };
+        }
+
+        let accept = headers
+            .get(header::ACCEPT)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+        let accepts = |media_type: &str| {
+            accept
+                .split(',')
+                .any(|entry| entry.split(';').next().unwrap_or_default().trim() == media_type)
+        };
+        Ok(if accepts("text/csv") {
+            Self::Csv
+        } else if accepts(PARQUET_CONTENT_TYPE) {
+            Self::Parquet
+        } else {
+            Self::Json
+        })
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            Self::Csv => "csv",
+            Self::Parquet => "parquet",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ColumnType {
+    Int64,
+    /// Exact in CSV; `decimal(38, 18)` in Parquet.
+    Decimal,
+    Boolean,
+    Utf8,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Cell {
+    Int64(i64),
+    Decimal(Decimal),
+    Boolean(bool),
+    Utf8(String),
+    Null,
+}
+
+/// A row type that can be exported. `cells` must follow `COLUMNS`.
+pub(crate) trait ExportRecord: Send + 'static {
+    const COLUMNS: &'static [(&'static str, ColumnType)];
+
+    fn cells(&self) -> Vec<Cell>;
+}
+
+/// Stream `records` as a `format` download named `{file_stem}.{extension}`.
+///
+/// Headers are sent before the first record is read, so a failure midway can
+/// only abort the body; it is logged and the client sees a truncated transfer.
+pub(crate) fn export_response<R, S>(format: ExportFormat, file_stem: &str, records: S) -> Response
+where
+    R: ExportRecord,
+    S: Stream<Item = Result<R>> + Send + 'static,
+{
+    let (content_type, body) = match format {
+        ExportFormat::Csv => (CSV_CONTENT_TYPE, Body::from_stream(csv_body(records))),
+        ExportFormat::Parquet => (
+            PARQUET_CONTENT_TYPE,
+            Body::from_stream(parquet_body(records)),
+        ),
+        ExportFormat::Json => {
+            return ApiError::internal(anyhow!("JSON is not an export format")).into_response();
+        }
+    };
+    let disposition = format!(
+        "attachment; filename=\"{file_stem}.{}\"",
+        format.extension()
+    );
+
+    (
+        [
+            (header::CONTENT_TYPE, HeaderValue::from_static(content_type)),
+            (
+                header::CONTENT_DISPOSITION,
+                HeaderValue::from_str(&disposition)
+                    .unwrap_or_else(|_| HeaderValue::from_static("attachment")),
+            ),
+        ],
+        body,
+    )
+        .into_response()
+}
+
+fn csv_body<R, S>(records: S) -> impl Stream<Item = Result<Vec<u8>>> + Send + 'static
+where
+    R: ExportRecord,
+    S: Stream<Item = Result<R>> + Send + 'static,
+{
+    let header_line = R::COLUMNS
+        .iter()
+        .map(|(name, _)| csv_field(name))
+        .collect::<Vec<_>>()
+        .join(",");
+    let header = stream::once(async move { Ok(format!("{header_line}\n").into_bytes()) });
+
+    let lines = records.chunks(CSV_CHUNK_ROWS).map(|chunk| {
+        let mut buffer = String::new();
+        for record in chunk {
+            let record = record.inspect_err(|error| eprintln!("CSV export failed: {error:#}"))?;
+            buffer.push_str(&csv_line(&record.cells()));
+        }
+        Ok(buffer.into_bytes())
+    });
+
+    header.chain(lines)
+}
+
+fn csv_line(cells: &[Cell]) -> String {
+    let fields: Vec<String> = cells
+        .iter()
+        .map(|cell| match cell {
+            Cell::Int64(value) => value.to_string(),
+            Cell::Decimal(value) => value.normalize().to_string(),
+            Cell::Boolean(value) => value.to_string(),
+            Cell::Utf8(value) => csv_field(value),
+            Cell::Null => String::new(),
+        })
+        .collect();
+    format!("{}\n", fields.join(","))
+}
+
+/// RFC 4180 quoting: fields with a comma, quote or line break are quoted and
+/// their quotes doubled.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+type ParquetState<R> = (
+    stream::BoxStream<'static, Vec<Result<R>>>,
+    ArrowWriter<Vec<u8>>,
+    Arc<Schema>,
+);
+
+fn parquet_body<R, S>(records: S) -> impl Stream<Item = Result<Vec<u8>>> + Send + 'static
+where
+    R: ExportRecord,
+    S: Stream<Item = Result<R>> + Send + 'static,
+{
+    let schema = Arc::new(arrow_schema::<R>());
+    let properties = WriterProperties::builder()
+        .set_compression(Compression::SNAPPY)
+        .build();
+    let initial: Result<ParquetState<R>> =
+        ArrowWriter::try_new(Vec::new(), schema.clone(), Some(properties))
+            .context("Failed to start Parquet writer")
+            .map(|writer| {
+                (
+                    records.chunks(PARQUET_ROW_GROUP_ROWS).boxed(),
+                    writer,
+                    schema,
+                )
+            });
+
+    stream::unfold(Some(initial), |state| async move {
+        let (mut chunks, mut writer, schema) = match state? {
+            Ok(state) => state,
+            Err(error) => return Some((Err(error), None)),
+        };
+        let (bytes, next) = match chunks.next().await {
+            Some(chunk) => match write_row_group(&mut writer, &schema, chunk) {
+                // Hand over what the writer has produced so far; the footer
+                // written on close only counts bytes, not buffer positions.
+                Ok(()) => (
+                    Ok(std::mem::take(writer.inner_mut())),
+                    Some(Ok((chunks, writer, schema))),
+                ),
+                Err(error) => (Err(error), None),
+            },
+            None => (
+                writer.into_inner().context("Failed to finish Parquet file"),
+                None,
+            ),
+        };
+        if let Err(error) = &bytes {
+            eprintln!("Parquet export failed: {error:#}");
+        }
+        Some((bytes, next))
+    })
+}
+
+fn write_row_group<R: ExportRecord>(
+    writer: &mut ArrowWriter<Vec<u8>>,
+    schema: &Arc<Schema>,
+    chunk: Vec<Result<R>>,
+) -> Result<()> {
+    let rows: Vec<Vec<Cell>> = chunk
+        .into_iter()
+        .map(|record| record.map(|record| record.cells()))
+        .collect::<Result<_>>()?;
+    let batch = record_batch::<R>(schema, &rows)?;
+    writer
+        .write(&batch)
+        .context("Failed to write Parquet rows")?;
+    writer.flush().context("Failed to flush Parquet row group")
+}
+
+fn arrow_schema<R: ExportRecord>() -> Schema {
+    Schema::new(
+        R::COLUMNS
+            .iter()
+            .map(|(name, column_type)| {
+                let data_type = match column_type {
+                    ColumnType::Int64 => DataType::Int64,
+                    ColumnType::Decimal => {
+                        DataType::Decimal128(PARQUET_DECIMAL_PRECISION, PARQUET_DECIMAL_SCALE)
+                    }
+                    ColumnType::Boolean => DataType::Boolean,
+                    ColumnType::Utf8 => DataType::Utf8,
+                };
+                Field::new(*name, data_type, true)
+            })
+            .collect::<Vec<_>>(),
+    )
+}
+
+/// Column-wise arrays of `rows`; a cell of the wrong type, or a decimal that
+/// does not fit `decimal(38, 18)`, becomes null.
+fn record_batch<R: ExportRecord>(schema: &Arc<Schema>, rows: &[Vec<Cell>]) -> Result<RecordBatch> {
+    let columns: Vec<ArrayRef> = R::COLUMNS
+        .iter()
+        .enumerate()
+        .map(|(index, (_, column_type))| {
+            let cells = rows
+                .iter()
+                .map(move |row| row.get(index).unwrap_or(&Cell::Null));
+            let array: ArrayRef = match column_type {
+                ColumnType::Int64 => {
+                    Arc::new(Int64Array::from_iter(cells.map(|cell| match cell {
+                        Cell::Int64(value) => Some(*value),
+                        _ => None,
+                    })))
+                }
+                ColumnType::Decimal => Arc::new(
+                    Decimal128Array::from_iter(cells.map(|cell| match cell {
+                        Cell::Decimal(value) => decimal128_mantissa(*value),
+                        _ => None,
+                    }))
+                    .with_precision_and_scale(PARQUET_DECIMAL_PRECISION, PARQUET_DECIMAL_SCALE)
+                    .context("Invalid Parquet decimal type")?,
+                ),
+                ColumnType::Boolean => {
+                    Arc::new(BooleanArray::from_iter(cells.map(|cell| match cell {
+                        Cell::Boolean(value) => Some(*value),
+                        _ => None,
+                    })))
+                }
+                ColumnType::Utf8 => {
+                    Arc::new(StringArray::from_iter(cells.map(|cell| match cell {
+                        Cell::Utf8(value) => Some(value.as_str()),
+                        _ => None,
+                    })))
+                }
+            };
+            Ok(array)
+        })
+        .collect::<Result<_>>()?;
+
+    RecordBatch::try_new(schema.clone(), columns).context("Failed to build Parquet record batch")
+}
+
+impl ExportRecord for MarketHistoryRow {
+    const COLUMNS: &'static [(&'static str, ColumnType)] = &[
+        ("event_uid", ColumnType::Utf8),
+        ("signature", ColumnType::Utf8),
+        ("event_index", ColumnType::Int64),
+        ("slot", ColumnType::Int64),
+        ("market_id", ColumnType::Int64),
+        ("base_flow", ColumnType::Int64),
+        ("quote_flow", ColumnType::Int64),
+        ("created_at", ColumnType::Utf8),
+    ];
+
+    fn cells(&self) -> Vec<Cell> {
+        vec![
+            Cell::Utf8(self.event_uid.clone()),
+            Cell::Utf8(self.signature.clone()),
+            Cell::Int64(i64::from(self.event_index)),
+            Cell::Int64(self.slot),
+            Cell::Int64(self.market_id),
+            Cell::Int64(self.base_flow),
+            Cell::Int64(self.quote_flow),
+            timestamp_cell(self.event_time_ms),
+        ]
+    }
+}
+
+impl ExportRecord for ClosedPositionRow {
+    const COLUMNS: &'static [(&'static str, ColumnType)] = &[
+        ("signature", ColumnType::Utf8),
+        ("event_index", ColumnType::Int64),
+        ("slot", ColumnType::Int64),
+        ("market_id", ColumnType::Int64),
+        ("start_slot", ColumnType::Int64),
+        ("end_slot", ColumnType::Int64),
+        ("deposit_amount", ColumnType::Int64),
+        ("swapped_amount", ColumnType::Int64),
+        ("remaining_amount", ColumnType::Int64),
+        ("fee_amount", ColumnType::Int64),
+        ("is_buy", ColumnType::Boolean),
+        ("event_time", ColumnType::Utf8),
+    ];
+
+    fn cells(&self) -> Vec<Cell> {
+        vec![
+            Cell::Utf8(self.signature.clone()),
+            Cell::Int64(i64::from(self.event_index)),
+            Cell::Int64(self.slot),
+            Cell::Int64(self.market_id),
+            Cell::Int64(self.start_slot),
+            Cell::Int64(self.end_slot),
+            Cell::Int64(self.deposit_amount),
+            Cell::Int64(self.swapped_amount),
+            Cell::Int64(self.remaining_amount),
+            Cell::Int64(self.fee_amount),
+            Cell::Boolean(self.is_buy),
+            timestamp_cell(self.event_time_ms),
+        ]
+    }
+}
+
+impl ExportRecord for CandleItem {
+    const COLUMNS: &'static [(&'static str, ColumnType)] = &[
+        ("time", ColumnType::Int64),
+        ("open", ColumnType::Decimal),
+        ("high", ColumnType::Decimal),
+        ("low", ColumnType::Decimal),
+        ("close", ColumnType::Decimal),
+    ];
+
+    fn cells(&self) -> Vec<Cell> {
+        vec![
+            Cell::Int64(i64::try_from(self.time).unwrap_or(i64::MAX)),
+            Cell::Decimal(self.open),
+            Cell::Decimal(self.high),
+            Cell::Decimal(self.low),
+            Cell::Decimal(self.close),
+        ]
+    }
+}
+
+/// `value` as a `Decimal128` mantissa at [`PARQUET_DECIMAL_SCALE`], rounding
+/// extra fraction digits; `None` when it needs more than
+/// [`PARQUET_DECIMAL_PRECISION`] digits.
+fn decimal128_mantissa(value: Decimal) -> Option<i128> {
+    let scale = PARQUET_DECIMAL_SCALE as u32;
+    let value = value.round_dp(scale);
+    let mantissa = value
+        .mantissa()
+        .checked_mul(10i128.checked_pow(scale - value.scale())?)?;
+    (mantissa.unsigned_abs() < 10u128.pow(u32::from(PARQUET_DECIMAL_PRECISION))).then_some(mantissa)
+}
+
+/// RFC 3339 with milliseconds, as in the JSON responses.
+fn timestamp_cell(millis: i64) -> Cell {
+    DateTime::<Utc>::from_timestamp_millis(millis)
+        .map(|time| Cell::Utf8(time.to_rfc3339_opts(SecondsFormat::Millis, true)))
+        .unwrap_or(Cell::Null)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parquet::{
+        basic::LogicalType,
+        file::reader::{FileReader, SerializedFileReader},
+    };
+
+    struct Sample {
+        id: i64,
+        label: &'static str,
+        price: Option<Decimal>,
+    }
+
+    impl ExportRecord for Sample {
+        const COLUMNS: &'static [(&'static str, ColumnType)] = &[
+            ("id", ColumnType::Int64),
+            ("label", ColumnType::Utf8),
+            ("price", ColumnType::Decimal),
+        ];
+
+        fn cells(&self) -> Vec<Cell> {
+            vec![
+                Cell::Int64(self.id),
+                Cell::Utf8(self.label.to_string()),
+                self.price.map(Cell::Decimal).unwrap_or(Cell::Null),
+            ]
+        }
+    }
+
+    fn samples() -> impl Stream<Item = Result<Sample>> + Send + 'static {
+        stream::iter([
+            Ok(Sample {
+                id: 1,
+                label: "plain",
+                price: Some(Decimal::new(1500, 3)),
+            }),
+            Ok(Sample {
+                id: 2,
+                label: "a \"quoted\", label",
+                price: None,
+            }),
+        ])
+    }
+
+    #[test]
+    fn test_negotiate_prefers_query_over_accept() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::ACCEPT,
+            HeaderValue::from_static("text/html, text/csv;q=0.9"),
+        );
+
+        assert_eq!(
+            ExportFormat::negotiate(None, &headers).unwrap(),
+            ExportFormat::Csv
+        );
+        assert_eq!(
+            ExportFormat::negotiate(Some("parquet"), &headers).unwrap(),
+            ExportFormat::Parquet
+        );
+        assert_eq!(
+            ExportFormat::negotiate(None, &HeaderMap::new()).unwrap(),
+            ExportFormat::Json
+        );
+        assert!(ExportFormat::negotiate(Some("xlsx"), &headers).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_csv_body_quotes_fields_and_leaves_nulls_empty() {
+        let chunks: Vec<Vec<u8>> = csv_body(samples())
+            .map(|chunk| chunk.unwrap())
+            .collect()
+            .await;
+        let csv = String::from_utf8(chunks.concat()).unwrap();
+
+        assert_eq!(
+            csv,
+            "id,label,price\n1,plain,1.5\n2,\"a \"\"quoted\"\", label\",\n"
+        );
+    }
+
+    #[test]
+    fn test_decimal128_mantissa_rescales_to_the_column_scale() {
+        assert_eq!(
+            decimal128_mantissa(Decimal::new(1500, 3)),
+            Some(1_500_000_000_000_000_000)
+        );
+        assert_eq!(
+            decimal128_mantissa(Decimal::from_str_exact("0.0000000000000000015").unwrap()),
+            Some(2)
+        );
+        assert_eq!(decimal128_mantissa(Decimal::MAX), None);
+    }
+
+    #[tokio::test]
+    async fn test_parquet_body_writes_a_complete_file() {
+        let chunks: Vec<Vec<u8>> = parquet_body(samples())
+            .map(|chunk| chunk.unwrap())
+            .collect()
+            .await;
+        let file = chunks.concat();
+
+        assert!(file.starts_with(b"PAR1"));
+        assert!(file.ends_with(b"PAR1"));
+        let path =
+            std::env::temp_dir().join(format!("read-api-export-{}.parquet", std::process::id()));
+        std::fs::write(&path, &file).unwrap();
+        let reader = SerializedFileReader::new(std::fs::File::open(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let metadata = reader.metadata().file_metadata();
+        assert_eq!(metadata.num_rows(), 2);
+        assert_eq!(metadata.schema_descr().num_columns(), 3);
+        assert_eq!(
+            metadata.schema_descr().column(2).logical_type(),
+            Some(LogicalType::Decimal {
+                scale: 18,
+                precision: 38
+            })
+        );
+    }
+}
@@ -0,0 +1,365 @@
+//! Protocol fee revenue: `/v1/fees`.
+//!
+//! Reads the daily `market_fees_1d` rollup the event keeper maintains from
+//! close-position events. A position pays its fee in the token it receives, so
+//! each market earns base fees from buys and quote fees from sells; totals are
+//! also folded per mint across markets.
+
+use anyhow::{Context, Result};
+use axum::{
+    Json,
+    extract::{Query, State},
+};
+use chrono::{DateTime, Duration, DurationRound, SecondsFormat, Utc};
+use deadpool_postgres::Pool;
+use rust_decimal::Decimal;
+use std::{collections::BTreeMap, sync::Arc};
+use twob_keepers::api::{ErrorResponse, FeeBucket, FeesQuery, FeesResponse, MarketFees, MintFees};
+
+use crate::{ApiError, AppState, MarketConfig, query_market_configs};
+
+const DEFAULT_FEES_WINDOW_DAYS: i64 = 30;
+const MAX_FEES_WINDOW_DAYS: i64 = 366;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum FeeInterval {
+    Day,
+    Week,
+    Month,
+}
+
+impl FeeInterval {
+    fn parse(value: &str) -> Result<Self, ApiError> {
+        match value {
+            "day" => Ok(Self::Day),
+            "week" => Ok(Self::Week),
+            "month" => Ok(Self::Month),
+            other => Err(ApiError::bad_request(format!(
+                "Unsupported interval '{other}'. Use one of: day, week, month"
+            ))),
+        }
+    }
+
+    /// Also the `date_trunc` field.
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Day => "day",
+            Self::Week => "week",
+            Self::Month => "month",
+        }
+    }
+}
+
+/// Fee atoms of one market in one bucket.
+struct FeeRow {
+    market_id: u64,
+    bucket_start: DateTime<Utc>,
+    positions: i64,
+    base_fees: Decimal,
+    quote_fees: Decimal,
+}
+
+struct AccumulatedFees {
+    base: Decimal,
+    quote: Decimal,
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/fees",
+    params(FeesQuery),
+    responses(
+        (status = 200, body = FeesResponse),
+        (status = 400, body = ErrorResponse),
+        (status = 404, body = ErrorResponse),
+    )
+)]
+pub(crate) async fn get_fees(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<FeesQuery>,
+) -> Result<Json<FeesResponse>, ApiError> {
+    let interval = query
+        .interval
+        .as_deref()
+        .map(FeeInterval::parse)
+        .transpose()?
+        .unwrap_or(FeeInterval::Day);
+    let to = query.to.unwrap_or_else(Utc::now);
+    let from = match query.from {
+        Some(from) => from,
+        None => (to - Duration::days(DEFAULT_FEES_WINDOW_DAYS))
+            .duration_trunc(Duration::days(1))
+            .map_err(|error| ApiError::bad_request(format!("Invalid 'to': {error}")))?,
+    };
+    if to <= from {
+        return Err(ApiError::bad_request("'to' must be later than 'from'"));
+    }
+    if to - from > Duration::days(MAX_FEES_WINDOW_DAYS) {
+        return Err(ApiError::bad_request(format!(
+            "The window may span at most {MAX_FEES_WINDOW_DAYS} days"
+        )));
+    }
+    let market_id = query
+        .market_id
+        .map(i64::try_from)
+        .transpose()
+        .map_err(|_| ApiError::bad_request("market_id out of range"))?;
+
+    let configs = query_market_configs(&state.pool, query.market_id)
+        .await
+        .map_err(|error| ApiError::internal(error.context("Failed to query market configs")))?;
+    if query.market_id.is_some() && configs.is_empty() {
+        return Err(ApiError::not_found(format!(
+            "No config for market_id={}",
+            query.market_id.unwrap_or_default()
+        )));
+    }
+    let rows = query_fee_rows(&state.pool, from, to, market_id, interval)
+        .await
+        .map_err(|error| ApiError::internal(error.context("Failed to query fees")))?;
+    let accumulated = query_accumulated_fees(&state.pool, market_id)
+        .await
+        .map_err(|error| ApiError::internal(error.context("Failed to query market state")))?;
+
+    let config_of = |market_id: u64| configs.iter().find(|config| config.market_id == market_id);
+    let items: Vec<FeeBucket> = rows
+        .iter()
+        .map(|row| {
+            let (base_fees, quote_fees) =
+                fees_to_units(config_of(row.market_id), row.base_fees, row.quote_fees);
+            FeeBucket {
+                bucket_start: row.bucket_start.to_rfc3339_opts(SecondsFormat::Secs, true),
+                market_id: row.market_id,
+                positions: row.positions,
+                base_fees,
+                quote_fees,
+            }
+        })
+        .collect();
+
+    let markets: Vec<MarketFees> = configs
+        .iter()
+        .map(|config| {
+            let market_rows = rows.iter().filter(|row| row.market_id == config.market_id);
+            let (positions, base_atoms, quote_atoms) = market_rows.fold(
+                (0, Decimal::ZERO, Decimal::ZERO),
+                |(positions, base, quote), row| {
+                    (
+                        positions + row.positions,
+                        base + row.base_fees,
+                        quote + row.quote_fees,
+                    )
+                },
+            );
+            let (base_fees, quote_fees) = fees_to_units(Some(config), base_atoms, quote_atoms);
+            let accumulated = accumulated
+                .get(&config.market_id)
+                .map(|fees| fees_to_units(Some(config), fees.base, fees.quote));
+            MarketFees {
+                market_id: config.market_id,
+                base_mint: config.base_mint.clone(),
+                quote_mint: config.quote_mint.clone(),
+                base_ticker: config.base_ticker.clone(),
+                quote_ticker: config.quote_ticker.clone(),
+                positions,
+                base_fees,
+                quote_fees,
+                accumulated_base_fees: accumulated.and_then(|(base, _)| base),
+                accumulated_quote_fees: accumulated.and_then(|(_, quote)| quote),
+            }
+        })
+        .collect();
+    let mints = mint_totals(&markets);
+
+    Ok(Json(FeesResponse {
+        from: from.to_rfc3339_opts(SecondsFormat::Secs, true),
+        to: to.to_rfc3339_opts(SecondsFormat::Secs, true),
+        interval: interval.as_str().to_string(),
+        market_id: query.market_id,
+        markets,
+        mints,
+        points: items.len(),
+        items,
+    }))
+}
+
+fn fees_to_units(
+    config: Option<&MarketConfig>,
+    base_atoms: Decimal,
+    quote_atoms: Decimal,
+) -> (Option<Decimal>, Option<Decimal>) {
+    let scale = |atoms: Decimal, decimals: Option<i16>| {
+        let decimals = u32::try_from(decimals?).ok().filter(|scale| *scale <= 28)?;
+        let mut units = atoms;
+        units.set_scale(units.scale() + decimals).ok()?;
+        Some(units.normalize())
+    };
+    (
+        scale(base_atoms, config.and_then(|config| config.base_decimals)),
+        scale(quote_atoms, config.and_then(|config| config.quote_decimals)),
+    )
+}
+
+/// Fold every market's base and quote fees into per-mint totals, ordered by
+/// mint. Markets without a mint or decimals are left out.
+fn mint_totals(markets: &[MarketFees]) -> Vec<MintFees> {
+    let mut totals: BTreeMap<&str, MintFees> = BTreeMap::new();
+    for market in markets {
+        let sides = [
+            (&market.base_mint, &market.base_ticker, market.base_fees),
+            (&market.quote_mint, &market.quote_ticker, market.quote_fees),
+        ];
+        for (mint, ticker, fees) in sides {
+            let (Some(mint), Some(fees)) = (mint, fees) else {
+                continue;
+            };
+            let total = totals.entry(mint).or_insert_with(|| MintFees {
+                mint: mint.clone(),
+                ticker: None,
+                fees: Decimal::ZERO,
+            });
+            total.fees += fees;
+            if total.ticker.is_none() {
+                total.ticker = ticker.clone();
+            }
+        }
+    }
+    totals.into_values().collect()
+}
+
+async fn query_fee_rows(
+    pool: &Pool,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    market_id: Option<i64>,
+    interval: FeeInterval,
+) -> Result<Vec<FeeRow>> {
+    let client = pool.get().await.context("Failed to get DB connection")?;
+    let rows = client
+        .query(
+            "SELECT market_id, \
+                    date_trunc($4, bucket_start AT TIME ZONE 'UTC') AT TIME ZONE 'UTC' AS bucket, \
+                    sum(positions)::bigint AS positions, \
+                    sum(base_fees) AS base_fees, \
+                    sum(quote_fees) AS quote_fees \
+             FROM market_fees_1d \
+             WHERE bucket_start >= $1 AND bucket_start < $2 \
+               AND ($3::bigint IS NULL OR market_id = $3) \
+             GROUP BY 1, 2 \
+             ORDER BY 2, 1",
+            &[&from, &to, &market_id, &interval.as_str()],
+        )
+        .await
+        .context("Failed to query market_fees_1d")?;
+
+    rows.iter()
+        .map(|row| {
+            let market_id: i64 = row.get("market_id");
+            Ok(FeeRow {
+                market_id: u64::try_from(market_id).context("market_id out of range")?,
+                bucket_start: row.get("bucket"),
+                positions: row.get("positions"),
+                base_fees: row.get("base_fees"),
+                quote_fees: row.get("quote_fees"),
+            })
+        })
+        .collect()
+}
+
+/// Latest snapshot fee counters per market.
+async fn query_accumulated_fees(
+    pool: &Pool,
+    market_id: Option<i64>,
+) -> Result<BTreeMap<u64, AccumulatedFees>> {
+    let client = pool.get().await.context("Failed to get DB connection")?;
+    let rows = client
+        .query(
+            "SELECT DISTINCT ON (market_id) \
+                    market_id, accumulated_base_fees, accumulated_quote_fees \
+             FROM market_state_snapshots \
+             WHERE ($1::bigint IS NULL OR market_id = $1) \
+             ORDER BY market_id, snapshot_time DESC",
+            &[&market_id],
+        )
+        .await
+        .context("Failed to query market_state_snapshots")?;
+
+    Ok(rows
+        .iter()
+        .filter_map(|row| {
+            let market_id: i64 = row.get("market_id");
+            Some((
+                u64::try_from(market_id).ok()?,
+                AccumulatedFees {
+                    base: row.get("accumulated_base_fees"),
+                    quote: row.get("accumulated_quote_fees"),
+                },
+            ))
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn market(market_id: u64, base: (&str, Option<i64>), quote: (&str, Option<i64>)) -> MarketFees {
+        MarketFees {
+            market_id,
+            base_mint: Some(base.0.to_string()),
+            quote_mint: Some(quote.0.to_string()),
+            base_ticker: None,
+            quote_ticker: Some("USDC".to_string()),
+            positions: 1,
+            base_fees: base.1.map(Decimal::from),
+            quote_fees: quote.1.map(Decimal::from),
+            accumulated_base_fees: None,
+            accumulated_quote_fees: None,
+        }
+    }
+
+    #[test]
+    fn test_mint_totals_fold_both_sides_across_markets() {
+        let markets = vec![
+            market(1, ("sol", Some(2)), ("usdc", Some(10))),
+            market(2, ("usdc", Some(3)), ("bonk", None)),
+        ];
+
+        let totals = mint_totals(&markets);
+        let fees: Vec<(&str, Decimal)> = totals
+            .iter()
+            .map(|total| (total.mint.as_str(), total.fees))
+            .collect();
+        assert_eq!(
+            fees,
+            vec![("sol", Decimal::from(2)), ("usdc", Decimal::from(13))]
+        );
+        assert_eq!(totals[1].ticker.as_deref(), Some("USDC"));
+    }
+
+    #[test]
+    fn test_fees_to_units_scales_each_side() {
+        let config = MarketConfig {
+            market_id: 1,
+            base_mint: None,
+            quote_mint: None,
+            base_decimals: Some(9),
+            quote_decimals: None,
+            base_ticker: None,
+            quote_ticker: None,
+        };
+
+        let (base, quote) = fees_to_units(
+            Some(&config),
+            Decimal::from(1_500_000_000u64),
+            Decimal::from(5),
+        );
+        assert_eq!(base, Some(Decimal::new(15, 1)));
+        assert_eq!(quote, None);
+        assert_eq!(
+            FeeInterval::parse("week").map(FeeInterval::as_str).ok(),
+            Some("week")
+        );
+        assert!(FeeInterval::parse("1d").is_err());
+    }
+}
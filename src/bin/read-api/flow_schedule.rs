@@ -0,0 +1,248 @@
+//! Forward-looking flow schedule of a market:
+//! `/v1/markets/{market_id}/flow-schedule`.
+//!
+//! A TWAP market has no order book; its price is the ratio of the quote and
+//! base flows currently trading. Trade positions leave the market at their
+//! `end_slot`, and `account-indexer` copies those upcoming exits from the
+//! `Exits` accounts into `market_exits` together with each state snapshot.
+//! Subtracting them from the snapshot's flows, slot by slot, shows how the flows
+//! and therefore the price will move if nothing else changes.
+
+use anyhow::{Context, Result};
+use axum::{
+    Json,
+    extract::{Path, State},
+};
+use chrono::{DateTime, SecondsFormat, Utc};
+use deadpool_postgres::Pool;
+use rust_decimal::Decimal;
+use std::sync::Arc;
+use twob_keepers::api::{ErrorResponse, FlowScheduleItem, FlowScheduleResponse};
+
+use crate::{ApiError, AppState, numeric_text, query_market_configs, slots::SlotClock};
+
+/// Flows in token atoms per slot.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Flows {
+    base: Decimal,
+    quote: Decimal,
+}
+
+#[derive(Debug, PartialEq)]
+struct FlowStep {
+    slot: u64,
+    base_exit: Decimal,
+    quote_exit: Decimal,
+    remaining: Flows,
+}
+
+struct ScheduleRow {
+    slot: u64,
+    snapshot_time: DateTime<Utc>,
+    flows: Flows,
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/markets/{market_id}/flow-schedule",
+    params(("market_id" = u64, Path, description = "Market id")),
+    responses(
+        (status = 200, body = FlowScheduleResponse),
+        (status = 400, body = ErrorResponse),
+        (status = 404, body = ErrorResponse),
+    )
+)]
+pub(crate) async fn get_flow_schedule(
+    State(state): State<Arc<AppState>>,
+    Path(market_id): Path<u64>,
+) -> Result<Json<FlowScheduleResponse>, ApiError> {
+    let market_id_i64 =
+        i64::try_from(market_id).map_err(|_| ApiError::bad_request("market_id out of range"))?;
+    let config = query_market_configs(&state.pool, Some(market_id))
+        .await
+        .map_err(|error| ApiError::internal(error.context("Failed to query market config")))?
+        .into_iter()
+        .next()
+        .ok_or_else(|| ApiError::not_found(format!("No config for market_id={market_id}")))?;
+
+    let (snapshot, exits) = query_flow_schedule(&state.pool, market_id_i64)
+        .await
+        .map_err(|error| ApiError::internal(error.context("Failed to query flow schedule")))?;
+    let Some(snapshot) = snapshot else {
+        return Err(ApiError::not_found(format!(
+            "No state snapshots for market_id={market_id}"
+        )));
+    };
+
+    let scales = config
+        .base_decimals
+        .zip(config.quote_decimals)
+        .and_then(|(base, quote)| Some((decimal_scale(base)?, decimal_scale(quote)?)));
+    let to_units = |flows: Flows| {
+        scales.map(|(base_scale, quote_scale)| Flows {
+            base: (flows.base / base_scale).normalize(),
+            quote: (flows.quote / quote_scale).normalize(),
+        })
+    };
+    let clock = SlotClock {
+        slot: snapshot.slot,
+        observed_at: snapshot.snapshot_time,
+    };
+    let current = to_units(snapshot.flows);
+    let current_price = current.and_then(implied_price);
+
+    let items: Vec<FlowScheduleItem> = flow_schedule(snapshot.flows, &exits)
+        .into_iter()
+        .map(|step| {
+            let exit = to_units(Flows {
+                base: step.base_exit,
+                quote: step.quote_exit,
+            });
+            let remaining = to_units(step.remaining);
+            let price = remaining.and_then(implied_price);
+            FlowScheduleItem {
+                slot: step.slot,
+                estimated_time: clock
+                    .time_of(step.slot)
+                    .to_rfc3339_opts(SecondsFormat::Secs, true),
+                base_exit: exit.map(|exit| exit.base),
+                quote_exit: exit.map(|exit| exit.quote),
+                base_flow: remaining.map(|flows| flows.base),
+                quote_flow: remaining.map(|flows| flows.quote),
+                price,
+                price_change_percent: price.zip(current_price).map(|(price, current)| {
+                    ((price - current) / current * Decimal::ONE_HUNDRED).round_dp(4)
+                }),
+            }
+        })
+        .collect();
+
+    Ok(Json(FlowScheduleResponse {
+        market_id,
+        slot: snapshot.slot,
+        snapshot_time: snapshot
+            .snapshot_time
+            .to_rfc3339_opts(SecondsFormat::Secs, true),
+        base_flow: current.map(|flows| flows.base),
+        quote_flow: current.map(|flows| flows.quote),
+        price: current_price,
+        points: items.len(),
+        items,
+    }))
+}
+
+fn decimal_scale(decimals: i16) -> Option<Decimal> {
+    let scale = u32::try_from(decimals).ok().filter(|scale| *scale <= 28)?;
+    Some(Decimal::from_i128_with_scale(10i128.pow(scale), 0))
+}
+
+/// Quote per base; `None` once no base flow is left.
+fn implied_price(flows: Flows) -> Option<Decimal> {
+    (!flows.base.is_zero()).then(|| (flows.quote / flows.base).round_dp(12).normalize())
+}
+
+/// Walk `exits` (ascending by slot) and the flows left after each. Flows that
+/// liquidity providers add never exit here, so the remainder is floored at
+/// zero rather than going negative on rounding.
+fn flow_schedule(current: Flows, exits: &[(u64, Flows)]) -> Vec<FlowStep> {
+    let mut remaining = current;
+    exits
+        .iter()
+        .map(|&(slot, exit)| {
+            remaining = Flows {
+                base: (remaining.base - exit.base).max(Decimal::ZERO),
+                quote: (remaining.quote - exit.quote).max(Decimal::ZERO),
+            };
+            FlowStep {
+                slot,
+                base_exit: exit.base,
+                quote_exit: exit.quote,
+                remaining,
+            }
+        })
+        .collect()
+}
+
+/// The market's latest state snapshot and the exits after its slot.
+async fn query_flow_schedule(
+    pool: &Pool,
+    market_id: i64,
+) -> Result<(Option<ScheduleRow>, Vec<(u64, Flows)>)> {
+    let client = pool.get().await.context("Failed to get DB connection")?;
+    let row = client
+        .query_opt(
+            "SELECT slot, snapshot_time, base_flow::text, quote_flow::text \
+             FROM market_state_snapshots \
+             WHERE market_id = $1 ORDER BY snapshot_time DESC LIMIT 1",
+            &[&market_id],
+        )
+        .await
+        .context("Failed to query market state")?;
+    let Some(row) = row else {
+        return Ok((None, Vec::new()));
+    };
+    // u128 flows can exceed `Decimal`; a schedule missing any of them would be
+    // wrong, so fail instead.
+    let slot: i64 = row.get("slot");
+    let snapshot = ScheduleRow {
+        slot: slot.max(0) as u64,
+        snapshot_time: row.get("snapshot_time"),
+        flows: Flows {
+            base: numeric_text(&row, "base_flow").context("Market base flow out of range")?,
+            quote: numeric_text(&row, "quote_flow").context("Market quote flow out of range")?,
+        },
+    };
+
+    let exits = client
+        .query(
+            "SELECT exit_slot, base_exit::text, quote_exit::text FROM market_exits \
+             WHERE market_id = $1 AND exit_slot > $2 \
+             ORDER BY exit_slot ASC",
+            &[&market_id, &(snapshot.slot as i64)],
+        )
+        .await
+        .context("Failed to query market exits")?
+        .iter()
+        .map(|row| {
+            let slot: i64 = row.get("exit_slot");
+            let flows = Flows {
+                base: numeric_text(row, "base_exit")?,
+                quote: numeric_text(row, "quote_exit")?,
+            };
+            Some((slot.max(0) as u64, flows))
+        })
+        .collect::<Option<_>>()
+        .context("Market exit out of range")?;
+
+    Ok((Some(snapshot), exits))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flows(base: i64, quote: i64) -> Flows {
+        Flows {
+            base: Decimal::from(base),
+            quote: Decimal::from(quote),
+        }
+    }
+
+    #[test]
+    fn test_flow_schedule_subtracts_exits_in_order() {
+        let steps = flow_schedule(
+            flows(100, 300),
+            &[
+                (10, flows(40, 0)),
+                (20, flows(0, 150)),
+                (30, flows(80, 200)),
+            ],
+        );
+
+        let remaining: Vec<Flows> = steps.iter().map(|step| step.remaining).collect();
+        assert_eq!(remaining, vec![flows(60, 300), flows(60, 150), flows(0, 0)]);
+        assert_eq!(implied_price(remaining[0]), Some(Decimal::from(5)));
+        assert_eq!(implied_price(remaining[1]), Some(Decimal::new(25, 1)));
+        assert_eq!(implied_price(remaining[2]), None);
+    }
+}
@@ -0,0 +1,423 @@
+//! Liquidity provider positions from the `liquidity_positions` table kept by
+//! `account-indexer`: `/v1/authorities/{authority}/liquidity-positions` and
+//! `/v1/markets/{market_id}/liquidity-positions`.
+//!
+//! A position continuously sells `base_flow` base and `quote_flow` quote per
+//! slot and receives the other token at the market price. Health projects the
+//! balances stored at `last_update_slot` to the estimated current slot (see
+//! [`slots`](crate::slots)) and reports how long they last at the current
+//! flows. A position whose balance runs out fails with
+//! `LiquidityPositionUnhealthy` and starts accruing debt.
+
+use anyhow::{Context, Result, anyhow};
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+};
+use chrono::{SecondsFormat, Utc};
+use deadpool_postgres::Pool;
+use rust_decimal::{Decimal, prelude::ToPrimitive};
+use std::{
+    collections::{HashMap, hash_map::Entry},
+    sync::Arc,
+};
+use twob_keepers::api::{
+    AuthorityLiquidityQuery, ErrorResponse, LiquidityHealth, LiquidityPositionItem,
+    LiquidityPositionsResponse, MarketLiquidityQuery,
+};
+
+use crate::{
+    ABSOLUTE_MAX_UPDATES_LIMIT, ApiError, AppState, DEFAULT_UPDATES_LIMIT,
+    fetch_latest_price_snapshot, numeric_text, query_market_configs,
+    slots::{SLOT_DURATION_MS, SlotClock, query_slot_clock},
+};
+
+/// Positions that run dry within this many slots (about an hour) are
+/// reported as `at_risk`.
+const AT_RISK_HORIZON_SLOTS: u64 = 9_000;
+
+/// Balances and flows of one position, in token units.
+#[derive(Clone, Copy, Debug)]
+struct LiquidityBalances {
+    base_balance: Decimal,
+    quote_balance: Decimal,
+    base_flow: Decimal,
+    quote_flow: Decimal,
+    base_debt: Decimal,
+    quote_debt: Decimal,
+}
+
+struct LiquidityPositionRow {
+    address: String,
+    market_id: i64,
+    authority: String,
+    base_balance: Option<Decimal>,
+    quote_balance: Option<Decimal>,
+    base_flow: Option<Decimal>,
+    quote_flow: Option<Decimal>,
+    base_debt: Option<Decimal>,
+    quote_debt: Option<Decimal>,
+    last_update_slot: i64,
+    observed_slot: i64,
+}
+
+impl LiquidityPositionRow {
+    fn balances(&self) -> Option<LiquidityBalances> {
+        Some(LiquidityBalances {
+            base_balance: self.base_balance?,
+            quote_balance: self.quote_balance?,
+            base_flow: self.base_flow?,
+            quote_flow: self.quote_flow?,
+            base_debt: self.base_debt?,
+            quote_debt: self.quote_debt?,
+        })
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/authorities/{authority}/liquidity-positions",
+    params(
+        ("authority" = String, Path, description = "Wallet address"),
+        AuthorityLiquidityQuery,
+    ),
+    responses(
+        (status = 200, body = LiquidityPositionsResponse),
+        (status = 400, body = ErrorResponse),
+    )
+)]
+pub(crate) async fn get_authority_liquidity_positions(
+    State(state): State<Arc<AppState>>,
+    Path(authority): Path<String>,
+    Query(query): Query<AuthorityLiquidityQuery>,
+) -> Result<Json<LiquidityPositionsResponse>, ApiError> {
+    let market_id = query
+        .market_id
+        .map(i64::try_from)
+        .transpose()
+        .map_err(|_| ApiError::bad_request("market_id out of range"))?;
+
+    // One position per market and authority, so no paging is needed.
+    let response = liquidity_positions_response(&state, Some(&authority), market_id, None).await?;
+    Ok(Json(LiquidityPositionsResponse {
+        authority: Some(authority),
+        market_id: query.market_id,
+        ..response
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/markets/{market_id}/liquidity-positions",
+    params(("market_id" = u64, Path, description = "Market id"), MarketLiquidityQuery),
+    responses(
+        (status = 200, body = LiquidityPositionsResponse),
+        (status = 400, body = ErrorResponse),
+        (status = 404, body = ErrorResponse),
+    )
+)]
+pub(crate) async fn get_market_liquidity_positions(
+    State(state): State<Arc<AppState>>,
+    Path(market_id): Path<u64>,
+    Query(query): Query<MarketLiquidityQuery>,
+) -> Result<Json<LiquidityPositionsResponse>, ApiError> {
+    let limit = query.limit.unwrap_or(DEFAULT_UPDATES_LIMIT);
+    if limit == 0 || limit > ABSOLUTE_MAX_UPDATES_LIMIT {
+        return Err(ApiError::bad_request(format!(
+            "limit must be between 1 and {ABSOLUTE_MAX_UPDATES_LIMIT}"
+        )));
+    }
+    let configs = query_market_configs(&state.pool, Some(market_id))
+        .await
+        .map_err(|error| ApiError::internal(error.context("Failed to query market config")))?;
+    if configs.is_empty() {
+        return Err(ApiError::not_found(format!(
+            "No config for market_id={market_id}"
+        )));
+    }
+    let market_id_i64 =
+        i64::try_from(market_id).map_err(|_| ApiError::bad_request("market_id out of range"))?;
+
+    let response =
+        liquidity_positions_response(&state, None, Some(market_id_i64), Some(limit)).await?;
+    Ok(Json(LiquidityPositionsResponse {
+        market_id: Some(market_id),
+        ..response
+    }))
+}
+
+async fn liquidity_positions_response(
+    state: &AppState,
+    authority: Option<&str>,
+    market_id: Option<i64>,
+    limit: Option<usize>,
+) -> Result<LiquidityPositionsResponse, ApiError> {
+    let mut rows = query_liquidity_positions(
+        &state.pool,
+        authority,
+        market_id,
+        limit.map(|limit| limit as i64 + 1),
+        state.config.bookkeeping_precision_factor,
+    )
+    .await
+    .map_err(|error| ApiError::internal(error.context("Failed to query liquidity positions")))?;
+    let has_more = limit.is_some_and(|limit| rows.len() > limit);
+    if let Some(limit) = limit {
+        rows.truncate(limit);
+    }
+
+    let clock = query_slot_clock(&state.pool, &state.config.market_updates_table)
+        .await
+        .map_err(|error| ApiError::internal(error.context("Failed to estimate current slot")))?;
+    let current_slot = clock.map(|clock| clock.slot_at(Utc::now()));
+
+    let mut prices = HashMap::new();
+    for row in &rows {
+        if let Entry::Vacant(entry) = prices.entry(row.market_id) {
+            let market_id = u64::try_from(row.market_id).unwrap_or_default();
+            let price = fetch_latest_price_snapshot(
+                &state.pool,
+                &state.config.market_updates_table,
+                market_id,
+            )
+            .await
+            .map_err(|error| ApiError::internal(error.context("Failed to query latest price")))?
+            .map(|snapshot| snapshot.price);
+            entry.insert(price);
+        }
+    }
+
+    let items = rows
+        .into_iter()
+        .map(|row| {
+            let price = prices.get(&row.market_id).copied().flatten();
+            liquidity_position_item(row, price, clock.zip(current_slot))
+        })
+        .collect::<Result<Vec<_>>>()
+        .map_err(|error| ApiError::internal(error.context("Invalid liquidity position row")))?;
+
+    Ok(LiquidityPositionsResponse {
+        authority: None,
+        market_id: None,
+        current_slot,
+        has_more,
+        points: items.len(),
+        items,
+    })
+}
+
+fn liquidity_position_item(
+    row: LiquidityPositionRow,
+    price: Option<Decimal>,
+    now: Option<(SlotClock, u64)>,
+) -> Result<LiquidityPositionItem> {
+    let market_id = u64::try_from(row.market_id)
+        .map_err(|_| anyhow!("market_id out of range: {}", row.market_id))?;
+    let last_update_slot = row.last_update_slot.max(0) as u64;
+    let health = row
+        .balances()
+        .zip(now)
+        .map(|(balances, (clock, current_slot))| {
+            let mut health = liquidity_health(
+                &balances,
+                price,
+                current_slot.saturating_sub(last_update_slot),
+            );
+            health.estimated_depletion_time = health.slots_until_depleted.map(|slots| {
+                clock
+                    .time_of(current_slot.saturating_add(slots))
+                    .to_rfc3339_opts(SecondsFormat::Secs, true)
+            });
+            health
+        });
+
+    Ok(LiquidityPositionItem {
+        address: row.address,
+        market_id,
+        authority: row.authority,
+        base_balance: row.base_balance.map(|value| value.normalize()),
+        quote_balance: row.quote_balance.map(|value| value.normalize()),
+        base_flow: row.base_flow.map(|value| value.normalize()),
+        quote_flow: row.quote_flow.map(|value| value.normalize()),
+        base_debt: row.base_debt.map(|value| value.normalize()),
+        quote_debt: row.quote_debt.map(|value| value.normalize()),
+        last_update_slot,
+        observed_slot: row.observed_slot.max(0) as u64,
+        market_price: price,
+        health,
+    })
+}
+
+/// Project a position `elapsed_slots` past its last update.
+///
+/// Without a market price only the outgoing flows are counted, which
+/// understates how long the balances last.
+fn liquidity_health(
+    balances: &LiquidityBalances,
+    price: Option<Decimal>,
+    elapsed_slots: u64,
+) -> LiquidityHealth {
+    let price = price.filter(|price| price.is_sign_positive() && !price.is_zero());
+    let net_base_per_slot =
+        price.map_or(Decimal::ZERO, |price| balances.quote_flow / price) - balances.base_flow;
+    let net_quote_per_slot =
+        price.map_or(Decimal::ZERO, |price| balances.base_flow * price) - balances.quote_flow;
+
+    let elapsed = Decimal::from(elapsed_slots);
+    let projected_base_balance = balances.base_balance + net_base_per_slot * elapsed;
+    let projected_quote_balance = balances.quote_balance + net_quote_per_slot * elapsed;
+
+    let runway = |balance: Decimal, net: Decimal| {
+        (net.is_sign_negative() && !net.is_zero()).then(|| {
+            (balance.max(Decimal::ZERO) / -net)
+                .floor()
+                .to_u64()
+                .unwrap_or(u64::MAX)
+        })
+    };
+    let depletion = [
+        ("base", runway(projected_base_balance, net_base_per_slot)),
+        ("quote", runway(projected_quote_balance, net_quote_per_slot)),
+    ]
+    .into_iter()
+    .filter_map(|(side, slots)| slots.map(|slots| (side, slots)))
+    .min_by_key(|(_, slots)| *slots);
+    let slots_until_depleted = depletion.map(|(_, slots)| slots);
+
+    let status = if !balances.base_debt.is_zero() || !balances.quote_debt.is_zero() {
+        "in_debt"
+    } else if slots_until_depleted == Some(0) {
+        "unhealthy"
+    } else if slots_until_depleted.is_some_and(|slots| slots < AT_RISK_HORIZON_SLOTS) {
+        "at_risk"
+    } else if balances.base_flow.is_zero() && balances.quote_flow.is_zero() {
+        "idle"
+    } else {
+        "healthy"
+    };
+
+    LiquidityHealth {
+        status: status.to_string(),
+        projected_base_balance: projected_base_balance.max(Decimal::ZERO).normalize(),
+        projected_quote_balance: projected_quote_balance.max(Decimal::ZERO).normalize(),
+        net_base_per_slot: net_base_per_slot.normalize(),
+        net_quote_per_slot: net_quote_per_slot.normalize(),
+        depleting: depletion.map(|(side, _)| side.to_string()),
+        slots_until_depleted,
+        seconds_until_depleted: slots_until_depleted
+            .map(|slots| slots.saturating_mul(SLOT_DURATION_MS as u64) / 1000),
+        estimated_depletion_time: None,
+    }
+}
+
+async fn query_liquidity_positions(
+    pool: &Pool,
+    authority: Option<&str>,
+    market_id: Option<i64>,
+    limit: Option<i64>,
+    precision_factor: u128,
+) -> Result<Vec<LiquidityPositionRow>> {
+    let client = pool.get().await.context("Failed to get DB connection")?;
+    let rows = client
+        .query(
+            "SELECT lp.address, lp.market_id, lp.authority, lp.last_update_slot, \
+                    lp.observed_slot, \
+                    round(lp.base_balance / $4::text::numeric \
+                          / power(10::numeric, mc.base_decimals::numeric), 12)::text AS base_balance, \
+                    round(lp.quote_balance / $4::text::numeric \
+                          / power(10::numeric, mc.quote_decimals::numeric), 12)::text AS quote_balance, \
+                    (lp.base_flow / power(10::numeric, mc.base_decimals::numeric))::text AS base_flow, \
+                    (lp.quote_flow / power(10::numeric, mc.quote_decimals::numeric))::text AS quote_flow, \
+                    (lp.base_debt / power(10::numeric, mc.base_decimals::numeric))::text AS base_debt, \
+                    (lp.quote_debt / power(10::numeric, mc.quote_decimals::numeric))::text AS quote_debt \
+             FROM liquidity_positions lp \
+             LEFT JOIN market_configs mc ON mc.market_id = lp.market_id \
+             WHERE ($1::text IS NULL OR lp.authority = $1) \
+               AND ($2::bigint IS NULL OR lp.market_id = $2) \
+             ORDER BY lp.market_id ASC, lp.address ASC \
+             LIMIT $3",
+            &[
+                &authority,
+                &market_id,
+                &limit,
+                &precision_factor.to_string(),
+            ],
+        )
+        .await
+        .context("Failed to query liquidity positions")?;
+
+    // u128 amounts can exceed `Decimal`; those are served as `null` so the
+    // position still counts towards the page.
+    Ok(rows
+        .iter()
+        .map(|row| LiquidityPositionRow {
+            address: row.get("address"),
+            market_id: row.get("market_id"),
+            authority: row.get("authority"),
+            base_balance: numeric_text(row, "base_balance"),
+            quote_balance: numeric_text(row, "quote_balance"),
+            base_flow: numeric_text(row, "base_flow"),
+            quote_flow: numeric_text(row, "quote_flow"),
+            base_debt: numeric_text(row, "base_debt"),
+            quote_debt: numeric_text(row, "quote_debt"),
+            last_update_slot: row.get("last_update_slot"),
+            observed_slot: row.get("observed_slot"),
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn balances(
+        base_balance: i64,
+        quote_balance: i64,
+        base_flow: i64,
+        quote_flow: i64,
+    ) -> LiquidityBalances {
+        LiquidityBalances {
+            base_balance: Decimal::from(base_balance),
+            quote_balance: Decimal::from(quote_balance),
+            base_flow: Decimal::from(base_flow),
+            quote_flow: Decimal::from(quote_flow),
+            base_debt: Decimal::ZERO,
+            quote_debt: Decimal::ZERO,
+        }
+    }
+
+    #[test]
+    fn test_health_nets_incoming_flow_at_market_price() {
+        // Sells 10 base (worth 20 quote) and 5 quote per slot: base drains by
+        // 10 - 2.5 = 7.5 per slot, quote grows by 15.
+        let health = liquidity_health(&balances(100_000, 1_000, 10, 5), Some(Decimal::TWO), 100);
+        assert_eq!(health.net_base_per_slot, Decimal::new(-75, 1));
+        assert_eq!(health.net_quote_per_slot, Decimal::from(15));
+        assert_eq!(health.projected_base_balance, Decimal::from(99_250));
+        assert_eq!(health.projected_quote_balance, Decimal::from(2_500));
+        assert_eq!(health.depleting.as_deref(), Some("base"));
+        assert_eq!(health.slots_until_depleted, Some(13_233));
+        assert_eq!(health.status, "healthy");
+    }
+
+    #[test]
+    fn test_health_statuses() {
+        let at_risk = liquidity_health(&balances(1_000, 1_000, 1, 0), None, 0);
+        assert_eq!(at_risk.status, "at_risk");
+        assert_eq!(at_risk.slots_until_depleted, Some(1_000));
+        assert_eq!(at_risk.seconds_until_depleted, Some(400));
+
+        let unhealthy = liquidity_health(&balances(1_000, 1_000, 1, 0), None, 5_000);
+        assert_eq!(unhealthy.status, "unhealthy");
+        assert_eq!(unhealthy.projected_base_balance, Decimal::ZERO);
+        assert_eq!(unhealthy.slots_until_depleted, Some(0));
+
+        let idle = liquidity_health(&balances(1_000, 1_000, 0, 0), None, 5_000);
+        assert_eq!(idle.status, "idle");
+        assert_eq!(idle.depleting, None);
+
+        let mut indebted = balances(1_000, 1_000, 0, 0);
+        indebted.quote_debt = Decimal::ONE;
+        assert_eq!(liquidity_health(&indebted, None, 0).status, "in_debt");
+    }
+}
@@ -1,62 +1,144 @@
 use anyhow::{Context, Result, anyhow};
 use axum::{
-    Json, Router,
+    Extension, Json, Router,
     extract::{Path, Query, State},
-    http::{StatusCode, header},
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    middleware,
     response::{
         IntoResponse, Response,
         sse::{Event, KeepAlive, Sse},
     },
     routing::get,
 };
+use base64::{Engine as _, engine::general_purpose::URL_SAFE_NO_PAD};
 use chrono::{DateTime, SecondsFormat, Utc};
 use deadpool_postgres::Pool;
-use futures_util::{Stream, stream};
+use futures_util::{Stream, StreamExt, stream, stream::BoxStream};
 use rust_decimal::Decimal;
 use rust_decimal::prelude::ToPrimitive;
 use serde::{Deserialize, Serialize};
-use std::{
-    collections::HashMap, convert::Infallible, env, net::SocketAddr, sync::Arc, time::Duration,
-};
-use tokio::{
-    sync::{RwLock, broadcast},
-    time::MissedTickBehavior,
+use std::{convert::Infallible, env, net::SocketAddr, sync::Arc, time::Duration};
+use tokio::sync::broadcast;
+use tokio_postgres::types::ToSql;
+use twob_keepers::{
+    BOOKKEEPING_PRECISION_FACTOR, StorageBackend,
+    api::{
+        CandleItem, CandleQuery, CandleResponse, ClosedPositionItem, ClosedPositionMiniChartItem,
+        ClosedPositionMiniChartQuery, ClosedPositionMiniChartResponse, ClosedPositionsQuery,
+        ClosedPositionsResponse, ErrorResponse, HealthResponse, LatestPriceResponse, MarketConfig,
+        MarketConfigListResponse, MarketHistoryItem, MarketHistoryQuery, MarketHistoryResponse,
+        MarketStateItem, MarketStateQuery, MarketStateResponse, MarketUpdatesQuery,
+        MarketUpdatesResponse, StorageStatusResponse,
+    },
+    candles::{GapfilledCandle, query_gapfilled_candles},
+    database::{connect_pool, validate_table_name},
+    policies::storage_stats,
 };
-use tower_http::cors::CorsLayer;
-use twob_keepers::database::connect_pool;
+
+mod access;
+mod aggregator;
+mod execution;
+mod export;
+mod fees;
+mod flow_schedule;
+mod liquidity;
+mod open_positions;
+mod openapi;
+mod portfolio;
+mod readiness;
+mod response_cache;
+mod slots;
+mod streams;
+mod tickers;
+mod ws;
+
+use access::{AccessConfig, AccessControl, StreamPermit};
+use export::{ExportFormat, export_response};
+use response_cache::ResponseCache;
+use streams::MarketStreams;
+use tickers::TickerCache;
 
 const DEFAULT_MARKET_UPDATES_TABLE: &str = "raw_market_update_events";
 const DEFAULT_CANDLES_1M_TABLE: &str = "market_candles_1m";
+const DEFAULT_CANDLES_1H_TABLE: &str = "market_candles_1h";
 const DEFAULT_CLOSE_POSITION_EVENTS_TABLE: &str = "raw_close_position_events";
 const DEFAULT_BIND_ADDR: &str = "0.0.0.0:8080";
 const DEFAULT_MAX_POINTS: usize = 1500;
 const ABSOLUTE_MAX_POINTS: usize = 5000;
 const DEFAULT_HISTORY_MAX_ROWS: usize = 25_000;
 const ABSOLUTE_MAX_HISTORY_ROWS: usize = 200_000;
+/// Response header carrying the `/history` cursor on exports.
+const NEXT_CURSOR_HEADER: &str = "x-next-cursor";
+/// Response header carrying `has_more` on `/updates` and `/closed-positions`
+/// exports.
+const HAS_MORE_HEADER: &str = "x-has-more";
 const DEFAULT_CLOSED_POSITION_MINI_CHART_POINTS: usize = 240;
 const ABSOLUTE_MAX_CLOSED_POSITION_MINI_CHART_POINTS: usize = 2_000;
 const DEFAULT_UPDATES_LIMIT: usize = 200;
 const ABSOLUTE_MAX_UPDATES_LIMIT: usize = 5000;
 const DEFAULT_PRICE_STREAM_POLL_MS: u64 = 1000;
+const DEFAULT_PRICE_STREAM_FALLBACK_POLL_MS: u64 = 30_000;
+const DEFAULT_STREAM_IDLE_GRACE_SECS: u64 = 60;
+const DEFAULT_RESPONSE_CACHE_TTL_MS: u64 = 2_000;
+const DEFAULT_READY_TIMEOUT_MS: u64 = 2_000;
+/// Off: a stalled keeper would take every instance out of rotation at once.
+const DEFAULT_READY_MAX_UPDATE_AGE_SECS: u64 = 0;
+/// Candle ranges that ended this long ago no longer change and are served as
+/// immutable: past account-indexer's 7-day candle backfill and the rollup's
+/// 3-day refresh window.
+const SETTLED_CANDLE_RANGE_AGE_SECS: i64 = 8 * 24 * 60 * 60;
+const IMMUTABLE_CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+/// Ended candle ranges that a backfill may still rewrite.
+const ENDED_CANDLE_RANGE_CACHE_CONTROL: &str = "public, max-age=60";
+/// Most events replayed to a stream resuming with `Last-Event-ID`; a longer
+/// backlog gets a `reset` event instead.
+const STREAM_REPLAY_LIMIT: i64 = 1000;
+/// How far back a resuming stream is replayed; older clients should reload.
+const STREAM_REPLAY_MAX_AGE_SECS: i64 = 60 * 60;
+const DEFAULT_STATE_HISTORY_LIMIT: usize = 500;
+const ABSOLUTE_MAX_STATE_HISTORY_LIMIT: usize = 5000;
+const DEFAULT_STATE_HISTORY_WINDOW_SECS: i64 = 24 * 60 * 60;
 /// Market configs change extremely rarely, so allow clients/CDNs to cache them.
 const MARKET_CONFIG_CACHE_CONTROL: &str = "public, max-age=300, stale-while-revalidate=60";
 const POOL_MAX_SIZE: usize = 16;
-const MAX_LIGHTWEIGHT_CHART_ABS_VALUE: f64 = 90_071_992_547_409.91;
+const LAST_EVENT_ID: &str = "last-event-id";
+const MAX_LIGHTWEIGHT_CHART_ABS_VALUE: f64 = 90_071_992_547_409.9;
 
 #[derive(Clone)]
 struct AppState {
     pool: Pool,
     config: ReadApiConfig,
-    market_price_streams: MarketPriceStreams,
+    market_streams: MarketStreams,
+    tickers: TickerCache,
+    access: Arc<AccessControl>,
+    response_cache: ResponseCache,
 }
 
 #[derive(Clone, Debug)]
 struct ReadApiConfig {
     bind_addr: SocketAddr,
+    storage_backend: StorageBackend,
     market_updates_table: String,
     candles_1m_table: String,
+    /// Hourly continuous aggregate (see `db-admin apply-policies`), used for
+    /// intervals of 1h and above when `CANDLES_1H_ROLLUP` is enabled.
+    candles_1h_table: Option<String>,
     close_position_events_table: String,
+    /// Database URL for the price-stream `LISTEN` connection; `None` when
+    /// `READ_API_PRICE_STREAM_LISTEN` is off and streams poll instead.
+    price_stream_listen_url: Option<String>,
     price_stream_poll_interval: Duration,
+    /// How long a live stream keeps running after its last subscriber leaves.
+    stream_idle_grace: Duration,
+    /// Lifetime of cached `/price`, `/candles` and `/updates` responses.
+    response_cache_ttl: Duration,
+    /// `/readyz` fails when its checks take longer than this.
+    ready_timeout: Duration,
+    /// `/readyz` fails once every market has gone this long without an
+    /// update; `0` disables the check.
+    ready_max_update_age_secs: u64,
+    /// Scale of on-chain liquidity position balances.
+    bookkeeping_precision_factor: u128,
 }
 
 impl ReadApiConfig {
@@ -75,28 +157,103 @@ impl ReadApiConfig {
         let candles_1m_table = validate_table_name(
             &env::var("CANDLES_1M_TABLE").unwrap_or_else(|_| DEFAULT_CANDLES_1M_TABLE.to_string()),
         )?;
+        let storage_backend = StorageBackend::from_env()?;
+        let candles_1h_rollup = parse_bool_env("CANDLES_1H_ROLLUP", false)?;
+        if candles_1h_rollup && storage_backend == StorageBackend::Postgres {
+            return Err(anyhow!(
+                "CANDLES_1H_ROLLUP needs TimescaleDB and cannot be used with STORAGE_BACKEND=postgres"
+            ));
+        }
+        let candles_1h_table = if candles_1h_rollup {
+            Some(validate_table_name(
+                &env::var("CANDLES_1H_TABLE")
+                    .unwrap_or_else(|_| DEFAULT_CANDLES_1H_TABLE.to_string()),
+            )?)
+        } else {
+            None
+        };
         let close_position_events_table = validate_table_name(
             &env::var("CLOSE_POSITION_EVENTS_TABLE")
                 .unwrap_or_else(|_| DEFAULT_CLOSE_POSITION_EVENTS_TABLE.to_string()),
         )?;
-        let price_stream_poll_interval = Duration::from_millis(parse_u64_env(
-            "READ_API_PRICE_STREAM_POLL_MS",
-            DEFAULT_PRICE_STREAM_POLL_MS,
+        let price_stream_listen = parse_bool_env("READ_API_PRICE_STREAM_LISTEN", true)?;
+        // With LISTEN the poll only catches notifications lost to a reconnect.
+        let price_stream_poll_interval = Duration::from_millis(if price_stream_listen {
+            parse_u64_env(
+                "READ_API_PRICE_STREAM_FALLBACK_POLL_MS",
+                DEFAULT_PRICE_STREAM_FALLBACK_POLL_MS,
+            )?
+        } else {
+            parse_u64_env(
+                "READ_API_PRICE_STREAM_POLL_MS",
+                DEFAULT_PRICE_STREAM_POLL_MS,
+            )?
+        });
+        let price_stream_listen_url = price_stream_listen.then(|| database_url.clone());
+        let stream_idle_grace = Duration::from_secs(parse_u64_env(
+            "READ_API_STREAM_IDLE_GRACE_SECS",
+            DEFAULT_STREAM_IDLE_GRACE_SECS,
         )?);
 
+        let response_cache_ttl = Duration::from_millis(parse_u64_env(
+            "READ_API_RESPONSE_CACHE_TTL_MS",
+            DEFAULT_RESPONSE_CACHE_TTL_MS,
+        )?);
+
+        let ready_timeout = Duration::from_millis(parse_u64_env(
+            "READ_API_READY_TIMEOUT_MS",
+            DEFAULT_READY_TIMEOUT_MS,
+        )?);
+        let ready_max_update_age_secs = parse_u64_env(
+            "READ_API_READY_MAX_UPDATE_AGE_SECS",
+            DEFAULT_READY_MAX_UPDATE_AGE_SECS,
+        )?;
+
+        let bookkeeping_precision_factor = match env::var("BOOKKEEPING_PRECISION_FACTOR") {
+            Ok(raw) => raw
+                .parse::<u128>()
+                .ok()
+                .filter(|factor| *factor > 0)
+                .ok_or_else(|| {
+                    anyhow!("BOOKKEEPING_PRECISION_FACTOR must be a positive integer")
+                })?,
+            Err(env::VarError::NotPresent) => BOOKKEEPING_PRECISION_FACTOR,
+            Err(error) => {
+                return Err(anyhow!(
+                    "Failed to read BOOKKEEPING_PRECISION_FACTOR: {error}"
+                ));
+            }
+        };
+
         let pool = connect_pool(&database_url, POOL_MAX_SIZE)?;
 
         Ok((
             Self {
                 bind_addr,
+                storage_backend,
                 market_updates_table,
                 candles_1m_table,
+                candles_1h_table,
                 close_position_events_table,
+                price_stream_listen_url,
                 price_stream_poll_interval,
+                stream_idle_grace,
+                response_cache_ttl,
+                ready_timeout,
+                ready_max_update_age_secs,
+                bookkeeping_precision_factor,
             },
             pool,
         ))
     }
+
+    fn candle_source(&self) -> CandleSource {
+        CandleSource {
+            storage_backend: self.storage_backend,
+            candles_1m_table: self.candles_1m_table.clone(),
+            candles_1h_table: self.candles_1h_table.clone(),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -120,6 +277,13 @@ impl ApiError {
         }
     }
 
+    fn unauthorized(message: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::UNAUTHORIZED,
+            message: message.into(),
+        }
+    }
+
     fn internal(error: anyhow::Error) -> Self {
         Self {
             status: StatusCode::INTERNAL_SERVER_ERROR,
@@ -140,156 +304,20 @@ impl IntoResponse for ApiError {
     }
 }
 
-#[derive(Serialize)]
-struct ErrorResponse {
-    error: String,
-}
-
-#[derive(Serialize)]
-struct HealthResponse {
-    status: &'static str,
-}
-
-#[derive(Clone, Serialize)]
-struct LatestPriceResponse {
-    market_id: u64,
-    slot: u64,
-    event_time: String,
-    #[serde(skip_serializing)]
-    event_time_ms: i64,
-    price: Decimal,
-}
-
 #[derive(Deserialize)]
-struct CandleQuery {
-    from: DateTime<Utc>,
-    to: DateTime<Utc>,
+struct PriceStreamQuery {
+    /// Also emit `candle_update` events for this candle interval.
     interval: Option<String>,
-    max_points: Option<usize>,
-}
-
-#[derive(Deserialize)]
-struct MarketHistoryQuery {
-    start_slot: u64,
-    end_slot: u64,
-    max_rows: Option<usize>,
-}
-
-#[derive(Deserialize)]
-struct MarketUpdatesQuery {
-    before_slot: Option<u64>,
-    limit: Option<usize>,
-}
-
-#[derive(Deserialize)]
-struct ClosedPositionMiniChartQuery {
-    start_slot: u64,
-    end_slot: u64,
-    max_points: Option<usize>,
 }
 
+/// `candle_update` SSE payload: the current bucket of `interval`, shaped like
+/// a `/candles` item.
 #[derive(Serialize)]
-struct CandleResponse {
+struct CandleUpdateEvent<'a> {
     market_id: u64,
-    interval: String,
-    from: String,
-    to: String,
-    points: usize,
-    items: Vec<CandleItem>,
-}
-
-#[derive(Serialize)]
-struct CandleItem {
-    time: u64,
-    open: Decimal,
-    high: Decimal,
-    low: Decimal,
-    close: Decimal,
-}
-
-#[derive(Serialize)]
-struct MarketHistoryResponse {
-    market_id: u64,
-    start_slot: u64,
-    end_slot: u64,
-    points: usize,
-    items: Vec<MarketHistoryItem>,
-}
-
-#[derive(Serialize)]
-struct MarketUpdatesResponse {
-    market_id: u64,
-    before_slot: Option<u64>,
-    has_more: bool,
-    limit: usize,
-    points: usize,
-    items: Vec<MarketHistoryItem>,
-}
-
-#[derive(Serialize)]
-struct ClosedPositionMiniChartResponse {
-    market_id: u64,
-    start_slot: u64,
-    end_slot: u64,
-    points: usize,
-    items: Vec<ClosedPositionMiniChartItem>,
-}
-
-#[derive(Serialize)]
-struct ClosedPositionMiniChartItem {
-    slot: u64,
-    price: Decimal,
-}
-
-#[derive(Clone, Serialize)]
-struct MarketConfig {
-    market_id: u64,
-    base_mint: Option<String>,
-    quote_mint: Option<String>,
-    base_decimals: Option<i16>,
-    quote_decimals: Option<i16>,
-    base_ticker: Option<String>,
-    quote_ticker: Option<String>,
-}
-
-#[derive(Serialize)]
-struct MarketConfigListResponse {
-    points: usize,
-    items: Vec<MarketConfig>,
-}
-
-#[derive(Deserialize)]
-struct ClosedPositionsQuery {
-    market_id: Option<u64>,
-    before_slot: Option<u64>,
-    limit: Option<usize>,
-}
-
-#[derive(Serialize)]
-struct ClosedPositionsResponse {
-    authority: String,
-    market_id: Option<u64>,
-    before_slot: Option<u64>,
-    has_more: bool,
-    limit: usize,
-    points: usize,
-    items: Vec<ClosedPositionItem>,
-}
-
-#[derive(Serialize)]
-struct ClosedPositionItem {
-    signature: String,
-    event_index: u16,
-    slot: u64,
-    market_id: u64,
-    start_slot: u64,
-    end_slot: u64,
-    deposit_amount: String,
-    swapped_amount: String,
-    remaining_amount: String,
-    fee_amount: String,
-    is_buy: bool,
-    event_time: String,
+    interval: &'static str,
+    #[serde(flatten)]
+    candle: &'a CandleItem,
 }
 
 struct ClosedPositionRow {
@@ -307,18 +335,6 @@ struct ClosedPositionRow {
     event_time_ms: i64,
 }
 
-#[derive(Serialize)]
-struct MarketHistoryItem {
-    event_uid: String,
-    signature: String,
-    event_index: u16,
-    slot: u64,
-    market_id: u64,
-    base_flow: String,
-    quote_flow: String,
-    created_at: String,
-}
-
 struct MarketHistoryRow {
     event_uid: String,
     signature: String,
@@ -330,7 +346,7 @@ struct MarketHistoryRow {
     event_time_ms: i64,
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 enum CandleInterval {
     M1,
     M5,
@@ -340,19 +356,6 @@ enum CandleInterval {
     D1,
 }
 
-#[derive(Clone)]
-struct MarketPriceStreams {
-    channels: Arc<RwLock<HashMap<u64, broadcast::Sender<LatestPriceResponse>>>>,
-    runtime: PriceStreamRuntime,
-    poll_interval: Duration,
-}
-
-#[derive(Clone)]
-struct PriceStreamRuntime {
-    pool: Pool,
-    market_updates_table: String,
-}
-
 impl CandleInterval {
     fn parse(raw: Option<&str>) -> Result<Self> {
         match raw.unwrap_or("1m").trim() {
@@ -391,6 +394,20 @@ impl CandleInterval {
         }
     }
 
+    const ALL: [Self; 6] = [Self::M1, Self::M5, Self::M15, Self::H1, Self::H4, Self::D1];
+
+    /// Bit used to track subscribed intervals in a `u8` mask.
+    fn bit(self) -> u8 {
+        1 << (self as u8)
+    }
+
+    fn from_bits(bits: u8) -> Vec<Self> {
+        Self::ALL
+            .into_iter()
+            .filter(|interval| bits & interval.bit() != 0)
+            .collect()
+    }
+
     fn step_seconds(self) -> i64 {
         match self {
             Self::M1 => 60,
@@ -408,11 +425,21 @@ async fn main() -> Result<()> {
     dotenv::dotenv().ok();
 
     let (config, pool) = ReadApiConfig::from_env()?;
-    let market_price_streams = MarketPriceStreams::new(
+    let access_config = AccessConfig::from_env()?;
+    let cors = access_config.cors_layer();
+    let response_cache = ResponseCache::new(config.response_cache_ttl);
+    let market_streams = MarketStreams::new(
         pool.clone(),
         config.market_updates_table.clone(),
+        config.candle_source(),
+        config.close_position_events_table.clone(),
         config.price_stream_poll_interval,
+        config.stream_idle_grace,
+        response_cache.clone(),
     );
+    if let Some(database_url) = &config.price_stream_listen_url {
+        market_streams.spawn_listener(database_url.clone());
+    }
 
     {
         let client = pool
@@ -425,50 +452,173 @@ async fn main() -> Result<()> {
             .context("Failed to verify Tiger Cloud connection")?;
         ensure_table_exists(&client, &config.market_updates_table).await?;
         ensure_table_exists(&client, &config.candles_1m_table).await?;
+        if let Some(candles_1h_table) = &config.candles_1h_table {
+            ensure_table_exists(&client, candles_1h_table).await?;
+        }
         ensure_table_exists(&client, &config.close_position_events_table).await?;
     }
 
     let state = Arc::new(AppState {
         pool,
         config: config.clone(),
-        market_price_streams,
+        market_streams,
+        tickers: TickerCache::default(),
+        access: Arc::new(AccessControl::new(access_config)),
+        response_cache,
     });
+    let cached = || middleware::from_fn_with_state(state.clone(), response_cache::cached);
 
     let app = Router::new()
         .route("/healthz", get(healthz))
+        .route("/readyz", get(readiness::readyz))
+        .route("/openapi.json", get(openapi::get_openapi))
+        .route("/v1/status/storage", get(get_storage_status))
+        .route("/v1/time/slot", get(slots::get_slot_time))
+        .route("/v1/time/at", get(slots::get_time_slot))
         .route("/v1/markets", get(list_market_configs))
         .route("/v1/markets/{market_id}/config", get(get_market_config))
         .route(
             "/v1/authorities/{authority}/closed-positions",
             get(get_closed_positions),
         )
-        .route("/v1/markets/{market_id}/price", get(get_latest_price))
+        .route(
+            "/v1/authorities/{authority}/open-positions",
+            get(open_positions::get_authority_open_positions),
+        )
+        .route(
+            "/v1/authorities/{authority}/liquidity-positions",
+            get(liquidity::get_authority_liquidity_positions),
+        )
+        .route(
+            "/v1/authorities/{authority}/summary",
+            get(portfolio::get_authority_summary),
+        )
+        .route(
+            "/v1/authorities/{authority}/execution",
+            get(execution::get_authority_execution),
+        )
+        .route(
+            "/v1/markets/{market_id}/execution-quality",
+            get(execution::get_market_execution),
+        )
+        .route(
+            "/v1/markets/{market_id}/price",
+            get(get_latest_price).layer(cached()),
+        )
         .route("/v1/markets/{market_id}/stream", get(stream_market_price))
-        .route("/v1/markets/{market_id}/candles", get(get_candles))
+        .route("/v1/ws", get(ws::market_socket))
+        .route(
+            "/v1/markets/{market_id}/candles",
+            get(get_candles).layer(cached()),
+        )
         .route("/v1/markets/{market_id}/history", get(get_market_history))
         .route(
             "/v1/markets/{market_id}/closed-position-mini-chart",
             get(get_closed_position_mini_chart),
         )
-        .route("/v1/markets/{market_id}/updates", get(get_market_updates))
-        .layer(CorsLayer::permissive())
+        .route(
+            "/v1/markets/{market_id}/open-positions",
+            get(open_positions::get_market_open_positions),
+        )
+        .route(
+            "/v1/markets/{market_id}/liquidity-positions",
+            get(liquidity::get_market_liquidity_positions),
+        )
+        .route(
+            "/v1/markets/{market_id}/flow-schedule",
+            get(flow_schedule::get_flow_schedule),
+        )
+        .route(
+            "/v1/markets/{market_id}/updates",
+            get(get_market_updates).layer(cached()),
+        )
+        .route("/v1/markets/{market_id}/state", get(get_market_state))
+        .route(
+            "/v1/markets/{market_id}/ticker",
+            get(tickers::get_market_ticker),
+        )
+        .route("/v1/fees", get(fees::get_fees))
+        .route("/v1/tickers", get(tickers::list_tickers))
+        .route("/v1/aggregator/pairs", get(aggregator::list_pairs))
+        .route("/v1/aggregator/tickers", get(aggregator::list_tickers))
+        .route(
+            "/v1/aggregator/historical_trades",
+            get(aggregator::get_historical_trades),
+        )
+        .route("/v1/aggregator/liquidity", get(aggregator::get_liquidity))
+        .route("/v1/usage", get(access::get_usage))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            access::enforce,
+        ))
+        .layer(cors)
         .with_state(state);
 
     println!("Read API listening on {}", config.bind_addr);
+    if config.ready_max_update_age_secs == 0 {
+        println!(
+            "/readyz does not check ingestion; set READ_API_READY_MAX_UPDATE_AGE_SECS to fail it on stalled updates"
+        );
+    }
 
     let listener = tokio::net::TcpListener::bind(config.bind_addr)
         .await
         .context("Failed to bind read-api listener")?;
-    axum::serve(listener, app)
-        .await
-        .context("Read API server exited unexpectedly")?;
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await
+    .context("Read API server exited unexpectedly")?;
     Ok(())
 }
 
+#[utoipa::path(get, path = "/healthz", responses((status = 200, body = HealthResponse)))]
 async fn healthz() -> Json<HealthResponse> {
-    Json(HealthResponse { status: "ok" })
+    Json(HealthResponse {
+        status: "ok".to_string(),
+    })
 }
 
+#[utoipa::path(
+    get,
+    path = "/v1/status/storage",
+    responses(
+        (status = 200, body = StorageStatusResponse),
+        (status = 500, body = ErrorResponse),
+    )
+)]
+async fn get_storage_status(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<StorageStatusResponse>, ApiError> {
+    let backend = state.config.storage_backend;
+    // Plain Postgres has no hypertables to report on.
+    let hypertables = if backend == StorageBackend::Timescale {
+        let client = state.pool.get().await.map_err(|error| {
+            ApiError::internal(anyhow!(error).context("Failed to get DB connection"))
+        })?;
+        storage_stats(&client)
+            .await
+            .map_err(|error| ApiError::internal(error.context("Failed to query storage stats")))?
+    } else {
+        Vec::new()
+    };
+
+    Ok(Json(StorageStatusResponse {
+        backend: backend.as_str().to_string(),
+        points: hypertables.len(),
+        hypertables,
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/markets",
+    responses(
+        (status = 200, body = MarketConfigListResponse),
+        (status = 500, body = ErrorResponse),
+    )
+)]
 async fn list_market_configs(
     State(state): State<Arc<AppState>>,
 ) -> Result<impl IntoResponse, ApiError> {
@@ -485,6 +635,15 @@ async fn list_market_configs(
     ))
 }
 
+#[utoipa::path(
+    get,
+    path = "/v1/markets/{market_id}/config",
+    params(("market_id" = u64, Path, description = "Market id")),
+    responses(
+        (status = 200, body = MarketConfig),
+        (status = 404, body = ErrorResponse),
+    )
+)]
 async fn get_market_config(
     State(state): State<Arc<AppState>>,
     Path(market_id): Path<u64>,
@@ -502,24 +661,49 @@ async fn get_market_config(
     ))
 }
 
+#[utoipa::path(
+    get,
+    path = "/v1/authorities/{authority}/closed-positions",
+    params(
+        ("authority" = String, Path, description = "Wallet address"),
+        ClosedPositionsQuery,
+    ),
+    responses(
+        (status = 200, body = ClosedPositionsResponse),
+        (status = 400, body = ErrorResponse),
+    )
+)]
 async fn get_closed_positions(
     State(state): State<Arc<AppState>>,
     Path(authority): Path<String>,
     Query(query): Query<ClosedPositionsQuery>,
-) -> Result<Json<ClosedPositionsResponse>, ApiError> {
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
     let limit = query.limit.unwrap_or(DEFAULT_UPDATES_LIMIT);
     if limit == 0 || limit > ABSOLUTE_MAX_UPDATES_LIMIT {
         return Err(ApiError::bad_request(format!(
             "limit must be between 1 and {ABSOLUTE_MAX_UPDATES_LIMIT}"
         )));
     }
+    let format = ExportFormat::negotiate(query.format.as_deref(), &headers)?;
+    let filter = ClosedPositionFilter {
+        market_id: query.market_id,
+        before_slot: query.before_slot,
+        from_slot: match query.from {
+            Some(from) => Some(slots::resolve_time_slot(&state, from).await?.0),
+            None => None,
+        },
+        to_slot: match query.to {
+            Some(to) => Some(slots::resolve_time_slot(&state, to).await?.0),
+            None => None,
+        },
+    };
 
     let mut rows = query_closed_position_rows(
         &state.pool,
         &state.config.close_position_events_table,
         &authority,
-        query.market_id,
-        query.before_slot,
+        &filter,
         limit.saturating_add(1),
     )
     .await
@@ -530,6 +714,18 @@ async fn get_closed_positions(
         rows.truncate(limit);
     }
 
+    if format != ExportFormat::Json {
+        let mut response = export_response(
+            format,
+            &format!("{authority}-closed-positions"),
+            stream::iter(rows.into_iter().map(Ok)),
+        );
+        response
+            .headers_mut()
+            .insert(HAS_MORE_HEADER, has_more_header(has_more));
+        return Ok(response);
+    }
+
     let mut items = Vec::with_capacity(rows.len());
     for row in rows {
         items.push(closed_position_item_from_row(row)?);
@@ -537,15 +733,27 @@ async fn get_closed_positions(
 
     Ok(Json(ClosedPositionsResponse {
         authority,
-        market_id: query.market_id,
-        before_slot: query.before_slot,
+        market_id: filter.market_id,
+        before_slot: filter.before_slot,
+        from_slot: filter.from_slot,
+        to_slot: filter.to_slot,
         has_more,
         limit,
         points: items.len(),
         items,
-    }))
+    })
+    .into_response())
 }
 
+#[utoipa::path(
+    get,
+    path = "/v1/markets/{market_id}/price",
+    params(("market_id" = u64, Path, description = "Market id")),
+    responses(
+        (status = 200, body = LatestPriceResponse),
+        (status = 404, body = ErrorResponse),
+    )
+)]
 async fn get_latest_price(
     State(state): State<Arc<AppState>>,
     Path(market_id): Path<u64>,
@@ -564,39 +772,114 @@ async fn get_latest_price(
     Ok(Json(snapshot))
 }
 
+/// `price_update` events carry `id: <slot>:<event_index>:<signature>` of their
+/// market update. A client reconnecting with `Last-Event-ID` first gets a
+/// `price_update` for every update it missed, then live events. When the
+/// missed updates cannot all be replayed it gets a `reset` event instead.
 async fn stream_market_price(
     State(state): State<Arc<AppState>>,
     Path(market_id): Path<u64>,
+    Query(query): Query<PriceStreamQuery>,
+    permit: Option<Extension<StreamPermit>>,
+    headers: HeaderMap,
 ) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, ApiError> {
-    let receiver = state.market_price_streams.subscribe(market_id).await;
-
-    let event_stream = stream::unfold(receiver, move |mut receiver| async move {
-        loop {
-            match receiver.recv().await {
-                Ok(snapshot) => {
-                    let event = match Event::default().event("price_update").json_data(&snapshot) {
-                        Ok(event) => event,
-                        Err(error) => {
-                            eprintln!(
-                                "Failed to encode price_update SSE payload for market_id={}: {}",
-                                market_id, error
-                            );
-                            continue;
-                        }
-                    };
-                    return Some((Ok::<Event, Infallible>(event), receiver));
-                }
-                Err(broadcast::error::RecvError::Lagged(skipped)) => {
-                    eprintln!(
-                        "Price stream lagged for market_id={}; skipped {} event(s)",
-                        market_id, skipped
-                    );
-                }
-                Err(broadcast::error::RecvError::Closed) => return None,
-            }
+    let candle_interval = query
+        .interval
+        .as_deref()
+        .map(|raw| CandleInterval::parse(Some(raw)))
+        .transpose()
+        .map_err(|error| ApiError::bad_request(error.to_string()))?;
+    let last_event_id = headers
+        .get(LAST_EVENT_ID)
+        .map(|value| {
+            value
+                .to_str()
+                .ok()
+                .and_then(PriceEventId::parse)
+                .ok_or_else(|| {
+                    ApiError::bad_request(
+                        "Last-Event-ID must be '<slot>:<event_index>:<signature>'",
+                    )
+                })
+        })
+        .transpose()?;
+
+    // Subscribe before reading the replay so nothing falls in between; live
+    // events already covered by the replay are dropped below.
+    let prices = state.market_streams.subscribe_prices(market_id).await?;
+    let replay = match &last_event_id {
+        Some(after) => fetch_price_replay(
+            &state.pool,
+            &state.config.market_updates_table,
+            market_id,
+            after,
+            STREAM_REPLAY_LIMIT,
+        )
+        .await
+        .map_err(|error| ApiError::internal(error.context("Failed to replay price updates")))?,
+        None => PriceReplay::Events(Vec::new()),
+    };
+    let (replay, resume_after) = match replay {
+        PriceReplay::Events(snapshots) => {
+            let resume_after = snapshots.last().map(PriceEventId::of).or(last_event_id);
+            let events = snapshots.iter().map(price_update_event).collect::<Vec<_>>();
+            (events, resume_after)
+        }
+        // The client reloads, so everything live is new to it.
+        PriceReplay::Reset(reason) => (
+            vec![
+                Event::default()
+                    .event("reset")
+                    .json_data(StreamResetEvent { market_id, reason }),
+            ],
+            None,
+        ),
+    };
+
+    let replay_events = stream::iter(replay.into_iter().filter_map(move |event| match event {
+        Ok(event) => Some(Ok::<Event, Infallible>(event)),
+        Err(error) => {
+            eprintln!("Failed to encode replayed SSE payload for market_id={market_id}: {error}");
+            None
         }
+    }));
+    let live_events = broadcast_event_stream(prices, market_id, "price_update", move |snapshot| {
+        resume_after
+            .as_ref()
+            .is_none_or(|after| PriceEventId::of(snapshot) > *after)
+            .then(|| price_update_event(snapshot))
     });
+    let price_events = replay_events.chain(live_events);
+
+    let event_stream: BoxStream<'static, Result<Event, Infallible>> = match candle_interval {
+        Some(interval) => {
+            let candles = state
+                .market_streams
+                .subscribe_candles(market_id, interval)
+                .await?;
+            // The market's candle channel carries every subscribed interval.
+            let candle_events =
+                broadcast_event_stream(candles, market_id, "candle_update", move |update| {
+                    (update.interval == interval).then(|| {
+                        Event::default()
+                            .event("candle_update")
+                            .json_data(CandleUpdateEvent {
+                                market_id,
+                                interval: interval.as_str(),
+                                candle: &update.candle,
+                            })
+                    })
+                });
+            stream::select(price_events, candle_events).boxed()
+        }
+        None => price_events.boxed(),
+    };
 
+    // The stream counts as open until the client disconnects and it drops.
+    let event_stream = event_stream.map(move |event| {
+        let _permit = &permit;
+        event
+    });
     Ok(Sse::new(event_stream).keep_alive(
         KeepAlive::new()
             .interval(Duration::from_secs(15))
@@ -604,14 +887,197 @@ async fn stream_market_price(
     ))
 }
 
+fn price_update_event(snapshot: &LatestPriceResponse) -> Result<Event, axum::Error> {
+    Event::default()
+        .event("price_update")
+        .id(snapshot.event_id())
+        .json_data(snapshot)
+}
+
+/// Position of a market update in price SSE ids and in replay order. The
+/// derived order matches the replay query, which compares signatures bytewise
+/// (`COLLATE "C"`).
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct PriceEventId {
+    slot: u64,
+    event_index: u16,
+    signature: String,
+}
+
+impl PriceEventId {
+    fn of(snapshot: &LatestPriceResponse) -> Self {
+        Self {
+            slot: snapshot.slot,
+            event_index: snapshot.event_index,
+            signature: snapshot.signature.clone(),
+        }
+    }
+
+    /// Parse `<slot>:<event_index>:<signature>`. Ids from before signatures
+    /// were added (`<slot>:<event_index>`) resume from the start of that
+    /// position, repeating at most the update they name.
+    fn parse(raw: &str) -> Option<Self> {
+        let mut parts = raw.trim().splitn(3, ':');
+        Some(Self {
+            slot: parts.next()?.parse().ok()?,
+            event_index: parts.next()?.parse().ok()?,
+            signature: parts.next().unwrap_or_default().to_string(),
+        })
+    }
+}
+
+/// `reset` SSE payload: the missed updates could not be replayed, so the
+/// client should reload state from the REST endpoints.
+#[derive(Serialize)]
+struct StreamResetEvent {
+    market_id: u64,
+    /// `replay_limit` or `replay_window`.
+    reason: &'static str,
+}
+
+/// Slot range of a `/history` request and where in it the page starts.
+struct HistoryRange {
+    market_id: u64,
+    start_slot: u64,
+    end_slot: u64,
+    cursor: Option<HistoryCursor>,
+}
+
+impl HistoryRange {
+    fn params(&self) -> Result<HistoryParams> {
+        Ok(HistoryParams {
+            market_id: i64::try_from(self.market_id).context("market_id out of range")?,
+            start_slot: i64::try_from(self.start_slot).context("start_slot out of range")?,
+            end_slot: i64::try_from(self.end_slot).context("end_slot out of range")?,
+            cursor: self.cursor.clone(),
+        })
+    }
+}
+
+/// [`HistoryRange`] as SQL parameters of [`history_range_sql`].
+struct HistoryParams {
+    market_id: i64,
+    start_slot: i64,
+    end_slot: i64,
+    cursor: Option<HistoryCursor>,
+}
+
+impl HistoryParams {
+    fn with_limit<'a>(&'a self, limit: &'a i64) -> Vec<&'a (dyn ToSql + Sync)> {
+        let mut params: Vec<&(dyn ToSql + Sync)> =
+            vec![&self.market_id, &self.start_slot, &self.end_slot, limit];
+        if let Some(cursor) = &self.cursor {
+            params.extend([
+                &cursor.slot as &(dyn ToSql + Sync),
+                &cursor.event_index,
+                &cursor.signature,
+            ]);
+        }
+        params
+    }
+}
+
+/// Position in `/history` paging: the last row returned. Events are ordered
+/// by slot and event index; the signature breaks ties between transactions
+/// that land in the same slot.
+#[derive(Clone, Debug, PartialEq)]
+struct HistoryCursor {
+    slot: i64,
+    event_index: i32,
+    signature: String,
+}
+
+impl HistoryCursor {
+    fn of(row: &MarketHistoryRow) -> Self {
+        Self {
+            slot: row.slot,
+            event_index: row.event_index,
+            signature: row.signature.clone(),
+        }
+    }
+
+    /// Opaque to clients: base64url of `<slot>:<event_index>:<signature>`.
+    fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(format!(
+            "{}:{}:{}",
+            self.slot, self.event_index, self.signature
+        ))
+    }
+
+    fn decode(raw: &str) -> Option<Self> {
+        let decoded = String::from_utf8(URL_SAFE_NO_PAD.decode(raw.trim()).ok()?).ok()?;
+        let mut parts = decoded.splitn(3, ':');
+        let slot: i64 = parts.next()?.parse().ok()?;
+        let event_index: i32 = parts.next()?.parse().ok()?;
+        let signature = parts.next()?;
+        (slot >= 0 && event_index >= 0 && !signature.is_empty()).then(|| Self {
+            slot,
+            event_index,
+            signature: signature.to_string(),
+        })
+    }
+}
+
+/// Relay a stream broadcast as SSE events. `render` returns `None` to skip a
+/// value; encoding failures are logged and skipped.
+fn broadcast_event_stream<T, F>(
+    receiver: broadcast::Receiver<T>,
+    market_id: u64,
+    event_name: &'static str,
+    render: F,
+) -> impl Stream<Item = Result<Event, Infallible>> + Send + 'static
+where
+    T: Clone + Send + 'static,
+    F: Fn(&T) -> Option<Result<Event, axum::Error>> + Send + Sync + 'static,
+{
+    let render = Arc::new(render);
+    stream::unfold(receiver, move |mut receiver| {
+        let render = render.clone();
+        async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(value) => match render(&value) {
+                        Some(Ok(event)) => return Some((Ok::<Event, Infallible>(event), receiver)),
+                        Some(Err(error)) => {
+                            eprintln!(
+                                "Failed to encode {} SSE payload for market_id={}: {}",
+                                event_name, market_id, error
+                            );
+                        }
+                        None => {}
+                    },
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        eprintln!(
+                            "{} stream lagged for market_id={}; skipped {} event(s)",
+                            event_name, market_id, skipped
+                        );
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        }
+    })
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/markets/{market_id}/candles",
+    params(("market_id" = u64, Path, description = "Market id"), CandleQuery),
+    responses(
+        (status = 200, body = CandleResponse),
+        (status = 400, body = ErrorResponse),
+    )
+)]
 async fn get_candles(
     State(state): State<Arc<AppState>>,
     Path(market_id): Path<u64>,
     Query(query): Query<CandleQuery>,
-) -> Result<Json<CandleResponse>, ApiError> {
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
     if query.to <= query.from {
         return Err(ApiError::bad_request("'to' must be later than 'from'"));
     }
+    let format = ExportFormat::negotiate(query.format.as_deref(), &headers)?;
 
     let interval = CandleInterval::parse(query.interval.as_deref())
         .map_err(|error| ApiError::bad_request(error.to_string()))?;
@@ -637,9 +1103,147 @@ async fn get_candles(
     let market_id_i64 =
         i64::try_from(market_id).map_err(|_| ApiError::bad_request("market_id out of range"))?;
 
-    // Gap-filled, carry-forward candles directly from the 1m rollup. Empty
-    // buckets get `locf` (last observation carried forward), seeded from the
-    // last candle strictly before `from` so leading gaps render as flat doji.
+    let client = state.pool.get().await.map_err(|error| {
+        ApiError::internal(anyhow!(error).context("Failed to get DB connection"))
+    })?;
+    let items = state
+        .config
+        .candle_source()
+        .query(&client, market_id_i64, query.from, query.to, interval)
+        .await
+        .map_err(|error| ApiError::internal(error.context("Failed to query candles")))?;
+
+    if format != ExportFormat::Json {
+        return Ok(export_response(
+            format,
+            &format!("market-{market_id}-candles-{}", interval.as_str()),
+            stream::iter(items.into_iter().map(Ok)),
+        ));
+    }
+
+    let mut response = Json(CandleResponse {
+        market_id,
+        interval: interval.as_str().to_string(),
+        from: query.from.to_rfc3339_opts(SecondsFormat::Secs, true),
+        to: query.to.to_rfc3339_opts(SecondsFormat::Secs, true),
+        points: items.len(),
+        items,
+    })
+    .into_response();
+    let now = Utc::now();
+    if query.to <= now - chrono::Duration::seconds(SETTLED_CANDLE_RANGE_AGE_SECS) {
+        response.headers_mut().insert(
+            header::CACHE_CONTROL,
+            HeaderValue::from_static(IMMUTABLE_CACHE_CONTROL),
+        );
+    } else if query.to <= now {
+        response.headers_mut().insert(
+            header::CACHE_CONTROL,
+            HeaderValue::from_static(ENDED_CANDLE_RANGE_CACHE_CONTROL),
+        );
+    }
+    Ok(response)
+}
+
+/// Where `/candles` and the `candle_update` stream read candles from, so both
+/// agree on every bucket.
+#[derive(Clone, Debug)]
+struct CandleSource {
+    storage_backend: StorageBackend,
+    candles_1m_table: String,
+    candles_1h_table: Option<String>,
+}
+
+impl CandleSource {
+    /// Candles in `[from, to)`, read from the 1h rollup for intervals of 1h
+    /// and above when it is enabled. Empty buckets carry the last close
+    /// forward as flat candles; invalid rows are dropped.
+    async fn query(
+        &self,
+        client: &tokio_postgres::Client,
+        market_id: i64,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        interval: CandleInterval,
+    ) -> Result<Vec<CandleItem>> {
+        let candles_table = match (&self.candles_1h_table, interval.step_seconds()) {
+            (Some(candles_1h_table), step_seconds) if step_seconds >= 3600 => candles_1h_table,
+            _ => &self.candles_1m_table,
+        };
+
+        let rows = match self.storage_backend {
+            StorageBackend::Timescale => {
+                query_timescale_candles(client, candles_table, market_id, from, to, interval)
+                    .await?
+            }
+            StorageBackend::Postgres => {
+                query_gapfilled_candles(
+                    client,
+                    candles_table,
+                    market_id,
+                    from,
+                    to,
+                    interval.step_seconds(),
+                )
+                .await?
+            }
+        };
+
+        let mut items = Vec::with_capacity(rows.len());
+        for row in rows {
+            let bucket_s = row.bucket_start.timestamp();
+
+            let (open, high, low, close) = match (row.open, row.close) {
+                (Some(open), Some(close)) => {
+                    let high = row.high.unwrap_or(close);
+                    let low = row.low.unwrap_or(close);
+                    (open, high, low, close)
+                }
+                // Empty bucket: carry the last known close forward as a flat candle.
+                _ => match row.carried_close {
+                    Some(carried) => (carried, carried, carried, carried),
+                    None => continue, // No data at or before this bucket yet.
+                },
+            };
+
+            if !is_valid_chart_price(open)
+                || !is_valid_chart_price(high)
+                || !is_valid_chart_price(low)
+                || !is_valid_chart_price(close)
+            {
+                eprintln!(
+                    "Dropping invalid candle row for market_id={}: time={} open={} high={} low={} close={}",
+                    market_id, bucket_s, open, high, low, close
+                );
+                continue;
+            }
+
+            let normalized_high = open.max(high).max(low).max(close);
+            let normalized_low = open.min(high).min(low).min(close);
+
+            items.push(CandleItem {
+                time: bucket_s.max(0) as u64,
+                open,
+                high: normalized_high,
+                low: normalized_low,
+                close,
+            });
+        }
+        Ok(items)
+    }
+}
+
+/// Gap-filled, carry-forward candles directly from the 1m rollup. Empty
+/// buckets get `locf` (last observation carried forward), seeded from the
+/// last candle strictly before `from` so leading gaps render as flat doji.
+async fn query_timescale_candles(
+    client: &tokio_postgres::Client,
+    candles_table: &str,
+    market_id: i64,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    interval: CandleInterval,
+) -> Result<Vec<GapfilledCandle>> {
     let sql = format!(
         "SELECT \
             time_bucket_gapfill($4::text::interval, bucket_start) AS bucket, \
@@ -659,103 +1263,101 @@ async fn get_candles(
            AND bucket_start < $3 \
          GROUP BY 1 \
          ORDER BY 1",
-        state.config.candles_1m_table
+        candles_table
     );
 
-    let client = state.pool.get().await.map_err(|error| {
-        ApiError::internal(anyhow!(error).context("Failed to get DB connection"))
-    })?;
     let rows = client
-        .query(
-            &sql,
-            &[
-                &market_id_i64,
-                &query.from,
-                &query.to,
-                &interval.pg_interval(),
-            ],
-        )
-        .await
-        .map_err(|error| ApiError::internal(anyhow!(error).context("Failed to query candles")))?;
-
-    let mut items = Vec::with_capacity(rows.len());
-    for row in rows {
-        let bucket: DateTime<Utc> = row.get("bucket");
-        let bucket_s = bucket.timestamp();
-        let open: Option<Decimal> = row.get("open");
-        let close: Option<Decimal> = row.get("close");
-
-        let (open, high, low, close) = match (open, close) {
-            (Some(open), Some(close)) => {
-                let high: Decimal = row.get::<_, Option<Decimal>>("high").unwrap_or(close);
-                let low: Decimal = row.get::<_, Option<Decimal>>("low").unwrap_or(close);
-                (open, high, low, close)
-            }
-            // Empty bucket: carry the last known close forward as a flat candle.
-            _ => match row.get::<_, Option<Decimal>>("carried_close") {
-                Some(carried) => (carried, carried, carried, carried),
-                None => continue, // No data at or before this bucket yet.
-            },
-        };
-
-        if !is_valid_chart_price(open)
-            || !is_valid_chart_price(high)
-            || !is_valid_chart_price(low)
-            || !is_valid_chart_price(close)
-        {
-            eprintln!(
-                "Dropping invalid candle row for market_id={}: time={} open={} high={} low={} close={}",
-                market_id, bucket_s, open, high, low, close
-            );
-            continue;
-        }
-
-        let normalized_high = open.max(high).max(low).max(close);
-        let normalized_low = open.min(high).min(low).min(close);
+        .query(&sql, &[&market_id, &from, &to, &interval.pg_interval()])
+        .await?;
 
-        items.push(CandleItem {
-            time: bucket_s.max(0) as u64,
-            open,
-            high: normalized_high,
-            low: normalized_low,
-            close,
-        });
-    }
-
-    Ok(Json(CandleResponse {
-        market_id,
-        interval: interval.as_str().to_string(),
-        from: query.from.to_rfc3339_opts(SecondsFormat::Secs, true),
-        to: query.to.to_rfc3339_opts(SecondsFormat::Secs, true),
-        points: items.len(),
-        items,
-    }))
+    Ok(rows
+        .into_iter()
+        .map(|row| GapfilledCandle {
+            bucket_start: row.get("bucket"),
+            open: row.get("open"),
+            high: row.get("high"),
+            low: row.get("low"),
+            close: row.get("close"),
+            carried_close: row.get("carried_close"),
+        })
+        .collect())
 }
 
+#[utoipa::path(
+    get,
+    path = "/v1/markets/{market_id}/history",
+    params(("market_id" = u64, Path, description = "Market id"), MarketHistoryQuery),
+    responses(
+        (status = 200, body = MarketHistoryResponse),
+        (status = 400, body = ErrorResponse),
+    )
+)]
 async fn get_market_history(
     State(state): State<Arc<AppState>>,
     Path(market_id): Path<u64>,
     Query(query): Query<MarketHistoryQuery>,
-) -> Result<Json<MarketHistoryResponse>, ApiError> {
-    if query.start_slot > query.end_slot {
-        return Err(ApiError::bad_request(
-            "'start_slot' must be less than or equal to 'end_slot'",
-        ));
-    }
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let (start_slot, end_slot) = slots::resolve_slot_range(
+        &state,
+        (query.start_slot, query.from),
+        (query.end_slot, query.to),
+    )
+    .await?;
 
-    let max_rows = query.max_rows.unwrap_or(DEFAULT_HISTORY_MAX_ROWS);
+    let format = ExportFormat::negotiate(query.format.as_deref(), &headers)?;
+    // Exports are streamed, so they may use the whole row budget by default.
+    let default_max_rows = match format {
+        ExportFormat::Json => DEFAULT_HISTORY_MAX_ROWS,
+        ExportFormat::Csv | ExportFormat::Parquet => ABSOLUTE_MAX_HISTORY_ROWS,
+    };
+    let max_rows = query.max_rows.unwrap_or(default_max_rows);
     if max_rows == 0 || max_rows > ABSOLUTE_MAX_HISTORY_ROWS {
         return Err(ApiError::bad_request(format!(
             "max_rows must be between 1 and {ABSOLUTE_MAX_HISTORY_ROWS}"
         )));
     }
 
-    let rows = query_market_history_rows(
+    let cursor = query
+        .cursor
+        .as_deref()
+        .map(|raw| {
+            HistoryCursor::decode(raw).ok_or_else(|| ApiError::bad_request("Invalid 'cursor'"))
+        })
+        .transpose()?;
+    let range = HistoryRange {
+        market_id,
+        start_slot,
+        end_slot,
+        cursor,
+    };
+
+    if format != ExportFormat::Json {
+        let (rows, next_cursor) = stream_market_history_rows(
+            &state.pool,
+            &state.config.market_updates_table,
+            &range,
+            max_rows,
+        )
+        .await
+        .map_err(|error| ApiError::internal(error.context("Failed to query market history")))?;
+        let mut response = export_response(
+            format,
+            &format!("market-{market_id}-history-{start_slot}-{end_slot}"),
+            rows,
+        );
+        if let Some(next_cursor) = next_cursor {
+            let value = HeaderValue::from_str(&next_cursor.encode())
+                .map_err(|error| ApiError::internal(anyhow!("Invalid cursor header: {error}")))?;
+            response.headers_mut().insert(NEXT_CURSOR_HEADER, value);
+        }
+        return Ok(response);
+    }
+
+    let (rows, next_cursor) = query_market_history_rows(
         &state.pool,
         &state.config.market_updates_table,
-        market_id,
-        query.start_slot,
-        query.end_slot,
+        &range,
         max_rows,
     )
     .await
@@ -768,24 +1370,38 @@ async fn get_market_history(
 
     Ok(Json(MarketHistoryResponse {
         market_id,
-        start_slot: query.start_slot,
-        end_slot: query.end_slot,
+        start_slot,
+        end_slot,
+        has_more: next_cursor.is_some(),
+        next_cursor: next_cursor.map(|cursor| cursor.encode()),
         points: items.len(),
         items,
-    }))
+    })
+    .into_response())
 }
 
+#[utoipa::path(
+    get,
+    path = "/v1/markets/{market_id}/updates",
+    params(("market_id" = u64, Path, description = "Market id"), MarketUpdatesQuery),
+    responses(
+        (status = 200, body = MarketUpdatesResponse),
+        (status = 400, body = ErrorResponse),
+    )
+)]
 async fn get_market_updates(
     State(state): State<Arc<AppState>>,
     Path(market_id): Path<u64>,
     Query(query): Query<MarketUpdatesQuery>,
-) -> Result<Json<MarketUpdatesResponse>, ApiError> {
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
     let limit = query.limit.unwrap_or(DEFAULT_UPDATES_LIMIT);
     if limit == 0 || limit > ABSOLUTE_MAX_UPDATES_LIMIT {
         return Err(ApiError::bad_request(format!(
             "limit must be between 1 and {ABSOLUTE_MAX_UPDATES_LIMIT}"
         )));
     }
+    let format = ExportFormat::negotiate(query.format.as_deref(), &headers)?;
 
     let mut rows = query_market_updates_rows(
         &state.pool,
@@ -802,6 +1418,18 @@ async fn get_market_updates(
         rows.truncate(limit);
     }
 
+    if format != ExportFormat::Json {
+        let mut response = export_response(
+            format,
+            &format!("market-{market_id}-updates"),
+            stream::iter(rows.into_iter().map(Ok)),
+        );
+        response
+            .headers_mut()
+            .insert(HAS_MORE_HEADER, has_more_header(has_more));
+        return Ok(response);
+    }
+
     let mut items = Vec::with_capacity(rows.len());
     for row in rows {
         items.push(market_history_item_from_row(row)?);
@@ -814,19 +1442,158 @@ async fn get_market_updates(
         limit,
         points: items.len(),
         items,
+    })
+    .into_response())
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/markets/{market_id}/state",
+    params(("market_id" = u64, Path, description = "Market id"), MarketStateQuery),
+    responses(
+        (status = 200, body = MarketStateResponse),
+        (status = 400, body = ErrorResponse),
+        (status = 404, body = ErrorResponse),
+    )
+)]
+async fn get_market_state(
+    State(state): State<Arc<AppState>>,
+    Path(market_id): Path<u64>,
+    Query(query): Query<MarketStateQuery>,
+) -> Result<Json<MarketStateResponse>, ApiError> {
+    let limit = query.limit.unwrap_or(DEFAULT_STATE_HISTORY_LIMIT);
+    if limit == 0 || limit > ABSOLUTE_MAX_STATE_HISTORY_LIMIT {
+        return Err(ApiError::bad_request(format!(
+            "limit must be between 1 and {ABSOLUTE_MAX_STATE_HISTORY_LIMIT}"
+        )));
+    }
+
+    let to = query.to.unwrap_or_else(Utc::now);
+    let from = query
+        .from
+        .unwrap_or_else(|| to - chrono::Duration::seconds(DEFAULT_STATE_HISTORY_WINDOW_SECS));
+    if to <= from {
+        return Err(ApiError::bad_request("'to' must be later than 'from'"));
+    }
+
+    let market_id_i64 =
+        i64::try_from(market_id).map_err(|_| ApiError::bad_request("market_id out of range"))?;
+
+    let client = state
+        .pool
+        .get()
+        .await
+        .map_err(|error| ApiError::internal(anyhow!("Failed to get DB connection: {error}")))?;
+
+    let current = client
+        .query_opt(
+            &format!(
+                "{MARKET_STATE_SELECT} WHERE market_id = $1 ORDER BY snapshot_time DESC LIMIT 1"
+            ),
+            &[&market_id_i64],
+        )
+        .await
+        .map_err(|error| ApiError::internal(anyhow!("Failed to query market state: {error}")))?;
+    let Some(current) = current else {
+        return Err(ApiError::not_found(format!(
+            "No state snapshots for market_id={market_id}"
+        )));
+    };
+
+    let mut rows = client
+        .query(
+            &format!(
+                "{MARKET_STATE_SELECT} \
+                 WHERE market_id = $1 AND snapshot_time >= $2 AND snapshot_time <= $3 \
+                 ORDER BY snapshot_time DESC \
+                 LIMIT $4"
+            ),
+            &[
+                &market_id_i64,
+                &from,
+                &to,
+                &(limit.saturating_add(1) as i64),
+            ],
+        )
+        .await
+        .map_err(|error| {
+            ApiError::internal(anyhow!("Failed to query market state history: {error}"))
+        })?;
+
+    let has_more = rows.len() > limit;
+    if has_more {
+        rows.truncate(limit);
+    }
+
+    let items = rows
+        .iter()
+        .map(market_state_item_from_row)
+        .collect::<Result<Vec<_>>>()
+        .map_err(|error| ApiError::internal(error.context("Invalid market state row")))?;
+    let current = market_state_item_from_row(&current)
+        .map_err(|error| ApiError::internal(error.context("Invalid market state row")))?;
+
+    Ok(Json(MarketStateResponse {
+        market_id,
+        from: from.to_rfc3339_opts(SecondsFormat::Secs, true),
+        to: to.to_rfc3339_opts(SecondsFormat::Secs, true),
+        has_more,
+        limit,
+        current,
+        points: items.len(),
+        items,
     }))
 }
 
+const MARKET_STATE_SELECT: &str = "\
+SELECT snapshot_time, slot, is_paused, fee_bps, unhealthy_liquidity_fee_bps, open_positions, \
+       accumulated_base_fees::text, accumulated_quote_fees::text, base_flow::text, \
+       quote_flow::text, end_slot_interval, base_per_quote::text, quote_per_base::text, \
+       slots_without_trade, bookkeeping_last_update_slot \
+FROM market_state_snapshots";
+
+fn market_state_item_from_row(row: &tokio_postgres::Row) -> Result<MarketStateItem> {
+    let snapshot_time: DateTime<Utc> = row.try_get(0)?;
+    let non_negative = |value: i64| value.max(0) as u64;
+    Ok(MarketStateItem {
+        snapshot_time: snapshot_time.to_rfc3339_opts(SecondsFormat::Millis, true),
+        slot: non_negative(row.try_get(1)?),
+        is_paused: row.try_get(2)?,
+        fee_bps: row.try_get::<_, i16>(3)?.max(0) as u16,
+        unhealthy_liquidity_fee_bps: row.try_get::<_, i16>(4)?.max(0) as u16,
+        open_positions: non_negative(row.try_get(5)?),
+        accumulated_base_fees: row.try_get(6)?,
+        accumulated_quote_fees: row.try_get(7)?,
+        base_flow: row.try_get(8)?,
+        quote_flow: row.try_get(9)?,
+        end_slot_interval: non_negative(row.try_get(10)?),
+        base_per_quote: row.try_get(11)?,
+        quote_per_base: row.try_get(12)?,
+        slots_without_trade: row.try_get::<_, Option<i64>>(13)?.map(non_negative),
+        bookkeeping_last_update_slot: row.try_get::<_, Option<i64>>(14)?.map(non_negative),
+    })
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/markets/{market_id}/closed-position-mini-chart",
+    params(("market_id" = u64, Path, description = "Market id"), ClosedPositionMiniChartQuery),
+    responses(
+        (status = 200, body = ClosedPositionMiniChartResponse),
+        (status = 400, body = ErrorResponse),
+    )
+)]
 async fn get_closed_position_mini_chart(
     State(state): State<Arc<AppState>>,
     Path(market_id): Path<u64>,
     Query(query): Query<ClosedPositionMiniChartQuery>,
 ) -> Result<Json<ClosedPositionMiniChartResponse>, ApiError> {
-    if query.start_slot > query.end_slot {
-        return Err(ApiError::bad_request(
-            "'start_slot' must be less than or equal to 'end_slot'",
-        ));
-    }
+    let (start_slot, end_slot) = slots::resolve_slot_range(
+        &state,
+        (query.start_slot, query.from),
+        (query.end_slot, query.to),
+    )
+    .await?;
 
     let max_points = query
         .max_points
@@ -841,8 +1608,8 @@ async fn get_closed_position_mini_chart(
         &state.pool,
         &state.config.market_updates_table,
         market_id,
-        query.start_slot,
-        query.end_slot,
+        start_slot,
+        end_slot,
         max_points,
     )
     .await
@@ -863,154 +1630,388 @@ async fn get_closed_position_mini_chart(
 
     Ok(Json(ClosedPositionMiniChartResponse {
         market_id,
-        start_slot: query.start_slot,
-        end_slot: query.end_slot,
+        start_slot,
+        end_slot,
         points: items.len(),
         items,
     }))
 }
 
-async fn fetch_latest_price_snapshot(
-    pool: &Pool,
-    market_updates_table: &str,
-    market_id: u64,
-) -> Result<Option<LatestPriceResponse>> {
-    let market_id_i64 = i64::try_from(market_id).context("market_id out of range")?;
-
-    let sql = format!(
+/// Per-update price of the non-debug updates with a non-zero base flow.
+fn price_snapshot_sql(market_updates_table: &str, filter: &str, order_and_limit: &str) -> String {
+    format!(
         "SELECT \
             r.slot AS slot, \
+            r.event_index AS event_index, \
+            r.signature AS signature, \
             (extract(epoch from r.event_time) * 1000)::bigint AS event_time_ms, \
             (r.quote_flow::numeric * power(10::numeric, mc.base_decimals::numeric)) \
               / (r.base_flow::numeric * power(10::numeric, mc.quote_decimals::numeric)) AS price \
-         FROM {} r \
+         FROM {market_updates_table} r \
          JOIN market_configs mc ON mc.market_id = r.market_id \
          WHERE r.market_id = $1 \
            AND r.base_flow <> 0 \
            AND mc.base_decimals IS NOT NULL \
            AND mc.quote_decimals IS NOT NULL \
            AND r.event_uid NOT LIKE 'debug:%' \
-         ORDER BY r.event_time DESC, r.slot DESC, r.event_index DESC \
-         LIMIT 1",
-        market_updates_table
-    );
-
-    let client = pool.get().await.context("Failed to get DB connection")?;
-    let maybe_row = client
-        .query_opt(&sql, &[&market_id_i64])
-        .await
-        .context("Failed to query latest price")?;
-
-    let Some(row) = maybe_row else {
-        return Ok(None);
-    };
+           {filter} \
+         {order_and_limit}"
+    )
+}
 
+fn price_snapshot_from_row(
+    market_id: u64,
+    row: &tokio_postgres::Row,
+) -> Result<LatestPriceResponse> {
     let slot: i64 = row.get("slot");
+    let event_index: i32 = row.get("event_index");
     let event_time_ms: i64 = row.get("event_time_ms");
     let price: Decimal = row.get("price");
 
     if !is_valid_chart_price(price) {
         return Err(anyhow!(
-            "Latest price out of supported range for market_id={market_id}: {price}"
+            "Price out of supported range for market_id={market_id} at slot {slot}: {price}"
         ));
     }
 
     let event_time = DateTime::<Utc>::from_timestamp_millis(event_time_ms)
         .ok_or_else(|| anyhow!("Invalid event_time_ms {event_time_ms}"))?;
 
-    Ok(Some(LatestPriceResponse {
+    Ok(LatestPriceResponse {
         market_id,
         slot: slot.max(0) as u64,
         event_time: event_time.to_rfc3339_opts(SecondsFormat::Millis, true),
         event_time_ms,
+        event_index: u16::try_from(event_index).unwrap_or_default(),
+        signature: row.get("signature"),
         price,
-    }))
+    })
 }
 
-async fn query_market_history_rows(
+async fn fetch_latest_price_snapshot(
     pool: &Pool,
     market_updates_table: &str,
     market_id: u64,
-    start_slot: u64,
-    end_slot: u64,
-    max_rows: usize,
-) -> Result<Vec<MarketHistoryRow>> {
-    if max_rows == 0 {
-        return Ok(Vec::new());
-    }
-
+) -> Result<Option<LatestPriceResponse>> {
     let market_id_i64 = i64::try_from(market_id).context("market_id out of range")?;
-    let start_slot_i64 = i64::try_from(start_slot).context("start_slot out of range")?;
-    let end_slot_i64 = i64::try_from(end_slot).context("end_slot out of range")?;
-
-    let client = pool.get().await.context("Failed to get DB connection")?;
 
-    let anchor_sql = format!(
-        "SELECT event_uid, signature, event_index, slot, market_id, base_flow, quote_flow, \
-            (extract(epoch from event_time) * 1000)::bigint AS event_time_ms \
-         FROM {} \
-         WHERE market_id = $1 \
-           AND slot < $2 \
-           AND event_uid NOT LIKE 'debug:%' \
-         ORDER BY slot DESC, event_index DESC \
-         LIMIT 1",
-        market_updates_table
+    let sql = price_snapshot_sql(
+        market_updates_table,
+        "",
+        "ORDER BY r.event_time DESC, r.slot DESC, r.event_index DESC, r.signature DESC LIMIT 1",
     );
 
-    let mut anchor_rows: Vec<MarketHistoryRow> = client
-        .query(&anchor_sql, &[&market_id_i64, &start_slot_i64])
+    let client = pool.get().await.context("Failed to get DB connection")?;
+    let maybe_row = client
+        .query_opt(&sql, &[&market_id_i64])
         .await
-        .context("Failed to query market history anchor row")?
-        .iter()
-        .map(market_history_row_from_pg)
-        .collect();
+        .context("Failed to query latest price")?;
+
+    maybe_row
+        .map(|row| price_snapshot_from_row(market_id, &row))
+        .transpose()
+}
+
+/// Outcome of resuming a price stream.
+enum PriceReplay {
+    /// Every missed update, oldest first.
+    Events(Vec<LatestPriceResponse>),
+    /// The missed updates cannot all be replayed; see [`StreamResetEvent`].
+    Reset(&'static str),
+}
+
+/// Prices of the updates after `after`, oldest first, for SSE resume. Only
+/// looks back [`STREAM_REPLAY_MAX_AGE_SECS`] and replays at most `limit`
+/// updates; past either bound the client has to reload instead. Updates whose
+/// price is out of chart range are skipped.
+async fn fetch_price_replay(
+    pool: &Pool,
+    market_updates_table: &str,
+    market_id: u64,
+    after: &PriceEventId,
+    limit: i64,
+) -> Result<PriceReplay> {
+    let market_id_i64 = i64::try_from(market_id).context("market_id out of range")?;
+    let slot = i64::try_from(after.slot).context("slot out of range")?;
+    let event_index = i32::from(after.event_index);
+    let client = pool.get().await.context("Failed to get DB connection")?;
 
-    let remaining_capacity = max_rows.saturating_sub(anchor_rows.len());
-    if remaining_capacity == 0 {
-        anchor_rows.sort_by(history_row_ordering);
-        return Ok(anchor_rows);
+    // The newest update that has aged out of the window: if the client had
+    // not seen it yet, the replay would skip it.
+    let aged_out = price_snapshot_sql(
+        market_updates_table,
+        "AND r.event_time <= now() - $2::bigint * interval '1 second'",
+        "ORDER BY r.event_time DESC LIMIT 1",
+    );
+    let newest_aged_out = client
+        .query_opt(&aged_out, &[&market_id_i64, &STREAM_REPLAY_MAX_AGE_SECS])
+        .await
+        .context("Failed to query price replay window")?;
+    if let Some(row) = newest_aged_out {
+        let signature: String = row.get("signature");
+        let position = (
+            row.get::<_, i64>("slot"),
+            row.get::<_, i32>("event_index"),
+            signature.as_str(),
+        );
+        if position > (slot, event_index, after.signature.as_str()) {
+            return Ok(PriceReplay::Reset("replay_window"));
+        }
     }
 
-    let range_sql = format!(
-        "SELECT event_uid, signature, event_index, slot, market_id, base_flow, quote_flow, \
-            (extract(epoch from event_time) * 1000)::bigint AS event_time_ms \
-         FROM {} \
-         WHERE market_id = $1 \
-           AND slot >= $2 \
-           AND slot <= $3 \
-           AND event_uid NOT LIKE 'debug:%' \
-         ORDER BY slot ASC, event_index ASC \
-         LIMIT $4",
-        market_updates_table
+    let sql = price_snapshot_sql(
+        market_updates_table,
+        "AND (r.slot, r.event_index, r.signature COLLATE \"C\") > ($2, $3, $4 COLLATE \"C\") \
+         AND r.event_time > now() - $6::bigint * interval '1 second'",
+        "ORDER BY r.slot, r.event_index, r.signature COLLATE \"C\" LIMIT $5",
     );
+    let rows = client
+        .query(
+            &sql,
+            &[
+                &market_id_i64,
+                &slot,
+                &event_index,
+                &after.signature,
+                &(limit + 1),
+                &STREAM_REPLAY_MAX_AGE_SECS,
+            ],
+        )
+        .await
+        .context("Failed to query price replay")?;
+    if rows.len() as i64 > limit {
+        return Ok(PriceReplay::Reset("replay_limit"));
+    }
+
+    Ok(PriceReplay::Events(
+        rows.iter()
+            .filter_map(|row| match price_snapshot_from_row(market_id, row) {
+                Ok(snapshot) => Some(snapshot),
+                Err(error) => {
+                    eprintln!("Skipping replayed price: {error:#}");
+                    None
+                }
+            })
+            .collect(),
+    ))
+}
+
+/// One page of history: the anchor row on the first page, then up to
+/// `max_rows` rows in total. The cursor is `Some` while rows are left.
+async fn query_market_history_rows(
+    pool: &Pool,
+    market_updates_table: &str,
+    range: &HistoryRange,
+    max_rows: usize,
+) -> Result<(Vec<MarketHistoryRow>, Option<HistoryCursor>)> {
+    let client = pool.get().await.context("Failed to get DB connection")?;
+    let params = range.params()?;
 
+    let mut rows = query_history_anchor(&client, market_updates_table, range, &params).await?;
+    let remaining_capacity = max_rows.saturating_sub(rows.len());
     let range_limit = remaining_capacity.saturating_add(1) as i64;
-    let mut range_rows: Vec<MarketHistoryRow> = client
+    let range_rows = client
         .query(
-            &range_sql,
-            &[&market_id_i64, &start_slot_i64, &end_slot_i64, &range_limit],
+            &history_range_sql(market_updates_table, range.cursor.is_some()),
+            &params.with_limit(&range_limit),
         )
         .await
-        .context("Failed to query market history range rows")?
-        .iter()
-        .map(market_history_row_from_pg)
-        .collect();
+        .context("Failed to query market history range rows")?;
+
+    let has_more = range_rows.len() > remaining_capacity;
+    rows.extend(
+        range_rows
+            .iter()
+            .take(remaining_capacity)
+            .map(market_history_row_from_pg),
+    );
+    let next_cursor = if has_more {
+        rows.last().map(HistoryCursor::of)
+    } else {
+        None
+    };
+    Ok((rows, next_cursor))
+}
 
-    if range_rows.len() > remaining_capacity {
-        return Err(anyhow!(
-            "Requested history range for market_id={} exceeds max_rows={} rows",
-            market_id,
-            max_rows
-        ));
+/// Like [`query_market_history_rows`], but streams the range rows from
+/// Postgres instead of collecting them. The end of the page is looked up
+/// first so the next cursor can go out with the response headers; both reads
+/// share one `REPEATABLE READ` snapshot so the cursor matches the rows sent.
+async fn stream_market_history_rows(
+    pool: &Pool,
+    market_updates_table: &str,
+    range: &HistoryRange,
+    max_rows: usize,
+) -> Result<(
+    BoxStream<'static, Result<MarketHistoryRow>>,
+    Option<HistoryCursor>,
+)> {
+    let snapshot = SnapshotClient::begin(pool).await?;
+    let client = snapshot.client();
+    let params = range.params()?;
+
+    let anchor_rows = query_history_anchor(client, market_updates_table, range, &params).await?;
+    let remaining_capacity = max_rows.saturating_sub(anchor_rows.len()) as i64;
+
+    // The last two rows of the page plus one, and how many there are.
+    let tail_sql = format!(
+        "SELECT slot, event_index, signature, count(*) OVER () AS total \
+         FROM ({}) capped \
+         ORDER BY slot DESC, event_index DESC, signature DESC \
+         LIMIT 2",
+        history_range_sql(market_updates_table, range.cursor.is_some())
+    );
+    let tail = client
+        .query(&tail_sql, &params.with_limit(&(remaining_capacity + 1)))
+        .await
+        .context("Failed to find the end of the market history page")?;
+    let has_more = tail
+        .first()
+        .is_some_and(|row| row.get::<_, i64>("total") > remaining_capacity);
+    let next_cursor = if !has_more {
+        None
+    } else if remaining_capacity == 0 {
+        anchor_rows.last().map(HistoryCursor::of)
+    } else {
+        tail.get(1).map(|row| HistoryCursor {
+            slot: row.get("slot"),
+            event_index: row.get("event_index"),
+            signature: row.get("signature"),
+        })
+    };
+
+    let range_rows = client
+        .query_raw(
+            &history_range_sql(market_updates_table, range.cursor.is_some()),
+            params.with_limit(&remaining_capacity),
+        )
+        .await
+        .context("Failed to query market history range rows")?;
+
+    // The pooled connection stays checked out until the stream is dropped,
+    // and the transaction is committed once the rows run out.
+    let range_rows = stream::unfold(
+        (Some(snapshot), Box::pin(range_rows)),
+        |(mut snapshot, mut rows)| async move {
+            let row = match rows.next().await {
+                Some(row) => row
+                    .map(|row| market_history_row_from_pg(&row))
+                    .context("Failed to read market history row"),
+                None => snapshot.take()?.commit().await.map(|()| None).transpose()?,
+            };
+            Some((row, (snapshot, rows)))
+        },
+    );
+
+    Ok((
+        stream::iter(anchor_rows.into_iter().map(Ok))
+            .chain(range_rows)
+            .boxed(),
+        next_cursor,
+    ))
+}
+
+/// A pooled connection inside a read-only `REPEATABLE READ` transaction begun
+/// by hand, so it can move into a `'static` stream. Dropped before
+/// [`SnapshotClient::commit`], the connection is detached from the pool and
+/// closed, which rolls the transaction back.
+struct SnapshotClient(Option<deadpool_postgres::Object>);
+
+impl SnapshotClient {
+    async fn begin(pool: &Pool) -> Result<Self> {
+        let client = pool.get().await.context("Failed to get DB connection")?;
+        let snapshot = Self(Some(client));
+        snapshot
+            .client()
+            .batch_execute("BEGIN ISOLATION LEVEL REPEATABLE READ READ ONLY")
+            .await
+            .context("Failed to begin snapshot transaction")?;
+        Ok(snapshot)
+    }
+
+    fn client(&self) -> &deadpool_postgres::Object {
+        self.0
+            .as_ref()
+            .expect("snapshot client is taken only on commit")
+    }
+
+    async fn commit(mut self) -> Result<()> {
+        let client = self
+            .0
+            .take()
+            .expect("snapshot client is taken only on commit");
+        client
+            .batch_execute("COMMIT")
+            .await
+            .context("Failed to commit snapshot transaction")
+    }
+}
+
+impl Drop for SnapshotClient {
+    fn drop(&mut self) {
+        if let Some(client) = self.0.take() {
+            drop(deadpool_postgres::Object::take(client));
+        }
     }
+}
 
-    if anchor_rows.is_empty() {
-        return Ok(range_rows);
+/// The anchor row of the first page; later pages continue after their cursor
+/// and have none.
+async fn query_history_anchor(
+    client: &tokio_postgres::Client,
+    market_updates_table: &str,
+    range: &HistoryRange,
+    params: &HistoryParams,
+) -> Result<Vec<MarketHistoryRow>> {
+    if range.cursor.is_some() {
+        return Ok(Vec::new());
     }
+    Ok(client
+        .query(
+            &history_anchor_sql(market_updates_table),
+            &[&params.market_id, &params.start_slot],
+        )
+        .await
+        .context("Failed to query market history anchor row")?
+        .iter()
+        .map(market_history_row_from_pg)
+        .collect())
+}
+
+/// The last event before `$2`, which sets the price at the start of a range.
+fn history_anchor_sql(market_updates_table: &str) -> String {
+    format!(
+        "SELECT event_uid, signature, event_index, slot, market_id, base_flow, quote_flow, \
+            (extract(epoch from event_time) * 1000)::bigint AS event_time_ms \
+         FROM {market_updates_table} \
+         WHERE market_id = $1 \
+           AND slot < $2 \
+           AND event_uid NOT LIKE 'debug:%' \
+         ORDER BY slot DESC, event_index DESC \
+         LIMIT 1"
+    )
+}
 
-    anchor_rows.append(&mut range_rows);
-    anchor_rows.sort_by(history_row_ordering);
-    Ok(anchor_rows)
+/// Events in `[$2, $3]`, oldest first, at most `$4`; with `after_cursor`,
+/// only those after `($5, $6, $7)`.
+fn history_range_sql(market_updates_table: &str, after_cursor: bool) -> String {
+    let cursor_filter = if after_cursor {
+        "AND (slot, event_index, signature) > ($5, $6, $7)"
+    } else {
+        ""
+    };
+    format!(
+        "SELECT event_uid, signature, event_index, slot, market_id, base_flow, quote_flow, \
+            (extract(epoch from event_time) * 1000)::bigint AS event_time_ms \
+         FROM {market_updates_table} \
+         WHERE market_id = $1 \
+           AND slot >= $2 \
+           AND slot <= $3 \
+           AND event_uid NOT LIKE 'debug:%' \
+           {cursor_filter} \
+         ORDER BY slot ASC, event_index ASC, signature ASC \
+         LIMIT $4"
+    )
 }
 
 async fn query_market_updates_rows(
@@ -1175,12 +2176,6 @@ fn market_history_row_from_pg(row: &tokio_postgres::Row) -> MarketHistoryRow {
     }
 }
 
-fn history_row_ordering(left: &MarketHistoryRow, right: &MarketHistoryRow) -> std::cmp::Ordering {
-    left.slot
-        .cmp(&right.slot)
-        .then(left.event_index.cmp(&right.event_index))
-}
-
 fn market_history_item_from_row(row: MarketHistoryRow) -> Result<MarketHistoryItem, ApiError> {
     let created_at =
         DateTime::<Utc>::from_timestamp_millis(row.event_time_ms).ok_or_else(|| {
@@ -1252,12 +2247,20 @@ async fn query_market_configs(pool: &Pool, market_id: Option<u64>) -> Result<Vec
         .collect()
 }
 
+/// Optional filters of `/closed-positions`; `from_slot`/`to_slot` are
+/// inclusive.
+struct ClosedPositionFilter {
+    market_id: Option<u64>,
+    before_slot: Option<u64>,
+    from_slot: Option<u64>,
+    to_slot: Option<u64>,
+}
+
 async fn query_closed_position_rows(
     pool: &Pool,
     close_position_events_table: &str,
     authority: &str,
-    market_id: Option<u64>,
-    before_slot: Option<u64>,
+    filter: &ClosedPositionFilter,
     limit: usize,
 ) -> Result<Vec<ClosedPositionRow>> {
     if limit == 0 {
@@ -1280,19 +2283,33 @@ async fn query_closed_position_rows(
     let mut predicates = String::from("WHERE position_authority = $1");
 
     let market_id_i64;
-    if let Some(market_id) = market_id {
+    if let Some(market_id) = filter.market_id {
         market_id_i64 = i64::try_from(market_id).context("market_id out of range")?;
         params.push(&market_id_i64);
         predicates.push_str(&format!(" AND market_id = ${}", params.len()));
     }
 
     let before_slot_i64;
-    if let Some(before_slot) = before_slot {
+    if let Some(before_slot) = filter.before_slot {
         before_slot_i64 = i64::try_from(before_slot).context("before_slot out of range")?;
         params.push(&before_slot_i64);
         predicates.push_str(&format!(" AND slot < ${}", params.len()));
     }
 
+    let from_slot_i64;
+    if let Some(from_slot) = filter.from_slot {
+        from_slot_i64 = i64::try_from(from_slot).context("from_slot out of range")?;
+        params.push(&from_slot_i64);
+        predicates.push_str(&format!(" AND slot >= ${}", params.len()));
+    }
+
+    let to_slot_i64;
+    if let Some(to_slot) = filter.to_slot {
+        to_slot_i64 = i64::try_from(to_slot).context("to_slot out of range")?;
+        params.push(&to_slot_i64);
+        predicates.push_str(&format!(" AND slot <= ${}", params.len()));
+    }
+
     params.push(&limit_i64);
     let sql = format!(
         "{select_columns} {predicates} \
@@ -1306,33 +2323,35 @@ async fn query_closed_position_rows(
         .await
         .context("Failed to query closed position rows")?;
 
-    Ok(pg_rows
-        .iter()
-        .map(|row| ClosedPositionRow {
-            signature: row.get("signature"),
-            event_index: row.get("event_index"),
-            slot: row.get("slot"),
-            market_id: row.get("market_id"),
-            start_slot: row.get("start_slot"),
-            end_slot: row.get("end_slot"),
-            deposit_amount: row.get("deposit_amount"),
-            swapped_amount: row.get("swapped_amount"),
-            remaining_amount: row.get("remaining_amount"),
-            fee_amount: row.get("fee_amount"),
-            is_buy: row.get("is_buy"),
-            event_time_ms: row.get("event_time_ms"),
-        })
-        .collect())
+    Ok(pg_rows.iter().map(closed_position_row_from_pg).collect())
+}
+
+fn closed_position_row_from_pg(row: &tokio_postgres::Row) -> ClosedPositionRow {
+    ClosedPositionRow {
+        signature: row.get("signature"),
+        event_index: row.get("event_index"),
+        slot: row.get("slot"),
+        market_id: row.get("market_id"),
+        start_slot: row.get("start_slot"),
+        end_slot: row.get("end_slot"),
+        deposit_amount: row.get("deposit_amount"),
+        swapped_amount: row.get("swapped_amount"),
+        remaining_amount: row.get("remaining_amount"),
+        fee_amount: row.get("fee_amount"),
+        is_buy: row.get("is_buy"),
+        event_time_ms: row.get("event_time_ms"),
+    }
 }
 
 fn closed_position_item_from_row(row: ClosedPositionRow) -> Result<ClosedPositionItem, ApiError> {
-    let event_time = DateTime::<Utc>::from_timestamp_millis(row.event_time_ms).ok_or_else(|| {
-        ApiError::internal(anyhow!(
-            "Invalid event_time_ms {} for signature={}",
-            row.event_time_ms,
-            row.signature
-        ))
-    })?;
+    let event_time =
+        DateTime::<Utc>::from_timestamp_millis(row.event_time_ms).ok_or_else(|| {
+            ApiError::internal(anyhow!(
+                "Invalid event_time_ms {} for signature={}",
+                row.event_time_ms,
+                row.signature
+            ))
+        })?;
 
     let event_index = u16::try_from(row.event_index).map_err(|_| {
         ApiError::internal(anyhow!(
@@ -1358,78 +2377,43 @@ fn closed_position_item_from_row(row: ClosedPositionRow) -> Result<ClosedPositio
     })
 }
 
-impl MarketPriceStreams {
-    fn new(pool: Pool, market_updates_table: String, poll_interval: Duration) -> Self {
-        Self {
-            channels: Arc::new(RwLock::new(HashMap::new())),
-            runtime: PriceStreamRuntime {
-                pool,
-                market_updates_table,
-            },
-            poll_interval,
-        }
+/// Base and quote atoms a closed trade position exchanged, `(base, quote)`.
+/// A buy spends quote for base and a sell spends base for quote; the unspent
+/// `remaining_amount` is refunded.
+fn position_fill(row: &ClosedPositionRow) -> (i64, i64) {
+    let spent = row
+        .deposit_amount
+        .saturating_sub(row.remaining_amount)
+        .max(0);
+    let received = row.swapped_amount.max(0);
+    if row.is_buy {
+        (received, spent)
+    } else {
+        (spent, received)
     }
+}
 
-    async fn subscribe(&self, market_id: u64) -> broadcast::Receiver<LatestPriceResponse> {
-        {
-            let channels = self.channels.read().await;
-            if let Some(sender) = channels.get(&market_id) {
-                return sender.subscribe();
-            }
-        }
-
-        let mut channels = self.channels.write().await;
-        if let Some(sender) = channels.get(&market_id) {
-            return sender.subscribe();
-        }
-
-        let (sender, _receiver) = broadcast::channel::<LatestPriceResponse>(512);
-        let runtime = self.runtime.clone();
-        let sender_for_task = sender.clone();
-        let poll_interval = self.poll_interval;
-        tokio::spawn(async move {
-            run_market_price_stream(runtime, market_id, sender_for_task, poll_interval).await;
-        });
+/// Raw token atoms as whole tokens; `None` for decimals outside `0..=28`.
+fn atoms_to_units(amount: i64, decimals: i16) -> Option<Decimal> {
+    let scale = u32::try_from(decimals).ok().filter(|scale| *scale <= 28)?;
+    Some(Decimal::from_i128_with_scale(i128::from(amount), scale).normalize())
+}
 
-        channels.insert(market_id, sender.clone());
-        sender.subscribe()
-    }
+/// A `numeric` column selected as `::text`. Decoding a `numeric` that does not
+/// fit a `Decimal` overflows inside `rust_decimal`, so u128 amounts are read
+/// as text instead: `None` for SQL `NULL` or a value out of range.
+fn has_more_header(has_more: bool) -> HeaderValue {
+    HeaderValue::from_static(if has_more { "true" } else { "false" })
 }
 
-async fn run_market_price_stream(
-    runtime: PriceStreamRuntime,
-    market_id: u64,
-    sender: broadcast::Sender<LatestPriceResponse>,
-    poll_interval: Duration,
-) {
-    let mut ticker = tokio::time::interval(poll_interval);
-    ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
-    let mut latest_snapshot_key: Option<(i64, u64)> = None;
-
-    loop {
-        match fetch_latest_price_snapshot(&runtime.pool, &runtime.market_updates_table, market_id)
-            .await
-        {
-            Ok(Some(snapshot)) => {
-                let snapshot_key = (snapshot.event_time_ms, snapshot.slot);
-                let is_newer = latest_snapshot_key
-                    .map(|latest_key| snapshot_key > latest_key)
-                    .unwrap_or(true);
-                if is_newer {
-                    latest_snapshot_key = Some(snapshot_key);
-                    let _ = sender.send(snapshot);
-                }
-            }
-            Ok(None) => {}
-            Err(error) => {
-                eprintln!(
-                    "Price stream polling error for market_id={}: {:#}",
-                    market_id, error
-                );
-            }
+fn numeric_text(row: &tokio_postgres::Row, column: &str) -> Option<Decimal> {
+    let text: String = row.get::<_, Option<String>>(column)?;
+    match text.parse::<Decimal>() {
+        Ok(value) => Some(value),
+        Err(error) => {
+            eprintln!("{column} {text} does not fit a decimal: {error}");
+            None
         }
-
-        ticker.tick().await;
     }
 }
 
@@ -1455,6 +2439,19 @@ fn parse_u64_env(key: &str, default_value: u64) -> Result<u64> {
     }
 }
 
+fn parse_bool_env(key: &str, default_value: bool) -> Result<bool> {
+    match env::var(key) {
+        Ok(raw) => match raw.trim().to_ascii_lowercase().as_str() {
+            "" => Ok(default_value),
+            "1" | "true" | "on" | "yes" => Ok(true),
+            "0" | "false" | "off" | "no" => Ok(false),
+            other => Err(anyhow!("{key} must be a boolean, got '{other}'")),
+        },
+        Err(env::VarError::NotPresent) => Ok(default_value),
+        Err(error) => Err(anyhow!("Failed to read {key}: {error}")),
+    }
+}
+
 fn resolve_bind_addr() -> Result<String> {
     if let Ok(value) = env::var("READ_API_BIND_ADDR") {
         let trimmed = value.trim();
@@ -1473,23 +2470,6 @@ fn resolve_bind_addr() -> Result<String> {
     Ok(DEFAULT_BIND_ADDR.to_string())
 }
 
-fn validate_table_name(table: &str) -> Result<String> {
-    let trimmed = table.trim();
-    if trimmed.is_empty() {
-        return Err(anyhow!("Table name must not be empty"));
-    }
-    if !is_safe_identifier(trimmed) {
-        return Err(anyhow!("Unsafe table identifier: {trimmed}"));
-    }
-    Ok(trimmed.to_string())
-}
-
-fn is_safe_identifier(value: &str) -> bool {
-    value
-        .chars()
-        .all(|character| character.is_ascii_alphanumeric() || character == '_' || character == '.')
-}
-
 async fn ensure_table_exists(client: &tokio_postgres::Client, table: &str) -> Result<()> {
     let row = client
         .query_one("SELECT to_regclass($1) IS NOT NULL", &[&table])
@@ -1513,3 +2493,88 @@ fn is_valid_chart_price(value: Decimal) -> bool {
         None => false,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn closed_position(
+        deposit: i64,
+        swapped: i64,
+        remaining: i64,
+        is_buy: bool,
+    ) -> ClosedPositionRow {
+        ClosedPositionRow {
+            signature: "sig".to_string(),
+            event_index: 0,
+            slot: 1,
+            market_id: 1,
+            start_slot: 0,
+            end_slot: 1,
+            deposit_amount: deposit,
+            swapped_amount: swapped,
+            remaining_amount: remaining,
+            fee_amount: 0,
+            is_buy,
+            event_time_ms: 0,
+        }
+    }
+
+    #[test]
+    fn test_position_fill_sides() {
+        assert_eq!(position_fill(&closed_position(100, 40, 20, true)), (40, 80));
+        assert_eq!(
+            position_fill(&closed_position(100, 40, 20, false)),
+            (80, 40)
+        );
+    }
+
+    #[test]
+    fn test_atoms_to_units() {
+        assert_eq!(atoms_to_units(1_500_000, 6), Some(Decimal::new(15, 1)));
+        assert_eq!(atoms_to_units(7, 0), Some(Decimal::from(7)));
+        assert_eq!(atoms_to_units(7, -1), None);
+        assert_eq!(atoms_to_units(7, 29), None);
+    }
+
+    #[test]
+    fn test_history_cursor_round_trip() {
+        let cursor = HistoryCursor {
+            slot: 120,
+            event_index: 3,
+            signature: "5xSig".to_string(),
+        };
+        assert_eq!(HistoryCursor::decode(&cursor.encode()), Some(cursor));
+        assert_eq!(HistoryCursor::decode("not base64!"), None);
+        assert_eq!(
+            HistoryCursor::decode(&URL_SAFE_NO_PAD.encode("120:3")),
+            None
+        );
+        assert_eq!(
+            HistoryCursor::decode(&URL_SAFE_NO_PAD.encode("-1:3:sig")),
+            None
+        );
+    }
+
+    #[test]
+    fn test_parse_price_event_id() {
+        let id = |slot, event_index, signature: &str| PriceEventId {
+            slot,
+            event_index,
+            signature: signature.to_string(),
+        };
+        assert_eq!(
+            PriceEventId::parse("120:3:5xSig"),
+            Some(id(120, 3, "5xSig"))
+        );
+        assert_eq!(PriceEventId::parse(" 7:0 "), Some(id(7, 0, "")));
+        assert_eq!(PriceEventId::parse("120"), None);
+        assert_eq!(PriceEventId::parse("120:70000:sig"), None);
+        assert_eq!(PriceEventId::parse("-1:0:sig"), None);
+        // Updates of two transactions in one slot stay ordered and distinct.
+        assert!(id(120, 0, "B") > id(120, 0, "A"));
+        assert!(id(120, 0, "a") > id(120, 0, "B"));
+        assert!(id(120, 1, "A") > id(120, 0, "B"));
+        assert!(id(120, 0, "A") > id(120, 0, ""));
+    }
+}
@@ -0,0 +1,307 @@
+//! Open TWAP positions from the `trade_positions` table kept by
+//! `account-indexer`: `/v1/authorities/{authority}/open-positions` and
+//! `/v1/markets/{market_id}/open-positions`.
+//!
+//! Progress and time to expiry are measured against the estimated current
+//! slot (see [`slots`](crate::slots)). Positions past `end_slot` stay listed as
+//! `expired` until they are closed; a close shows up as soon as the event
+//! keeper has indexed its close-position event.
+
+use anyhow::{Context, Result, anyhow};
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+};
+use chrono::{SecondsFormat, Utc};
+use deadpool_postgres::Pool;
+use rust_decimal::Decimal;
+use std::sync::Arc;
+use twob_keepers::api::{
+    AuthorityOpenPositionsQuery, ErrorResponse, MarketOpenPositionsQuery, OpenPositionItem,
+    OpenPositionsResponse, PositionProgress,
+};
+
+use crate::{
+    ABSOLUTE_MAX_UPDATES_LIMIT, ApiError, AppState, DEFAULT_UPDATES_LIMIT, query_market_configs,
+    slots::{SlotClock, query_slot_clock},
+};
+
+struct OpenPositionRow {
+    address: String,
+    market_id: i64,
+    authority: String,
+    position_id: Decimal,
+    is_buy: bool,
+    amount: Decimal,
+    amount_units: Option<Decimal>,
+    start_slot: i64,
+    end_slot: i64,
+    observed_slot: i64,
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/authorities/{authority}/open-positions",
+    params(
+        ("authority" = String, Path, description = "Wallet address"),
+        AuthorityOpenPositionsQuery,
+    ),
+    responses(
+        (status = 200, body = OpenPositionsResponse),
+        (status = 400, body = ErrorResponse),
+    )
+)]
+pub(crate) async fn get_authority_open_positions(
+    State(state): State<Arc<AppState>>,
+    Path(authority): Path<String>,
+    Query(query): Query<AuthorityOpenPositionsQuery>,
+) -> Result<Json<OpenPositionsResponse>, ApiError> {
+    let limit = parse_limit(query.limit)?;
+    let market_id = query
+        .market_id
+        .map(i64::try_from)
+        .transpose()
+        .map_err(|_| ApiError::bad_request("market_id out of range"))?;
+
+    let response = open_positions_response(&state, Some(&authority), market_id, limit).await?;
+    Ok(Json(OpenPositionsResponse {
+        authority: Some(authority),
+        market_id: query.market_id,
+        ..response
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/markets/{market_id}/open-positions",
+    params(("market_id" = u64, Path, description = "Market id"), MarketOpenPositionsQuery),
+    responses(
+        (status = 200, body = OpenPositionsResponse),
+        (status = 400, body = ErrorResponse),
+        (status = 404, body = ErrorResponse),
+    )
+)]
+pub(crate) async fn get_market_open_positions(
+    State(state): State<Arc<AppState>>,
+    Path(market_id): Path<u64>,
+    Query(query): Query<MarketOpenPositionsQuery>,
+) -> Result<Json<OpenPositionsResponse>, ApiError> {
+    let limit = parse_limit(query.limit)?;
+    let configs = query_market_configs(&state.pool, Some(market_id))
+        .await
+        .map_err(|error| ApiError::internal(error.context("Failed to query market config")))?;
+    if configs.is_empty() {
+        return Err(ApiError::not_found(format!(
+            "No config for market_id={market_id}"
+        )));
+    }
+    let market_id_i64 =
+        i64::try_from(market_id).map_err(|_| ApiError::bad_request("market_id out of range"))?;
+
+    let response = open_positions_response(&state, None, Some(market_id_i64), limit).await?;
+    Ok(Json(OpenPositionsResponse {
+        market_id: Some(market_id),
+        ..response
+    }))
+}
+
+fn parse_limit(limit: Option<usize>) -> Result<usize, ApiError> {
+    let limit = limit.unwrap_or(DEFAULT_UPDATES_LIMIT);
+    if limit == 0 || limit > ABSOLUTE_MAX_UPDATES_LIMIT {
+        return Err(ApiError::bad_request(format!(
+            "limit must be between 1 and {ABSOLUTE_MAX_UPDATES_LIMIT}"
+        )));
+    }
+    Ok(limit)
+}
+
+async fn open_positions_response(
+    state: &AppState,
+    authority: Option<&str>,
+    market_id: Option<i64>,
+    limit: usize,
+) -> Result<OpenPositionsResponse, ApiError> {
+    let mut rows = query_open_positions(
+        &state.pool,
+        &state.config.close_position_events_table,
+        authority,
+        market_id,
+        limit as i64 + 1,
+    )
+    .await
+    .map_err(|error| ApiError::internal(error.context("Failed to query open positions")))?;
+    let has_more = rows.len() > limit;
+    rows.truncate(limit);
+
+    let clock = query_slot_clock(&state.pool, &state.config.market_updates_table)
+        .await
+        .map_err(|error| ApiError::internal(error.context("Failed to estimate current slot")))?;
+    let now = Utc::now();
+    let current_slot = clock.map(|clock| clock.slot_at(now));
+
+    let items = rows
+        .into_iter()
+        .map(|row| open_position_item(row, clock, current_slot))
+        .collect::<Result<Vec<_>>>()
+        .map_err(|error| ApiError::internal(error.context("Invalid open position row")))?;
+
+    Ok(OpenPositionsResponse {
+        authority: None,
+        market_id: None,
+        current_slot,
+        has_more,
+        limit,
+        points: items.len(),
+        items,
+    })
+}
+
+fn open_position_item(
+    row: OpenPositionRow,
+    clock: Option<SlotClock>,
+    current_slot: Option<u64>,
+) -> Result<OpenPositionItem> {
+    let market_id = u64::try_from(row.market_id)
+        .map_err(|_| anyhow!("market_id out of range: {}", row.market_id))?;
+    let start_slot = row.start_slot.max(0) as u64;
+    let end_slot = row.end_slot.max(0) as u64;
+    let progress = clock
+        .zip(current_slot)
+        .map(|(clock, current_slot)| position_progress(&clock, current_slot, start_slot, end_slot));
+
+    Ok(OpenPositionItem {
+        address: row.address,
+        market_id,
+        authority: row.authority,
+        position_id: row.position_id.to_string(),
+        is_buy: row.is_buy,
+        amount: row.amount.to_string(),
+        amount_units: row.amount_units.map(|units| units.normalize()),
+        start_slot,
+        end_slot,
+        observed_slot: row.observed_slot.max(0) as u64,
+        progress,
+    })
+}
+
+fn position_progress(
+    clock: &SlotClock,
+    current_slot: u64,
+    start_slot: u64,
+    end_slot: u64,
+) -> PositionProgress {
+    let status = if current_slot < start_slot {
+        "pending"
+    } else if current_slot < end_slot {
+        "active"
+    } else {
+        "expired"
+    };
+    let progress = if end_slot <= start_slot || current_slot >= end_slot {
+        Decimal::ONE
+    } else if current_slot <= start_slot {
+        Decimal::ZERO
+    } else {
+        (Decimal::from(current_slot - start_slot) / Decimal::from(end_slot - start_slot))
+            .round_dp(4)
+    };
+    let slots_remaining = end_slot.saturating_sub(current_slot);
+
+    PositionProgress {
+        status: status.to_string(),
+        progress,
+        slots_remaining,
+        seconds_to_expiry: slots_remaining.saturating_mul(crate::slots::SLOT_DURATION_MS as u64)
+            / 1000,
+        estimated_end_time: clock
+            .time_of(end_slot)
+            .to_rfc3339_opts(SecondsFormat::Secs, true),
+    }
+}
+
+/// Rows of `trade_positions`, minus those a close-position event at or after
+/// `observed_slot` already closed: `account-indexer` only drops closed accounts
+/// on its next scan, while the event keeper sees the close right away. The
+/// event carries no position id, so it is matched on market, authority, side
+/// and slot window.
+async fn query_open_positions(
+    pool: &Pool,
+    close_position_events_table: &str,
+    authority: Option<&str>,
+    market_id: Option<i64>,
+    limit: i64,
+) -> Result<Vec<OpenPositionRow>> {
+    let sql = format!(
+        "SELECT p.address, p.market_id, p.authority, p.position_id, p.is_buy, p.amount, \
+                p.start_slot, p.end_slot, p.observed_slot, \
+                p.amount / power(10::numeric, \
+                    (CASE WHEN p.is_buy THEN mc.quote_decimals ELSE mc.base_decimals END)::numeric \
+                ) AS amount_units \
+         FROM trade_positions p \
+         LEFT JOIN market_configs mc ON mc.market_id = p.market_id \
+         WHERE ($1::text IS NULL OR p.authority = $1) \
+           AND ($2::bigint IS NULL OR p.market_id = $2) \
+           AND NOT EXISTS ( \
+                SELECT 1 FROM {close_position_events_table} c \
+                WHERE c.market_id = p.market_id \
+                  AND c.position_authority = p.authority \
+                  AND c.is_buy = p.is_buy \
+                  AND c.start_slot = p.start_slot \
+                  AND c.end_slot = p.end_slot \
+                  AND c.slot >= p.observed_slot \
+           ) \
+         ORDER BY p.end_slot ASC, p.address ASC \
+         LIMIT $3"
+    );
+
+    let client = pool.get().await.context("Failed to get DB connection")?;
+    let rows = client
+        .query(&sql, &[&authority, &market_id, &limit])
+        .await
+        .context("Failed to query trade positions")?;
+
+    Ok(rows
+        .iter()
+        .map(|row| OpenPositionRow {
+            address: row.get("address"),
+            market_id: row.get("market_id"),
+            authority: row.get("authority"),
+            position_id: row.get("position_id"),
+            is_buy: row.get("is_buy"),
+            amount: row.get("amount"),
+            amount_units: row.get("amount_units"),
+            start_slot: row.get("start_slot"),
+            end_slot: row.get("end_slot"),
+            observed_slot: row.get("observed_slot"),
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::slots::tests::clock;
+
+    #[test]
+    fn test_position_progress_phases() {
+        let clock = clock();
+
+        let pending = position_progress(&clock, 1_000, 1_100, 1_300);
+        assert_eq!(pending.status, "pending");
+        assert_eq!(pending.progress, Decimal::ZERO);
+        assert_eq!(pending.slots_remaining, 300);
+        assert_eq!(pending.seconds_to_expiry, 120);
+        assert_eq!(pending.estimated_end_time, "2025-01-01T00:02:00Z");
+
+        let active = position_progress(&clock, 1_150, 1_100, 1_300);
+        assert_eq!(active.status, "active");
+        assert_eq!(active.progress, Decimal::new(25, 2));
+        assert_eq!(active.slots_remaining, 150);
+
+        let expired = position_progress(&clock, 1_400, 1_100, 1_300);
+        assert_eq!(expired.status, "expired");
+        assert_eq!(expired.progress, Decimal::ONE);
+        assert_eq!(expired.slots_remaining, 0);
+        assert_eq!(expired.seconds_to_expiry, 0);
+    }
+}
@@ -0,0 +1,77 @@
+//! OpenAPI document of the JSON endpoints: `/openapi.json`.
+//!
+//! The schemas come from `twob_keepers::api`, which `twob_keepers::client`
+//! decodes as well. Streams, the WebSocket and CSV/Parquet exports are
+//! described in the README only.
+
+use axum::Json;
+use utoipa::OpenApi;
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "read-api",
+        description = "Market data, history and positions indexed from the TwoB program."
+    ),
+    paths(
+        crate::healthz,
+        crate::readiness::readyz,
+        crate::get_storage_status,
+        crate::list_market_configs,
+        crate::get_market_config,
+        crate::get_latest_price,
+        crate::get_candles,
+        crate::get_market_history,
+        crate::get_market_updates,
+        crate::get_market_state,
+        crate::get_closed_position_mini_chart,
+        crate::get_closed_positions,
+        crate::slots::get_slot_time,
+        crate::slots::get_time_slot,
+        crate::tickers::list_tickers,
+        crate::tickers::get_market_ticker,
+        crate::open_positions::get_authority_open_positions,
+        crate::open_positions::get_market_open_positions,
+        crate::liquidity::get_authority_liquidity_positions,
+        crate::liquidity::get_market_liquidity_positions,
+        crate::portfolio::get_authority_summary,
+        crate::execution::get_authority_execution,
+        crate::execution::get_market_execution,
+        crate::flow_schedule::get_flow_schedule,
+        crate::fees::get_fees,
+        crate::access::get_usage,
+        crate::aggregator::list_pairs,
+        crate::aggregator::list_tickers,
+        crate::aggregator::get_historical_trades,
+        crate::aggregator::get_liquidity,
+    )
+)]
+struct ApiDoc;
+
+pub(crate) async fn get_openapi() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_document_references_known_schemas() {
+        let document = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        assert!(document["paths"]["/v1/markets/{market_id}/history"]["get"].is_object());
+        assert!(document["paths"]["/v1/fees"]["get"].is_object());
+        assert!(document["paths"]["/v1/aggregator/historical_trades"]["get"].is_object());
+
+        // Every `$ref` must resolve to a component, including nested ones
+        // such as `MarketHistoryResponse.items`.
+        let schemas = &document["components"]["schemas"];
+        let text = document.to_string();
+        for reference in text.split("\"#/components/schemas/").skip(1) {
+            let name = reference.split('"').next().unwrap();
+            assert!(schemas[name].is_object(), "missing schema {name}");
+        }
+        assert!(schemas["HypertableStats"].is_object());
+        assert!(schemas["PositionProgress"].is_object());
+    }
+}
@@ -0,0 +1,366 @@
+//! `/v1/authorities/{authority}/summary`: a wallet's closed trade positions
+//! aggregated per market and side, in token units.
+//!
+//! Each position is also compared with the market: its *market price* is the
+//! TWAP over the position's `[start_slot, end_slot)` window, built from the
+//! per-slot flows of the market updates active in that window.
+
+use anyhow::{Context, Result};
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+};
+use chrono::{DateTime, Utc};
+use deadpool_postgres::Pool;
+use rust_decimal::Decimal;
+use std::{collections::BTreeMap, sync::Arc};
+use twob_keepers::api::{ErrorResponse, MarketSummary, SideSummary, SummaryQuery, SummaryResponse};
+
+use crate::{
+    ApiError, AppState, ClosedPositionRow, MarketConfig, atoms_to_units,
+    closed_position_row_from_pg, position_fill, query_market_configs,
+};
+
+/// Most recent positions folded into one summary.
+const MAX_SUMMARY_POSITIONS: i64 = 10_000;
+
+/// A closed trade position and the market TWAP over its window, in quote
+/// atoms per base atom (`None` when no update covers the window).
+pub(crate) struct PositionFill {
+    pub(crate) row: ClosedPositionRow,
+    pub(crate) market_twap: Option<Decimal>,
+}
+
+/// A [`SideSummary`] being accumulated, with the running sums behind its
+/// market average price.
+#[derive(Default)]
+struct SideTotals {
+    summary: SideSummary,
+    weighted_market_price: Decimal,
+    weighted_base_volume: Decimal,
+}
+
+/// A [`MarketSummary`] being accumulated.
+struct MarketTotals {
+    base_ticker: Option<String>,
+    quote_ticker: Option<String>,
+    buy: SideTotals,
+    sell: SideTotals,
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/authorities/{authority}/summary",
+    params(
+        ("authority" = String, Path, description = "Wallet address"),
+        SummaryQuery,
+    ),
+    responses(
+        (status = 200, body = SummaryResponse),
+        (status = 400, body = ErrorResponse),
+    )
+)]
+pub(crate) async fn get_authority_summary(
+    State(state): State<Arc<AppState>>,
+    Path(authority): Path<String>,
+    Query(query): Query<SummaryQuery>,
+) -> Result<Json<SummaryResponse>, ApiError> {
+    let market_id = query
+        .market_id
+        .map(i64::try_from)
+        .transpose()
+        .map_err(|_| ApiError::bad_request("market_id out of range"))?;
+
+    let mut fills = query_position_fills(
+        &state.pool,
+        &state.config.close_position_events_table,
+        &state.config.market_updates_table,
+        &PositionFilter {
+            authority: Some(&authority),
+            market_id,
+            ..PositionFilter::default()
+        },
+        MAX_SUMMARY_POSITIONS + 1,
+    )
+    .await
+    .map_err(|error| ApiError::internal(error.context("Failed to query position fills")))?;
+    let truncated = fills.len() as i64 > MAX_SUMMARY_POSITIONS;
+    fills.truncate(MAX_SUMMARY_POSITIONS as usize);
+
+    let configs = query_market_configs(&state.pool, None)
+        .await
+        .map_err(|error| ApiError::internal(error.context("Failed to query market configs")))?;
+
+    let mut markets: BTreeMap<u64, MarketTotals> = BTreeMap::new();
+    let (mut buy_count, mut sell_count) = (0, 0);
+    for fill in &fills {
+        let market_id = fill.row.market_id.max(0) as u64;
+        let Some(config) = configs.iter().find(|config| config.market_id == market_id) else {
+            continue;
+        };
+        let Some(amounts) = FillUnits::new(fill, config) else {
+            continue;
+        };
+
+        let market = markets.entry(market_id).or_insert_with(|| MarketTotals {
+            base_ticker: config.base_ticker.clone(),
+            quote_ticker: config.quote_ticker.clone(),
+            buy: SideTotals::default(),
+            sell: SideTotals::default(),
+        });
+        if fill.row.is_buy {
+            buy_count += 1;
+            market.buy.add(&amounts);
+        } else {
+            sell_count += 1;
+            market.sell.add(&amounts);
+        }
+    }
+
+    let markets: Vec<MarketSummary> = markets
+        .into_iter()
+        .map(|(market_id, market)| MarketSummary {
+            market_id,
+            base_ticker: market.base_ticker,
+            quote_ticker: market.quote_ticker,
+            buy: market.buy.finish(),
+            sell: market.sell.finish(),
+        })
+        .collect();
+
+    Ok(Json(SummaryResponse {
+        authority,
+        market_id: query.market_id,
+        position_count: buy_count + sell_count,
+        buy_count,
+        sell_count,
+        truncated,
+        markets,
+    }))
+}
+
+/// One position's amounts in token units.
+struct FillUnits {
+    deposited: Decimal,
+    swapped: Decimal,
+    remaining: Decimal,
+    fees: Decimal,
+    base: Decimal,
+    quote: Decimal,
+    /// Market TWAP in quote per base.
+    market_price: Option<Decimal>,
+}
+
+impl FillUnits {
+    fn new(fill: &PositionFill, config: &MarketConfig) -> Option<Self> {
+        let row = &fill.row;
+        let base_decimals = config.base_decimals?;
+        let quote_decimals = config.quote_decimals?;
+        let (spent_decimals, received_decimals) = if row.is_buy {
+            (quote_decimals, base_decimals)
+        } else {
+            (base_decimals, quote_decimals)
+        };
+        let (base_atoms, quote_atoms) = position_fill(row);
+
+        Some(Self {
+            deposited: atoms_to_units(row.deposit_amount, spent_decimals)?,
+            swapped: atoms_to_units(row.swapped_amount, received_decimals)?,
+            remaining: atoms_to_units(row.remaining_amount, spent_decimals)?,
+            fees: atoms_to_units(row.fee_amount, received_decimals)?,
+            base: atoms_to_units(base_atoms, base_decimals)?,
+            quote: atoms_to_units(quote_atoms, quote_decimals)?,
+            market_price: fill
+                .market_twap
+                .map(|twap| atoms_price_to_units(twap, base_decimals, quote_decimals)),
+        })
+    }
+}
+
+impl SideTotals {
+    fn add(&mut self, fill: &FillUnits) {
+        let summary = &mut self.summary;
+        summary.count += 1;
+        summary.deposited += fill.deposited;
+        summary.swapped += fill.swapped;
+        summary.remaining += fill.remaining;
+        summary.fees += fill.fees;
+        summary.base_volume += fill.base;
+        summary.quote_volume += fill.quote;
+        if let Some(market_price) = fill.market_price {
+            self.weighted_market_price += market_price * fill.base;
+            self.weighted_base_volume += fill.base;
+        }
+    }
+
+    fn finish(self) -> SideSummary {
+        let mut summary = self.summary;
+        summary.average_execution_price = (!summary.base_volume.is_zero()).then(|| {
+            (summary.quote_volume / summary.base_volume)
+                .round_dp(12)
+                .normalize()
+        });
+        summary.market_average_price = (!self.weighted_base_volume.is_zero()).then(|| {
+            (self.weighted_market_price / self.weighted_base_volume)
+                .round_dp(12)
+                .normalize()
+        });
+        summary
+    }
+}
+
+/// Quote atoms per base atom as quote per base in token units.
+pub(crate) fn atoms_price_to_units(
+    price: Decimal,
+    base_decimals: i16,
+    quote_decimals: i16,
+) -> Decimal {
+    let mut scaled = price;
+    let exponent = i32::from(base_decimals) - i32::from(quote_decimals);
+    for _ in 0..exponent.unsigned_abs() {
+        if exponent > 0 {
+            scaled *= Decimal::TEN;
+        } else {
+            scaled /= Decimal::TEN;
+        }
+    }
+    scaled
+}
+
+/// Which closed positions [`query_position_fills`] reads; `None` fields do not
+/// filter.
+#[derive(Default)]
+pub(crate) struct PositionFilter<'a> {
+    pub(crate) authority: Option<&'a str>,
+    pub(crate) market_id: Option<i64>,
+    pub(crate) before_slot: Option<i64>,
+    pub(crate) from: Option<DateTime<Utc>>,
+    pub(crate) to: Option<DateTime<Utc>>,
+}
+
+/// Closed positions matching `filter`, newest first, each with the market
+/// TWAP over its window.
+pub(crate) async fn query_position_fills(
+    pool: &Pool,
+    close_position_events_table: &str,
+    market_updates_table: &str,
+    filter: &PositionFilter<'_>,
+    limit: i64,
+) -> Result<Vec<PositionFill>> {
+    // The flow active at `start_slot` comes from the last update before it;
+    // every update inside the window then runs until the next one or the
+    // window end.
+    let sql = format!(
+        "SELECT p.signature, p.event_index, p.slot, p.market_id, p.start_slot, \
+                p.end_slot, p.deposit_amount, p.swapped_amount, p.remaining_amount, \
+                p.fee_amount, p.is_buy, \
+                (extract(epoch from p.event_time) * 1000)::bigint AS event_time_ms, \
+                twap.price AS market_twap \
+         FROM {close_position_events_table} p \
+         LEFT JOIN LATERAL ( \
+            SELECT sum(seg.quote_flow::numeric * seg.slots) \
+                     / nullif(sum(seg.base_flow::numeric * seg.slots), 0) AS price \
+            FROM ( \
+                SELECT u.base_flow, u.quote_flow, \
+                       greatest( \
+                           least(coalesce(lead(u.slot) OVER w, p.end_slot), p.end_slot) \
+                             - greatest(u.slot, p.start_slot), \
+                           0) AS slots \
+                FROM ( \
+                    (SELECT r.slot, r.event_index, r.base_flow, r.quote_flow \
+                     FROM {market_updates_table} r \
+                     WHERE r.market_id = p.market_id AND r.slot < p.start_slot \
+                       AND r.event_uid NOT LIKE 'debug:%' \
+                     ORDER BY r.slot DESC, r.event_index DESC LIMIT 1) \
+                    UNION ALL \
+                    (SELECT r.slot, r.event_index, r.base_flow, r.quote_flow \
+                     FROM {market_updates_table} r \
+                     WHERE r.market_id = p.market_id \
+                       AND r.slot >= p.start_slot AND r.slot < p.end_slot \
+                       AND r.event_uid NOT LIKE 'debug:%') \
+                ) u \
+                WINDOW w AS (ORDER BY u.slot, u.event_index) \
+            ) seg \
+         ) twap ON true \
+         WHERE ($1::text IS NULL OR p.position_authority = $1) \
+           AND ($2::bigint IS NULL OR p.market_id = $2) \
+           AND ($3::bigint IS NULL OR p.slot < $3) \
+           AND ($4::timestamptz IS NULL OR p.event_time >= $4) \
+           AND ($5::timestamptz IS NULL OR p.event_time < $5) \
+         ORDER BY p.event_time DESC, p.slot DESC, p.event_index DESC \
+         LIMIT $6"
+    );
+
+    let client = pool.get().await.context("Failed to get DB connection")?;
+    let rows = client
+        .query(
+            &sql,
+            &[
+                &filter.authority,
+                &filter.market_id,
+                &filter.before_slot,
+                &filter.from,
+                &filter.to,
+                &limit,
+            ],
+        )
+        .await
+        .context("Failed to query closed positions")?;
+
+    Ok(rows
+        .iter()
+        .map(|row| PositionFill {
+            row: closed_position_row_from_pg(row),
+            market_twap: row.get("market_twap"),
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_atoms_price_to_units() {
+        // 9 base decimals, 6 quote decimals: 1 quote atom per 1000 base atoms
+        // is 1 quote per base.
+        assert_eq!(atoms_price_to_units(Decimal::new(1, 3), 9, 6), Decimal::ONE);
+        assert_eq!(
+            atoms_price_to_units(Decimal::from(1000), 6, 9),
+            Decimal::ONE
+        );
+        assert_eq!(
+            atoms_price_to_units(Decimal::from(2), 6, 6),
+            Decimal::from(2)
+        );
+    }
+
+    #[test]
+    fn test_side_summary_weights_market_price_by_base_volume() {
+        let mut side = SideTotals::default();
+        side.add(&FillUnits {
+            deposited: Decimal::from(10),
+            swapped: Decimal::from(5),
+            remaining: Decimal::ZERO,
+            fees: Decimal::ZERO,
+            base: Decimal::from(5),
+            quote: Decimal::from(10),
+            market_price: Some(Decimal::from(2)),
+        });
+        side.add(&FillUnits {
+            deposited: Decimal::from(20),
+            swapped: Decimal::from(5),
+            remaining: Decimal::from(5),
+            fees: Decimal::ZERO,
+            base: Decimal::from(5),
+            quote: Decimal::from(15),
+            market_price: Some(Decimal::from(4)),
+        });
+        let side = side.finish();
+
+        assert_eq!(side.count, 2);
+        assert_eq!(side.average_execution_price, Some(Decimal::new(25, 1)));
+        assert_eq!(side.market_average_price, Some(Decimal::from(3)));
+        assert_eq!(side.remaining, Decimal::from(5));
+    }
+}
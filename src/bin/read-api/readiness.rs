@@ -0,0 +1,211 @@
+//! Readiness probe: `/readyz`.
+//!
+//! `/healthz` only says the process is up. `/readyz` answers `503` when this
+//! instance cannot serve data, so a load balancer can take it out of rotation:
+//! the database is unreachable or slower than `READ_API_READY_TIMEOUT_MS`, or a
+//! table read-api queries is missing. Stalled ingestion is shared by every
+//! instance, so it only fails the probe when `READ_API_READY_MAX_UPDATE_AGE_SECS`
+//! is set: then every market with updates going that long without one fails
+//! it, while a single quiet market only shows up as `stale` in the report.
+
+use anyhow::{Context, Result};
+use axum::{
+    Json,
+    extract::State,
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, SecondsFormat, Utc};
+use std::{sync::Arc, time::Instant};
+use twob_keepers::api::{MarketReadiness, ReadinessResponse, TableReadiness};
+
+use crate::AppState;
+
+const MARKET_CONFIGS_TABLE: &str = "market_configs";
+const MARKET_STATE_SNAPSHOTS_TABLE: &str = "market_state_snapshots";
+const TRADE_POSITIONS_TABLE: &str = "trade_positions";
+const LIQUIDITY_POSITIONS_TABLE: &str = "liquidity_positions";
+const MARKET_EXITS_TABLE: &str = "market_exits";
+const MARKET_FEES_1D_TABLE: &str = "market_fees_1d";
+
+#[utoipa::path(
+    get,
+    path = "/readyz",
+    responses(
+        (status = 200, body = ReadinessResponse),
+        (status = 503, body = ReadinessResponse),
+    )
+)]
+pub(crate) async fn readyz(State(state): State<Arc<AppState>>) -> Response {
+    let mut report = ReadinessResponse {
+        status: String::new(),
+        reasons: Vec::new(),
+        database_latency_ms: None,
+        tables: Vec::new(),
+        max_update_age_secs: state.config.ready_max_update_age_secs,
+        markets: Vec::new(),
+    };
+    let timeout = state.config.ready_timeout;
+    match tokio::time::timeout(timeout, run_checks(&state, &mut report)).await {
+        Ok(Ok(())) => {}
+        Ok(Err(error)) => report.reasons.push(format!("{error:#}")),
+        Err(_) => report.reasons.push(format!(
+            "Readiness checks timed out after {}ms",
+            timeout.as_millis()
+        )),
+    }
+
+    let status = if report.reasons.is_empty() {
+        report.status = "ready".to_string();
+        StatusCode::OK
+    } else {
+        eprintln!("Not ready: {}", report.reasons.join("; "));
+        report.status = "not_ready".to_string();
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, [(header::CACHE_CONTROL, "no-store")], Json(report)).into_response()
+}
+
+/// Fills `report`; failed checks add to `report.reasons`, and an error means
+/// the database could not be queried at all.
+async fn run_checks(state: &AppState, report: &mut ReadinessResponse) -> Result<()> {
+    let config = &state.config;
+    let started_at = Instant::now();
+    let client = state
+        .pool
+        .get()
+        .await
+        .context("Failed to get DB connection")?;
+
+    let mut required = vec![
+        config.market_updates_table.as_str(),
+        config.candles_1m_table.as_str(),
+        config.close_position_events_table.as_str(),
+        MARKET_CONFIGS_TABLE,
+        MARKET_STATE_SNAPSHOTS_TABLE,
+        TRADE_POSITIONS_TABLE,
+        LIQUIDITY_POSITIONS_TABLE,
+        MARKET_EXITS_TABLE,
+        MARKET_FEES_1D_TABLE,
+    ];
+    if let Some(candles_1h_table) = &config.candles_1h_table {
+        required.push(candles_1h_table.as_str());
+    }
+    let rows = client
+        .query(
+            "SELECT name, to_regclass(name) IS NOT NULL AS present \
+             FROM unnest($1::text[]) WITH ORDINALITY AS t(name, position) \
+             ORDER BY position",
+            &[&required],
+        )
+        .await
+        .context("Failed to query tables")?;
+    report.database_latency_ms = Some(started_at.elapsed().as_millis() as u64);
+    report.tables = rows
+        .iter()
+        .map(|row| TableReadiness {
+            name: row.get("name"),
+            present: row.get("present"),
+        })
+        .collect();
+    for table in report.tables.iter().filter(|table| !table.present) {
+        report.reasons.push(format!("Missing table {}", table.name));
+    }
+    let has_table = |name: &str| {
+        report
+            .tables
+            .iter()
+            .any(|table| table.name == name && table.present)
+    };
+    if !has_table(&config.market_updates_table) || !has_table(MARKET_CONFIGS_TABLE) {
+        return Ok(());
+    }
+
+    // One index probe on (market_id, event_time DESC) per market rather than
+    // an aggregate over the whole table.
+    let sql = format!(
+        "SELECT c.market_id, u.slot, u.event_time, \
+         extract(epoch from now() - u.event_time)::float8 AS age_secs \
+         FROM {MARKET_CONFIGS_TABLE} c \
+         LEFT JOIN LATERAL ( \
+             SELECT slot, event_time FROM {} \
+             WHERE market_id = c.market_id AND event_uid NOT LIKE 'debug:%' \
+             ORDER BY event_time DESC \
+             LIMIT 1 \
+         ) u ON true \
+         ORDER BY c.market_id ASC",
+        config.market_updates_table
+    );
+    let rows = client
+        .query(&sql, &[])
+        .await
+        .context("Failed to query latest market updates")?;
+    report.markets = rows
+        .iter()
+        .map(|row| {
+            let market_id: i64 = row.get("market_id");
+            let slot: Option<i64> = row.get("slot");
+            let event_time: Option<DateTime<Utc>> = row.get("event_time");
+            // The database clock ages the rows, so skew with this host does
+            // not matter; clamp rows stamped slightly in the future.
+            let age_secs = row
+                .get::<_, Option<f64>>("age_secs")
+                .map(|age| age.max(0.0) as u64);
+            MarketReadiness {
+                market_id: market_id.max(0) as u64,
+                latest_update_slot: slot.map(|slot| slot.max(0) as u64),
+                latest_update_time: event_time
+                    .map(|time| time.to_rfc3339_opts(SecondsFormat::Millis, true)),
+                age_secs,
+                stale: is_stale(age_secs, report.max_update_age_secs),
+            }
+        })
+        .collect();
+    if ingestion_stalled(&report.markets) {
+        report.reasons.push(format!(
+            "No market update in the last {}s",
+            report.max_update_age_secs
+        ));
+    }
+    Ok(())
+}
+
+/// A market without updates is not stale: it may simply be new. A threshold
+/// of `0` turns the check off.
+fn is_stale(age_secs: Option<u64>, max_update_age_secs: u64) -> bool {
+    max_update_age_secs > 0 && age_secs.is_some_and(|age| age > max_update_age_secs)
+}
+
+/// Every market that has had updates is stale.
+fn ingestion_stalled(markets: &[MarketReadiness]) -> bool {
+    let mut updated = markets
+        .iter()
+        .filter(|market| market.latest_update_slot.is_some())
+        .peekable();
+    updated.peek().is_some() && updated.all(|market| market.stale)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn market(age_secs: Option<u64>) -> MarketReadiness {
+        MarketReadiness {
+            market_id: 1,
+            latest_update_slot: age_secs.map(|_| 100),
+            latest_update_time: None,
+            age_secs,
+            stale: is_stale(age_secs, 600),
+        }
+    }
+
+    #[test]
+    fn test_ingestion_stalled_only_when_every_updated_market_is_stale() {
+        assert!(!is_stale(Some(10_000), 0));
+        assert!(!is_stale(None, 600));
+        assert!(!ingestion_stalled(&[]));
+        assert!(!ingestion_stalled(&[market(None)]));
+        assert!(!ingestion_stalled(&[market(Some(5)), market(Some(10_000))]));
+        assert!(ingestion_stalled(&[market(Some(601)), market(None)]));
+    }
+}
@@ -0,0 +1,342 @@
+//! In-process response cache with ETags for hot market endpoints: `/price`,
+//! `/candles` and `/updates`.
+//!
+//! JSON responses are kept per path, query string and `Accept` header for
+//! `READ_API_RESPONSE_CACHE_TTL_MS`. Each entry remembers the market's
+//! generation, which the LISTEN connection bumps on every keeper `NOTIFY` (see
+//! [`crate::streams::MarketStreams::spawn_listener`]), so a new event retires
+//! the market's entries before their TTL. Without LISTEN the TTL alone bounds
+//! staleness.
+//!
+//! Every cached route answers with a strong ETag over the body, `Vary: Accept`,
+//! and `304 Not Modified` on a matching `If-None-Match`. A handler that marks its response
+//! `immutable` (candles of a range past the backfill window) is kept for
+//! [`IMMUTABLE_TTL`] and ignores invalidation.
+
+use axum::{
+    body::{Body, Bytes, to_bytes},
+    extract::{Request, State},
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::{
+    collections::HashMap,
+    hash::{DefaultHasher, Hash, Hasher},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::AppState;
+
+/// `Cache-Control` of cacheable responses that may still change: clients keep
+/// them but revalidate with the ETag.
+const REVALIDATE_CACHE_CONTROL: &str = "no-cache";
+const IMMUTABLE_TTL: Duration = Duration::from_secs(60 * 60);
+const MAX_ENTRIES: usize = 10_000;
+/// Larger bodies are served but not kept.
+const MAX_CACHED_BODY_BYTES: usize = 1024 * 1024;
+
+#[derive(Clone)]
+struct CacheEntry {
+    market_id: u64,
+    generation: u64,
+    stored_at: Instant,
+    immutable: bool,
+    etag: HeaderValue,
+    cache_control: HeaderValue,
+    content_type: HeaderValue,
+    body: Bytes,
+}
+
+impl CacheEntry {
+    fn is_fresh(&self, ttl: Duration, generation: u64, now: Instant) -> bool {
+        if self.immutable {
+            now.duration_since(self.stored_at) < IMMUTABLE_TTL
+        } else {
+            self.generation == generation && now.duration_since(self.stored_at) < ttl
+        }
+    }
+
+    fn response(&self, request_headers: &HeaderMap) -> Response {
+        let headers = [
+            (header::ETAG, self.etag.clone()),
+            (header::CACHE_CONTROL, self.cache_control.clone()),
+        ];
+        if etag_matches(request_headers, &self.etag) {
+            return (StatusCode::NOT_MODIFIED, headers).into_response();
+        }
+        (
+            headers,
+            [(header::CONTENT_TYPE, self.content_type.clone())],
+            self.body.clone(),
+        )
+            .into_response()
+    }
+}
+
+#[derive(Default)]
+struct CacheState {
+    entries: HashMap<String, CacheEntry>,
+    /// Bumped for a market whenever it has new events.
+    generations: HashMap<u64, u64>,
+}
+
+/// Shared handle; clones use the same cache.
+#[derive(Clone)]
+pub(crate) struct ResponseCache {
+    ttl: Duration,
+    state: Arc<Mutex<CacheState>>,
+}
+
+impl ResponseCache {
+    /// A `ttl` of zero keeps nothing; responses still carry ETags.
+    pub(crate) fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            state: Arc::default(),
+        }
+    }
+
+    /// Retire the cached responses of `market_id` that may change.
+    pub(crate) fn invalidate_market(&self, market_id: u64) {
+        let mut state = self.state.lock().expect("response cache poisoned");
+        *state.generations.entry(market_id).or_default() += 1;
+    }
+
+    /// Retire every cached response that may change, e.g. after missing
+    /// notifications.
+    pub(crate) fn invalidate_all(&self) {
+        let mut state = self.state.lock().expect("response cache poisoned");
+        state.entries.retain(|_, entry| entry.immutable);
+    }
+
+    fn generation(&self, market_id: u64) -> u64 {
+        let state = self.state.lock().expect("response cache poisoned");
+        state.generations.get(&market_id).copied().unwrap_or(0)
+    }
+
+    fn lookup(&self, key: &str, market_id: u64, now: Instant) -> Option<CacheEntry> {
+        let state = self.state.lock().expect("response cache poisoned");
+        let generation = state.generations.get(&market_id).copied().unwrap_or(0);
+        state
+            .entries
+            .get(key)
+            .filter(|entry| entry.is_fresh(self.ttl, generation, now))
+            .cloned()
+    }
+
+    fn store(&self, key: String, entry: CacheEntry, now: Instant) {
+        if self.ttl.is_zero() || entry.body.len() > MAX_CACHED_BODY_BYTES {
+            return;
+        }
+        let mut state = self.state.lock().expect("response cache poisoned");
+        if state.entries.len() >= MAX_ENTRIES && !state.entries.contains_key(&key) {
+            let CacheState {
+                entries,
+                generations,
+            } = &mut *state;
+            entries.retain(|_, entry| {
+                let generation = generations.get(&entry.market_id).copied().unwrap_or(0);
+                entry.is_fresh(self.ttl, generation, now)
+            });
+            if entries.len() >= MAX_ENTRIES {
+                let oldest = entries
+                    .iter()
+                    .min_by_key(|(_, entry)| entry.stored_at)
+                    .map(|(key, _)| key.clone());
+                if let Some(oldest) = oldest {
+                    entries.remove(&oldest);
+                }
+            }
+        }
+        state.entries.insert(key, entry);
+    }
+}
+
+/// Middleware for the cached routes; all of them are under
+/// `/v1/markets/{market_id}/`.
+pub(crate) async fn cached(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let mut response = cached_response(&state, request, next).await;
+    // Entries are keyed by `Accept`, so shared caches must key on it too;
+    // that includes hits and `304`s.
+    response
+        .headers_mut()
+        .insert(header::VARY, HeaderValue::from_static("Accept"));
+    response
+}
+
+async fn cached_response(state: &AppState, request: Request, next: Next) -> Response {
+    let cache = &state.response_cache;
+    let Some(market_id) = path_market_id(request.uri().path()) else {
+        return next.run(request).await;
+    };
+    let key = cache_key(&request);
+    let request_headers = request.headers().clone();
+    if let Some(entry) = cache.lookup(&key, market_id, Instant::now()) {
+        return entry.response(&request_headers);
+    }
+
+    // Taken before the handler reads, so an event that lands meanwhile
+    // retires the entry.
+    let generation = cache.generation(market_id);
+    let response = next.run(request).await;
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|value| value.as_bytes().starts_with(b"application/json"));
+    if response.status() != StatusCode::OK || !is_json {
+        return response;
+    }
+
+    // JSON bodies are already serialized in memory, so buffering is cheap.
+    let (mut parts, body) = response.into_parts();
+    let body = match to_bytes(body, usize::MAX).await {
+        Ok(body) => body,
+        Err(error) => {
+            eprintln!("Failed to buffer response for {key}: {error}");
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to buffer response",
+            )
+                .into_response();
+        }
+    };
+    let immutable = parts
+        .headers
+        .get(header::CACHE_CONTROL)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.contains("immutable"));
+    let cache_control = parts
+        .headers
+        .get(header::CACHE_CONTROL)
+        .cloned()
+        .unwrap_or(HeaderValue::from_static(REVALIDATE_CACHE_CONTROL));
+    let entry = CacheEntry {
+        market_id,
+        generation,
+        stored_at: Instant::now(),
+        immutable,
+        etag: body_etag(&body),
+        cache_control,
+        content_type: parts.headers[header::CONTENT_TYPE].clone(),
+        body,
+    };
+
+    if etag_matches(&request_headers, &entry.etag) {
+        let response = entry.response(&request_headers);
+        cache.store(key, entry, Instant::now());
+        return response;
+    }
+    parts.headers.insert(header::ETAG, entry.etag.clone());
+    parts
+        .headers
+        .insert(header::CACHE_CONTROL, entry.cache_control.clone());
+    let body = entry.body.clone();
+    cache.store(key, entry, Instant::now());
+    Response::from_parts(parts, Body::from(body))
+}
+
+fn path_market_id(path: &str) -> Option<u64> {
+    path.strip_prefix("/v1/markets/")?
+        .split('/')
+        .next()?
+        .parse()
+        .ok()
+}
+
+/// Path, query string and `Accept`, which selects JSON or an export.
+fn cache_key(request: &Request) -> String {
+    let accept = request
+        .headers()
+        .get(header::ACCEPT)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    format!("{}|{}", request.uri(), accept)
+}
+
+fn body_etag(body: &[u8]) -> HeaderValue {
+    let mut hasher = DefaultHasher::new();
+    body.hash(&mut hasher);
+    HeaderValue::from_str(&format!("\"{:016x}-{:x}\"", hasher.finish(), body.len()))
+        .expect("hex ETag is a valid header value")
+}
+
+/// `If-None-Match` lists `etag` or is `*`. Weak validators compare equal to
+/// their strong form, as RFC 9110 requires for `If-None-Match`.
+fn etag_matches(request_headers: &HeaderMap, etag: &HeaderValue) -> bool {
+    let Ok(etag) = etag.to_str() else {
+        return false;
+    };
+    request_headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|candidate| candidate.trim())
+        .any(|candidate| candidate == "*" || candidate.trim_start_matches("W/") == etag)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(generation: u64, stored_at: Instant, immutable: bool) -> CacheEntry {
+        CacheEntry {
+            market_id: 1,
+            generation,
+            stored_at,
+            immutable,
+            etag: body_etag(b"{}"),
+            cache_control: HeaderValue::from_static(REVALIDATE_CACHE_CONTROL),
+            content_type: HeaderValue::from_static("application/json"),
+            body: Bytes::from_static(b"{}"),
+        }
+    }
+
+    #[test]
+    fn test_entries_expire_on_ttl_or_new_events() {
+        let cache = ResponseCache::new(Duration::from_secs(5));
+        let now = Instant::now();
+        cache.store("price".to_string(), entry(0, now, false), now);
+        cache.store("candles".to_string(), entry(0, now, true), now);
+        assert!(cache.lookup("price", 1, now).is_some());
+        assert!(
+            cache
+                .lookup("price", 1, now + Duration::from_secs(5))
+                .is_none()
+        );
+
+        cache.invalidate_market(2);
+        assert!(cache.lookup("price", 1, now).is_some());
+        cache.invalidate_market(1);
+        assert!(cache.lookup("price", 1, now).is_none());
+        // Settled ranges survive invalidation.
+        assert!(cache.lookup("candles", 1, now).is_some());
+    }
+
+    #[test]
+    fn test_etag_matches_if_none_match() {
+        let etag = HeaderValue::from_static("\"abc-2\"");
+        let mut headers = HeaderMap::new();
+        assert!(!etag_matches(&headers, &etag));
+        headers.insert(
+            header::IF_NONE_MATCH,
+            HeaderValue::from_static("\"other\", W/\"abc-2\""),
+        );
+        assert!(etag_matches(&headers, &etag));
+        headers.insert(header::IF_NONE_MATCH, HeaderValue::from_static("*"));
+        assert!(etag_matches(&headers, &etag));
+    }
+
+    #[test]
+    fn test_path_market_id() {
+        assert_eq!(path_market_id("/v1/markets/12/candles"), Some(12));
+        assert_eq!(path_market_id("/v1/markets/x/candles"), None);
+        assert_eq!(path_market_id("/v1/tickers"), None);
+    }
+}
@@ -0,0 +1,434 @@
+//! Slot ⇄ wall-clock estimates: `/v1/time/slot` and `/v1/time/at`.
+//!
+//! read-api has no RPC connection, so the ingested market updates are the
+//! slot-to-time mapping: each row pairs a slot with the time the keeper saw it.
+//! A conversion interpolates between the nearest rows either side. Past the
+//! newest one, the current slot is extrapolated from the newest slot the
+//! indexers have written, assuming the nominal slot time.
+
+use anyhow::{Context, Result};
+use axum::{
+    Json,
+    extract::{Query, State},
+};
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use deadpool_postgres::Pool;
+use std::sync::Arc;
+use twob_keepers::api::{
+    ErrorResponse, SlotEstimate as Estimate, SlotTimeQuery, SlotTimeResponse, TimeSlotQuery,
+};
+
+use crate::{ApiError, AppState};
+
+/// Nominal Solana slot time. Real slots run a little slower under load, so
+/// estimates far from the reference drift early.
+pub(crate) const SLOT_DURATION_MS: i64 = 400;
+
+/// A known `(slot, time)` pair to extrapolate from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct SlotClock {
+    pub(crate) slot: u64,
+    pub(crate) observed_at: DateTime<Utc>,
+}
+
+impl SlotClock {
+    /// Estimated slot at `time`; never before slot 0.
+    pub(crate) fn slot_at(&self, time: DateTime<Utc>) -> u64 {
+        let elapsed_slots = (time - self.observed_at).num_milliseconds() / SLOT_DURATION_MS;
+        if elapsed_slots >= 0 {
+            self.slot.saturating_add(elapsed_slots as u64)
+        } else {
+            self.slot.saturating_sub(elapsed_slots.unsigned_abs())
+        }
+    }
+
+    /// Estimated wall-clock time of `slot`.
+    pub(crate) fn time_of(&self, slot: u64) -> DateTime<Utc> {
+        let slots = i64::try_from(slot).unwrap_or(i64::MAX) - self.slot as i64;
+        self.observed_at + Duration::milliseconds(slots.saturating_mul(SLOT_DURATION_MS))
+    }
+}
+
+/// The nearest known `(slot, time)` pairs before and after a slot or time.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct SlotBracket {
+    pub(crate) before: Option<SlotClock>,
+    pub(crate) after: Option<SlotClock>,
+}
+
+impl SlotBracket {
+    /// Estimated time of `slot`; `None` without any indexed events.
+    pub(crate) fn time_of(&self, slot: u64) -> Option<(DateTime<Utc>, Estimate)> {
+        for reference in [self.before, self.after].into_iter().flatten() {
+            if reference.slot == slot {
+                return Some((reference.observed_at, Estimate::Exact));
+            }
+        }
+        match (self.before, self.after) {
+            // Ingestion times can run backwards across slots; only interpolate
+            // where they do not.
+            (Some(before), Some(after))
+                if after.slot > before.slot && after.observed_at >= before.observed_at =>
+            {
+                let span_ms = (after.observed_at - before.observed_at).num_milliseconds();
+                let offset_ms = i128::from(span_ms) * i128::from(slot - before.slot)
+                    / i128::from(after.slot - before.slot);
+                Some((
+                    before.observed_at + Duration::milliseconds(offset_ms as i64),
+                    Estimate::Interpolated,
+                ))
+            }
+            (before, after) => {
+                let reference = nearest(before, after, |clock| clock.slot.abs_diff(slot))?;
+                Some((reference.time_of(slot), Estimate::Extrapolated))
+            }
+        }
+    }
+
+    /// Estimated slot at `time`; `None` without any indexed events.
+    pub(crate) fn slot_at(&self, time: DateTime<Utc>) -> Option<(u64, Estimate)> {
+        for reference in [self.before, self.after].into_iter().flatten() {
+            if reference.observed_at == time {
+                return Some((reference.slot, Estimate::Exact));
+            }
+        }
+        match (self.before, self.after) {
+            (Some(before), Some(after))
+                if after.observed_at > before.observed_at && after.slot >= before.slot =>
+            {
+                let span_ms = (after.observed_at - before.observed_at).num_milliseconds();
+                let offset_ms = (time - before.observed_at).num_milliseconds();
+                let slots = i128::from(after.slot - before.slot) * i128::from(offset_ms)
+                    / i128::from(span_ms.max(1));
+                Some((before.slot + slots as u64, Estimate::Interpolated))
+            }
+            (before, after) => {
+                let reference = nearest(before, after, |clock| {
+                    (clock.observed_at - time).num_milliseconds().unsigned_abs()
+                })?;
+                Some((reference.slot_at(time), Estimate::Extrapolated))
+            }
+        }
+    }
+}
+
+fn nearest(
+    before: Option<SlotClock>,
+    after: Option<SlotClock>,
+    distance: impl Fn(&SlotClock) -> u64,
+) -> Option<SlotClock> {
+    [before, after]
+        .into_iter()
+        .flatten()
+        .min_by_key(|clock| distance(clock))
+}
+
+/// Indexed events nearest to `slot` on either side.
+pub(crate) async fn query_slot_bracket(
+    pool: &Pool,
+    market_updates_table: &str,
+    slot: u64,
+) -> Result<SlotBracket> {
+    let slot_i64 = i64::try_from(slot).context("slot out of range")?;
+    let side_sql = |comparison: &str, order: &str| {
+        format!(
+            "SELECT slot, event_time AS observed_at FROM {market_updates_table} \
+             WHERE slot {comparison} $1 AND event_uid NOT LIKE 'debug:%' \
+             ORDER BY slot {order}, event_time ASC \
+             LIMIT 1"
+        )
+    };
+    let mut bracket = query_bracket(
+        pool,
+        &side_sql("<=", "DESC"),
+        &side_sql(">=", "ASC"),
+        &slot_i64,
+    )
+    .await?;
+    // Past the newest update, a newer state snapshot is a better reference.
+    if bracket.after.is_none() {
+        if let Some(latest) = query_slot_clock(pool, market_updates_table).await? {
+            if latest.slot >= slot {
+                bracket.after = Some(latest);
+            } else if bracket
+                .before
+                .is_none_or(|before| latest.slot > before.slot)
+            {
+                bracket.before = Some(latest);
+            }
+        }
+    }
+    Ok(bracket)
+}
+
+/// Indexed events nearest to `time` on either side.
+pub(crate) async fn query_time_bracket(
+    pool: &Pool,
+    market_updates_table: &str,
+    time: DateTime<Utc>,
+) -> Result<SlotBracket> {
+    let side_sql = |comparison: &str, order: &str| {
+        format!(
+            "SELECT slot, event_time AS observed_at FROM {market_updates_table} \
+             WHERE event_time {comparison} $1 AND event_uid NOT LIKE 'debug:%' \
+             ORDER BY event_time {order}, slot ASC \
+             LIMIT 1"
+        )
+    };
+    let mut bracket =
+        query_bracket(pool, &side_sql("<=", "DESC"), &side_sql(">=", "ASC"), &time).await?;
+    if bracket.after.is_none() {
+        if let Some(latest) = query_slot_clock(pool, market_updates_table).await? {
+            if latest.observed_at >= time {
+                bracket.after = Some(latest);
+            } else if bracket
+                .before
+                .is_none_or(|before| latest.observed_at > before.observed_at)
+            {
+                bracket.before = Some(latest);
+            }
+        }
+    }
+    Ok(bracket)
+}
+
+async fn query_bracket(
+    pool: &Pool,
+    before_sql: &str,
+    after_sql: &str,
+    param: &(dyn tokio_postgres::types::ToSql + Sync),
+) -> Result<SlotBracket> {
+    let client = pool.get().await.context("Failed to get DB connection")?;
+    let mut sides = [None, None];
+    for (side, sql) in sides.iter_mut().zip([before_sql, after_sql]) {
+        *side = client
+            .query_opt(sql, &[param])
+            .await
+            .context("Failed to query slot mapping")?
+            .map(|row| {
+                let slot: i64 = row.get("slot");
+                SlotClock {
+                    slot: slot.max(0) as u64,
+                    observed_at: row.get("observed_at"),
+                }
+            });
+    }
+    let [before, after] = sides;
+    Ok(SlotBracket { before, after })
+}
+
+/// `/v1/time/slot?slot=`: estimated time of a slot.
+#[utoipa::path(
+    get,
+    path = "/v1/time/slot",
+    params(SlotTimeQuery),
+    responses(
+        (status = 200, body = SlotTimeResponse),
+        (status = 404, body = ErrorResponse),
+    )
+)]
+pub(crate) async fn get_slot_time(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<SlotTimeQuery>,
+) -> Result<Json<SlotTimeResponse>, ApiError> {
+    let (time, estimate) =
+        query_slot_bracket(&state.pool, &state.config.market_updates_table, query.slot)
+            .await
+            .map_err(|error| ApiError::internal(error.context("Failed to query slot mapping")))?
+            .time_of(query.slot)
+            .ok_or_else(no_slots_indexed)?;
+    Ok(Json(SlotTimeResponse {
+        slot: query.slot,
+        time: time.to_rfc3339_opts(SecondsFormat::Millis, true),
+        estimate,
+    }))
+}
+
+/// `/v1/time/at?ts=`: estimated slot at a time.
+#[utoipa::path(
+    get,
+    path = "/v1/time/at",
+    params(TimeSlotQuery),
+    responses(
+        (status = 200, body = SlotTimeResponse),
+        (status = 404, body = ErrorResponse),
+    )
+)]
+pub(crate) async fn get_time_slot(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<TimeSlotQuery>,
+) -> Result<Json<SlotTimeResponse>, ApiError> {
+    let (slot, estimate) = resolve_time_slot(&state, query.ts).await?;
+    Ok(Json(SlotTimeResponse {
+        slot,
+        time: query.ts.to_rfc3339_opts(SecondsFormat::Millis, true),
+        estimate,
+    }))
+}
+
+pub(crate) async fn resolve_time_slot(
+    state: &AppState,
+    time: DateTime<Utc>,
+) -> Result<(u64, Estimate), ApiError> {
+    query_time_bracket(&state.pool, &state.config.market_updates_table, time)
+        .await
+        .map_err(|error| ApiError::internal(error.context("Failed to query slot mapping")))?
+        .slot_at(time)
+        .ok_or_else(no_slots_indexed)
+}
+
+/// A slot bound given either as a slot or as a time; `None` when neither is
+/// set.
+async fn resolve_slot_bound(
+    state: &AppState,
+    (slot_name, slot): (&str, Option<u64>),
+    (time_name, time): (&str, Option<DateTime<Utc>>),
+) -> Result<Option<u64>, ApiError> {
+    match (slot, time) {
+        (Some(_), Some(_)) => Err(ApiError::bad_request(format!(
+            "Pass either '{slot_name}' or '{time_name}', not both"
+        ))),
+        (Some(slot), None) => Ok(Some(slot)),
+        (None, Some(time)) => Ok(Some(resolve_time_slot(state, time).await?.0)),
+        (None, None) => Ok(None),
+    }
+}
+
+/// Slot range of the slot-based endpoints, each end given as a slot or a time.
+pub(crate) async fn resolve_slot_range(
+    state: &AppState,
+    (start_slot, from): (Option<u64>, Option<DateTime<Utc>>),
+    (end_slot, to): (Option<u64>, Option<DateTime<Utc>>),
+) -> Result<(u64, u64), ApiError> {
+    let start_slot = resolve_slot_bound(state, ("start_slot", start_slot), ("from", from))
+        .await?
+        .ok_or_else(|| ApiError::bad_request("Missing 'start_slot' or 'from'"))?;
+    let end_slot = resolve_slot_bound(state, ("end_slot", end_slot), ("to", to))
+        .await?
+        .ok_or_else(|| ApiError::bad_request("Missing 'end_slot' or 'to'"))?;
+    if start_slot > end_slot {
+        return Err(ApiError::bad_request(
+            "'start_slot' must be less than or equal to 'end_slot'",
+        ));
+    }
+    Ok((start_slot, end_slot))
+}
+
+fn no_slots_indexed() -> ApiError {
+    ApiError::not_found("No indexed events to map slots to times")
+}
+
+/// The newest slot recorded by either the event keeper (market updates) or
+/// the account indexer (state snapshots), with the time it was written.
+/// `None` on an empty database.
+pub(crate) async fn query_slot_clock(
+    pool: &Pool,
+    market_updates_table: &str,
+) -> Result<Option<SlotClock>> {
+    let sql = format!(
+        "SELECT slot, observed_at FROM ( \
+            (SELECT slot, event_time AS observed_at FROM {market_updates_table} \
+             ORDER BY slot DESC LIMIT 1) \
+            UNION ALL \
+            (SELECT slot, snapshot_time AS observed_at FROM market_state_snapshots \
+             ORDER BY snapshot_time DESC LIMIT 1) \
+         ) refs \
+         ORDER BY slot DESC \
+         LIMIT 1"
+    );
+
+    let client = pool.get().await.context("Failed to get DB connection")?;
+    let row = client
+        .query_opt(&sql, &[])
+        .await
+        .context("Failed to query latest slot")?;
+
+    Ok(row.map(|row| {
+        let slot: i64 = row.get("slot");
+        SlotClock {
+            slot: slot.max(0) as u64,
+            observed_at: row.get("observed_at"),
+        }
+    }))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Slot 1000 at 2025-01-01T00:00:00Z; shared with other modules' tests.
+    pub(crate) fn clock() -> SlotClock {
+        SlotClock {
+            slot: 1_000,
+            observed_at: DateTime::parse_from_rfc3339("2025-01-01T00:00:00Z")
+                .unwrap()
+                .with_timezone(&Utc),
+        }
+    }
+
+    #[test]
+    fn test_slot_at_extrapolates_both_ways() {
+        let clock = clock();
+        assert_eq!(clock.slot_at(clock.observed_at), 1_000);
+        assert_eq!(
+            clock.slot_at(clock.observed_at + Duration::seconds(4)),
+            1_010
+        );
+        assert_eq!(clock.slot_at(clock.observed_at - Duration::seconds(2)), 995);
+        assert_eq!(clock.slot_at(clock.observed_at - Duration::days(1)), 0);
+    }
+
+    #[test]
+    fn test_bracket_interpolates_between_events() {
+        let before = clock();
+        let after = SlotClock {
+            slot: 1_100,
+            observed_at: before.observed_at + Duration::seconds(50),
+        };
+        let bracket = SlotBracket {
+            before: Some(before),
+            after: Some(after),
+        };
+        // 500ms per slot between these two, not the nominal 400ms.
+        assert_eq!(
+            bracket.time_of(1_040),
+            Some((
+                before.observed_at + Duration::seconds(20),
+                Estimate::Interpolated
+            ))
+        );
+        assert_eq!(
+            bracket.slot_at(before.observed_at + Duration::seconds(20)),
+            Some((1_040, Estimate::Interpolated))
+        );
+        assert_eq!(
+            bracket.time_of(1_100),
+            Some((after.observed_at, Estimate::Exact))
+        );
+
+        let newest_only = SlotBracket {
+            before: Some(after),
+            after: None,
+        };
+        assert_eq!(
+            newest_only.time_of(1_110),
+            Some((
+                after.observed_at + Duration::seconds(4),
+                Estimate::Extrapolated
+            ))
+        );
+        assert_eq!(SlotBracket::default().slot_at(after.observed_at), None);
+    }
+
+    #[test]
+    fn test_time_of_round_trips_slot_at() {
+        let clock = clock();
+        let time = clock.time_of(1_250);
+        assert_eq!(time, clock.observed_at + Duration::seconds(100));
+        assert_eq!(clock.slot_at(time), 1_250);
+        assert_eq!(
+            clock.time_of(900),
+            clock.observed_at - Duration::seconds(40)
+        );
+    }
+}
@@ -0,0 +1,779 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use deadpool_postgres::Pool;
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc,
+        atomic::{AtomicU8, Ordering},
+    },
+    time::Duration,
+};
+use tokio::{
+    sync::{Notify, RwLock, broadcast},
+    time::{Instant, sleep},
+};
+use twob_keepers::database::{CLOSE_POSITIONS_CHANNEL, MARKET_UPDATES_CHANNEL, connect_listener};
+
+use crate::{
+    ApiError, CandleInterval, CandleItem, CandleSource, ClosedPositionItem, LatestPriceResponse,
+    MarketHistoryItem, closed_position_item_from_row, closed_position_row_from_pg,
+    fetch_latest_price_snapshot, market_history_item_from_row, market_history_row_from_pg,
+    response_cache::ResponseCache,
+};
+
+const LISTEN_RECONNECT_MIN_DELAY: Duration = Duration::from_secs(1);
+const LISTEN_RECONNECT_MAX_DELAY: Duration = Duration::from_secs(30);
+const STREAM_CHANNEL_CAPACITY: usize = 512;
+/// Rows read per wake-up when catching up on new events.
+const STREAM_FETCH_LIMIT: i64 = 500;
+
+/// A candle of one interval whose current bucket changed.
+#[derive(Clone)]
+pub(crate) struct CandleUpdate {
+    pub(crate) interval: CandleInterval,
+    pub(crate) candle: CandleItem,
+}
+
+/// A newly inserted close-position event.
+#[derive(Clone)]
+pub(crate) struct ClosePositionUpdate {
+    pub(crate) authority: String,
+    pub(crate) item: ClosedPositionItem,
+}
+
+/// Live broadcast channels fed from the database.
+///
+/// Each subscribed market has one task that, when woken, re-reads the latest
+/// price, any new market updates and the current candle of every subscribed
+/// interval, and broadcasts what changed. Close-position events share one
+/// global task. With a LISTEN connection running
+/// (see [`MarketStreams::spawn_listener`]) tasks are woken by the keeper's
+/// `NOTIFY` and `poll_interval` is only a fallback; without one they poll.
+///
+/// Tasks run only while someone listens: a stream found without receivers
+/// for `idle_grace` (checked on each wake-up) stops and drops its channel,
+/// and market channels are only created for markets that have a
+/// `market_configs` row.
+#[derive(Clone)]
+pub(crate) struct MarketStreams {
+    markets: Arc<RwLock<HashMap<u64, MarketChannel>>>,
+    close_positions: Arc<RwLock<Option<ClosePositionChannel>>>,
+    runtime: StreamRuntime,
+    /// Invalidated from the same notifications that wake the streams.
+    response_cache: ResponseCache,
+}
+
+#[derive(Clone)]
+struct MarketChannel {
+    prices: broadcast::Sender<LatestPriceResponse>,
+    updates: broadcast::Sender<MarketHistoryItem>,
+    candles: broadcast::Sender<CandleUpdate>,
+    /// Bitmask of [`CandleInterval::bit`] values with at least one subscriber.
+    candle_intervals: Arc<AtomicU8>,
+    wake: Arc<Notify>,
+}
+
+#[derive(Clone)]
+struct ClosePositionChannel {
+    sender: broadcast::Sender<ClosePositionUpdate>,
+    wake: Arc<Notify>,
+}
+
+#[derive(Clone)]
+struct StreamRuntime {
+    pool: Pool,
+    market_updates_table: String,
+    candles: CandleSource,
+    close_position_events_table: String,
+    poll_interval: Duration,
+    idle_grace: Duration,
+}
+
+impl MarketStreams {
+    pub(crate) fn new(
+        pool: Pool,
+        market_updates_table: String,
+        candles: CandleSource,
+        close_position_events_table: String,
+        poll_interval: Duration,
+        idle_grace: Duration,
+        response_cache: ResponseCache,
+    ) -> Self {
+        Self {
+            markets: Arc::new(RwLock::new(HashMap::new())),
+            close_positions: Arc::new(RwLock::new(None)),
+            response_cache,
+            runtime: StreamRuntime {
+                pool,
+                market_updates_table,
+                candles,
+                close_position_events_table,
+                poll_interval,
+                idle_grace,
+            },
+        }
+    }
+
+    pub(crate) async fn subscribe_prices(
+        &self,
+        market_id: u64,
+    ) -> Result<broadcast::Receiver<LatestPriceResponse>, ApiError> {
+        self.with_market_channel(market_id, |channel| channel.prices.subscribe())
+            .await
+    }
+
+    pub(crate) async fn subscribe_updates(
+        &self,
+        market_id: u64,
+    ) -> Result<broadcast::Receiver<MarketHistoryItem>, ApiError> {
+        self.with_market_channel(market_id, |channel| channel.updates.subscribe())
+            .await
+    }
+
+    /// Receives updates for every subscribed interval of the market; callers
+    /// filter on [`CandleUpdate::interval`]. Subscribing and setting the
+    /// interval bit happen under the map lock, which the stream task also
+    /// holds when it clears the bits of a market without candle receivers.
+    pub(crate) async fn subscribe_candles(
+        &self,
+        market_id: u64,
+        interval: CandleInterval,
+    ) -> Result<broadcast::Receiver<CandleUpdate>, ApiError> {
+        self.with_market_channel(market_id, |channel| {
+            let receiver = channel.candles.subscribe();
+            let previous = channel
+                .candle_intervals
+                .fetch_or(interval.bit(), Ordering::Relaxed);
+            if previous & interval.bit() == 0 {
+                // Emit the current candle of the new interval straight away.
+                channel.wake.notify_one();
+            }
+            receiver
+        })
+        .await
+    }
+
+    pub(crate) async fn subscribe_close_positions(
+        &self,
+    ) -> broadcast::Receiver<ClosePositionUpdate> {
+        let mut close_positions = self.close_positions.write().await;
+        let channel = close_positions.get_or_insert_with(|| {
+            let channel = ClosePositionChannel {
+                sender: broadcast::channel(STREAM_CHANNEL_CAPACITY).0,
+                wake: Arc::new(Notify::new()),
+            };
+            let runtime = self.runtime.clone();
+            let slot = self.close_positions.clone();
+            let task_channel = channel.clone();
+            tokio::spawn(async move {
+                run_close_position_stream(runtime, slot, task_channel).await;
+            });
+            channel
+        });
+        channel.sender.subscribe()
+    }
+
+    /// Run `subscribe` against the market's channel while holding the map
+    /// lock, so an idle task cannot remove the channel in between. New
+    /// channels are only created for markets with a `market_configs` row.
+    async fn with_market_channel<T>(
+        &self,
+        market_id: u64,
+        subscribe: impl FnOnce(&MarketChannel) -> T,
+    ) -> Result<T, ApiError> {
+        {
+            let markets = self.markets.read().await;
+            if let Some(channel) = markets.get(&market_id) {
+                return Ok(subscribe(channel));
+            }
+        }
+
+        if !market_exists(&self.runtime.pool, market_id)
+            .await
+            .map_err(|error| ApiError::internal(error.context("Failed to look up market")))?
+        {
+            return Err(ApiError::not_found(format!(
+                "Unknown market_id={market_id}"
+            )));
+        }
+
+        let mut markets = self.markets.write().await;
+        if let Some(channel) = markets.get(&market_id) {
+            return Ok(subscribe(channel));
+        }
+
+        let channel = MarketChannel {
+            prices: broadcast::channel(STREAM_CHANNEL_CAPACITY).0,
+            updates: broadcast::channel(STREAM_CHANNEL_CAPACITY).0,
+            candles: broadcast::channel(STREAM_CHANNEL_CAPACITY).0,
+            candle_intervals: Arc::new(AtomicU8::new(0)),
+            wake: Arc::new(Notify::new()),
+        };
+        let receiver = subscribe(&channel);
+        let runtime = self.runtime.clone();
+        let markets_for_task = self.markets.clone();
+        let task_channel = channel.clone();
+        tokio::spawn(async move {
+            run_market_stream(runtime, markets_for_task, market_id, task_channel).await;
+        });
+
+        markets.insert(market_id, channel);
+        Ok(receiver)
+    }
+
+    /// Start the background `LISTEN` connection that wakes stream tasks on
+    /// every keeper `NOTIFY`. Reconnects with backoff if the connection drops.
+    pub(crate) fn spawn_listener(&self, database_url: String) {
+        let streams = self.clone();
+        tokio::spawn(async move {
+            run_notification_listener(streams, database_url).await;
+        });
+    }
+
+    async fn wake_market(&self, market_id: u64) {
+        if let Some(channel) = self.markets.read().await.get(&market_id) {
+            channel.wake.notify_one();
+        }
+    }
+
+    async fn wake_close_positions(&self) {
+        if let Some(channel) = self.close_positions.read().await.as_ref() {
+            channel.wake.notify_one();
+        }
+    }
+
+    async fn wake_all(&self) {
+        for channel in self.markets.read().await.values() {
+            channel.wake.notify_one();
+        }
+        self.wake_close_positions().await;
+    }
+}
+
+impl MarketChannel {
+    fn receiver_count(&self) -> usize {
+        self.prices.receiver_count() + self.updates.receiver_count() + self.candles.receiver_count()
+    }
+}
+
+async fn market_exists(pool: &Pool, market_id: u64) -> Result<bool> {
+    let Ok(market_id_i64) = i64::try_from(market_id) else {
+        return Ok(false);
+    };
+    let client = pool.get().await.context("Failed to get DB connection")?;
+    let row = client
+        .query_one(
+            "SELECT EXISTS (SELECT 1 FROM market_configs WHERE market_id = $1)",
+            &[&market_id_i64],
+        )
+        .await
+        .context("Failed to query market_configs")?;
+    Ok(row.get(0))
+}
+
+/// Idle bookkeeping shared by the stream tasks: a task with no receivers for
+/// `idle_grace` stops.
+struct IdleTracker {
+    idle_since: Option<Instant>,
+    idle_grace: Duration,
+}
+
+impl IdleTracker {
+    fn new(idle_grace: Duration) -> Self {
+        Self {
+            idle_since: None,
+            idle_grace,
+        }
+    }
+
+    /// Record the current receiver count. Returns `true` once the stream has
+    /// had no receivers for the whole grace period.
+    fn observe(&mut self, receivers: usize) -> bool {
+        if receivers > 0 {
+            self.idle_since = None;
+            return false;
+        }
+        let idle_since = *self.idle_since.get_or_insert_with(Instant::now);
+        idle_since.elapsed() >= self.idle_grace
+    }
+
+    fn is_idle(&self) -> bool {
+        self.idle_since.is_some()
+    }
+
+    /// How long to sleep before re-checking; shorter while idle so the grace
+    /// period is honoured even with a long poll interval.
+    fn wait(&self, poll_interval: Duration) -> Duration {
+        if self.is_idle() {
+            poll_interval.min(self.idle_grace)
+        } else {
+            poll_interval
+        }
+    }
+}
+
+async fn run_notification_listener(streams: MarketStreams, database_url: String) {
+    let mut reconnect_delay = LISTEN_RECONNECT_MIN_DELAY;
+
+    loop {
+        match connect_listener(
+            &database_url,
+            &[MARKET_UPDATES_CHANNEL, CLOSE_POSITIONS_CHANNEL],
+        )
+        .await
+        {
+            Ok((_client, mut notifications)) => {
+                println!(
+                    "Streams listening on {MARKET_UPDATES_CHANNEL} and {CLOSE_POSITIONS_CHANNEL}"
+                );
+                reconnect_delay = LISTEN_RECONNECT_MIN_DELAY;
+                // Anything inserted while disconnected was not notified.
+                streams.response_cache.invalidate_all();
+                streams.wake_all().await;
+
+                while let Some(notification) = notifications.recv().await {
+                    if notification.channel() == CLOSE_POSITIONS_CHANNEL {
+                        streams.wake_close_positions().await;
+                        continue;
+                    }
+                    match notification.payload().parse::<u64>() {
+                        Ok(market_id) => {
+                            streams.response_cache.invalidate_market(market_id);
+                            streams.wake_market(market_id).await;
+                        }
+                        Err(_) => eprintln!(
+                            "Ignoring {} notification with payload '{}'",
+                            notification.channel(),
+                            notification.payload()
+                        ),
+                    }
+                }
+                eprintln!("LISTEN connection closed; reconnecting");
+            }
+            Err(error) => eprintln!("Failed to open LISTEN connection: {error:#}"),
+        }
+
+        sleep(reconnect_delay).await;
+        reconnect_delay = (reconnect_delay * 2).min(LISTEN_RECONNECT_MAX_DELAY);
+    }
+}
+
+/// Latest price already broadcast: event time, slot, event index, signature.
+type PriceSnapshotKey = (i64, u64, u16, String);
+
+/// Position of the last market update already broadcast.
+type MarketUpdateCursor = (DateTime<Utc>, i64, i32);
+
+async fn run_market_stream(
+    runtime: StreamRuntime,
+    markets: Arc<RwLock<HashMap<u64, MarketChannel>>>,
+    market_id: u64,
+    channel: MarketChannel,
+) {
+    let mut latest_snapshot_key: Option<PriceSnapshotKey> = None;
+    let mut update_cursor: Option<MarketUpdateCursor> = None;
+    let mut cursor_is_current = false;
+    let mut latest_candles: HashMap<i64, CandleItem> = HashMap::new();
+    let mut idle = IdleTracker::new(runtime.idle_grace);
+
+    loop {
+        if idle.observe(channel.receiver_count()) {
+            let mut markets = markets.write().await;
+            // A subscriber may have joined while we waited for the lock.
+            if channel.receiver_count() == 0 {
+                markets.remove(&market_id);
+                println!("Stopped idle market stream for market_id={market_id}");
+                return;
+            }
+            continue;
+        }
+
+        if !idle.is_idle() {
+            if channel.candle_intervals.load(Ordering::Relaxed) != 0
+                && channel.candles.receiver_count() == 0
+            {
+                // `subscribe_candles` subscribes and sets its bit under the
+                // map lock, so re-check under the write lock before clearing.
+                let _markets = markets.write().await;
+                if channel.candles.receiver_count() == 0 {
+                    channel.candle_intervals.store(0, Ordering::Relaxed);
+                }
+            }
+            if !cursor_is_current {
+                // Start from the newest row instead of replaying what was
+                // missed while nobody was listening.
+                match latest_market_update_cursor(&runtime, market_id).await {
+                    Ok(cursor) => {
+                        update_cursor = cursor;
+                        cursor_is_current = true;
+                    }
+                    Err(error) => {
+                        eprintln!("Market stream error for market_id={market_id}: {error:#}")
+                    }
+                }
+            }
+            broadcast_market_state(
+                &runtime,
+                market_id,
+                &channel,
+                &mut latest_snapshot_key,
+                &mut update_cursor,
+                &mut latest_candles,
+            )
+            .await;
+        } else {
+            cursor_is_current = false;
+        }
+
+        tokio::select! {
+            _ = channel.wake.notified() => {}
+            _ = sleep(idle.wait(runtime.poll_interval)) => {}
+        }
+    }
+}
+
+async fn broadcast_market_state(
+    runtime: &StreamRuntime,
+    market_id: u64,
+    channel: &MarketChannel,
+    latest_snapshot_key: &mut Option<PriceSnapshotKey>,
+    update_cursor: &mut Option<MarketUpdateCursor>,
+    latest_candles: &mut HashMap<i64, CandleItem>,
+) {
+    match fetch_latest_price_snapshot(&runtime.pool, &runtime.market_updates_table, market_id).await
+    {
+        Ok(Some(snapshot)) => {
+            let snapshot_key = (
+                snapshot.event_time_ms,
+                snapshot.slot,
+                snapshot.event_index,
+                snapshot.signature.clone(),
+            );
+            let is_newer = latest_snapshot_key
+                .as_ref()
+                .map(|latest_key| snapshot_key > *latest_key)
+                .unwrap_or(true);
+            if is_newer {
+                *latest_snapshot_key = Some(snapshot_key);
+                let _ = channel.prices.send(snapshot);
+            }
+        }
+        Ok(None) => {}
+        Err(error) => {
+            eprintln!(
+                "Price stream polling error for market_id={}: {:#}",
+                market_id, error
+            );
+        }
+    }
+
+    if let Err(error) =
+        broadcast_new_market_updates(runtime, market_id, channel, update_cursor).await
+    {
+        eprintln!("Market update stream error for market_id={market_id}: {error:#}");
+    }
+
+    let intervals = CandleInterval::from_bits(channel.candle_intervals.load(Ordering::Relaxed));
+    if !intervals.is_empty() {
+        if let Err(error) =
+            broadcast_current_candles(runtime, market_id, channel, &intervals, latest_candles).await
+        {
+            eprintln!("Candle stream error for market_id={market_id}: {error:#}");
+        }
+    }
+}
+
+async fn latest_market_update_cursor(
+    runtime: &StreamRuntime,
+    market_id: u64,
+) -> Result<Option<MarketUpdateCursor>> {
+    let market_id_i64 = i64::try_from(market_id).context("market_id out of range")?;
+    let sql = format!(
+        "SELECT event_time, slot, event_index FROM {} \
+         WHERE market_id = $1 \
+         ORDER BY event_time DESC, slot DESC, event_index DESC \
+         LIMIT 1",
+        runtime.market_updates_table
+    );
+    let client = runtime
+        .pool
+        .get()
+        .await
+        .context("Failed to get DB connection")?;
+    let row = client
+        .query_opt(&sql, &[&market_id_i64])
+        .await
+        .context("Failed to query latest market update")?;
+    Ok(row.map(|row| (row.get(0), row.get(1), row.get(2))))
+}
+
+async fn broadcast_new_market_updates(
+    runtime: &StreamRuntime,
+    market_id: u64,
+    channel: &MarketChannel,
+    cursor: &mut Option<MarketUpdateCursor>,
+) -> Result<()> {
+    let market_id_i64 = i64::try_from(market_id).context("market_id out of range")?;
+    let client = runtime
+        .pool
+        .get()
+        .await
+        .context("Failed to get DB connection")?;
+
+    let select_columns = format!(
+        "SELECT event_uid, signature, event_index, slot, market_id, base_flow, quote_flow, \
+            (extract(epoch from event_time) * 1000)::bigint AS event_time_ms, event_time \
+         FROM {}",
+        runtime.market_updates_table
+    );
+    let rows = match cursor {
+        Some((event_time, slot, event_index)) => {
+            let sql = format!(
+                "{select_columns} \
+                 WHERE market_id = $1 \
+                   AND (event_time, slot, event_index) > ($2, $3, $4) \
+                 ORDER BY event_time, slot, event_index \
+                 LIMIT $5"
+            );
+            client
+                .query(
+                    &sql,
+                    &[
+                        &market_id_i64,
+                        event_time,
+                        slot,
+                        event_index,
+                        &STREAM_FETCH_LIMIT,
+                    ],
+                )
+                .await
+        }
+        None => {
+            let sql = format!(
+                "{select_columns} \
+                 WHERE market_id = $1 \
+                 ORDER BY event_time, slot, event_index \
+                 LIMIT $2"
+            );
+            client
+                .query(&sql, &[&market_id_i64, &STREAM_FETCH_LIMIT])
+                .await
+        }
+    }
+    .context("Failed to query new market updates")?;
+
+    for row in rows {
+        *cursor = Some((
+            row.get("event_time"),
+            row.get("slot"),
+            row.get("event_index"),
+        ));
+        let history_row = market_history_row_from_pg(&row);
+        if history_row.event_uid.starts_with("debug:") {
+            continue;
+        }
+        match market_history_item_from_row(history_row) {
+            Ok(item) => {
+                let _ = channel.updates.send(item);
+            }
+            Err(error) => eprintln!("Skipping market update: {}", error.message),
+        }
+    }
+    Ok(())
+}
+
+/// Read the current bucket of each interval the same way `/candles` does and
+/// broadcast those that changed since the last wake. A bucket without trades
+/// yet carries the previous close forward, so a new bucket shows up as soon as
+/// it starts.
+async fn broadcast_current_candles(
+    runtime: &StreamRuntime,
+    market_id: u64,
+    channel: &MarketChannel,
+    intervals: &[CandleInterval],
+    latest_candles: &mut HashMap<i64, CandleItem>,
+) -> Result<()> {
+    let market_id_i64 = i64::try_from(market_id).context("market_id out of range")?;
+    let client = runtime
+        .pool
+        .get()
+        .await
+        .context("Failed to get DB connection")?;
+    let now = Utc::now().timestamp();
+
+    for &interval in intervals {
+        let step = interval.step_seconds();
+        let bucket_s = now.div_euclid(step) * step;
+        let (Some(from), Some(to)) = (
+            DateTime::from_timestamp(bucket_s, 0),
+            DateTime::from_timestamp(bucket_s + step, 0),
+        ) else {
+            continue;
+        };
+        let Some(candle) = runtime
+            .candles
+            .query(&client, market_id_i64, from, to, interval)
+            .await
+            .with_context(|| format!("Failed to query current {} candle", interval.as_str()))?
+            .pop()
+        else {
+            continue;
+        };
+
+        if latest_candles.get(&step) == Some(&candle) {
+            continue;
+        }
+        latest_candles.insert(step, candle.clone());
+        let _ = channel.candles.send(CandleUpdate { interval, candle });
+    }
+    Ok(())
+}
+
+/// Position of the last close-position event already broadcast.
+type ClosePositionCursor = (DateTime<Utc>, String);
+
+async fn run_close_position_stream(
+    runtime: StreamRuntime,
+    slot: Arc<RwLock<Option<ClosePositionChannel>>>,
+    channel: ClosePositionChannel,
+) {
+    let mut cursor: Option<ClosePositionCursor> = None;
+    let mut cursor_is_current = false;
+    let mut idle = IdleTracker::new(runtime.idle_grace);
+
+    loop {
+        if idle.observe(channel.sender.receiver_count()) {
+            let mut slot = slot.write().await;
+            if channel.sender.receiver_count() == 0 {
+                *slot = None;
+                println!("Stopped idle close-position stream");
+                return;
+            }
+            continue;
+        }
+
+        if !idle.is_idle() {
+            if !cursor_is_current {
+                match latest_close_position_cursor(&runtime).await {
+                    Ok(latest) => {
+                        cursor = latest;
+                        cursor_is_current = true;
+                    }
+                    Err(error) => eprintln!("Close-position stream error: {error:#}"),
+                }
+            }
+            if let Err(error) = broadcast_new_close_positions(&runtime, &channel, &mut cursor).await
+            {
+                eprintln!("Close-position stream error: {error:#}");
+            }
+        } else {
+            cursor_is_current = false;
+        }
+
+        tokio::select! {
+            _ = channel.wake.notified() => {}
+            _ = sleep(idle.wait(runtime.poll_interval)) => {}
+        }
+    }
+}
+
+async fn latest_close_position_cursor(
+    runtime: &StreamRuntime,
+) -> Result<Option<ClosePositionCursor>> {
+    let sql = format!(
+        "SELECT event_time, event_uid FROM {} ORDER BY event_time DESC, event_uid DESC LIMIT 1",
+        runtime.close_position_events_table
+    );
+    let client = runtime
+        .pool
+        .get()
+        .await
+        .context("Failed to get DB connection")?;
+    let row = client
+        .query_opt(&sql, &[])
+        .await
+        .context("Failed to query latest close-position event")?;
+    Ok(row.map(|row| (row.get(0), row.get(1))))
+}
+
+async fn broadcast_new_close_positions(
+    runtime: &StreamRuntime,
+    channel: &ClosePositionChannel,
+    cursor: &mut Option<ClosePositionCursor>,
+) -> Result<()> {
+    let client = runtime
+        .pool
+        .get()
+        .await
+        .context("Failed to get DB connection")?;
+
+    let select_columns = format!(
+        "SELECT event_uid, event_time, position_authority, signature, event_index, slot, \
+            market_id, start_slot, end_slot, deposit_amount, swapped_amount, remaining_amount, \
+            fee_amount, is_buy, (extract(epoch from event_time) * 1000)::bigint AS event_time_ms \
+         FROM {}",
+        runtime.close_position_events_table
+    );
+    let rows = match cursor {
+        Some((event_time, event_uid)) => {
+            let sql = format!(
+                "{select_columns} \
+                 WHERE (event_time, event_uid) > ($1, $2) \
+                 ORDER BY event_time, event_uid \
+                 LIMIT $3"
+            );
+            client
+                .query(&sql, &[event_time, event_uid, &STREAM_FETCH_LIMIT])
+                .await
+        }
+        None => {
+            let sql = format!(
+                "{select_columns} \
+                 ORDER BY event_time, event_uid \
+                 LIMIT $1"
+            );
+            client.query(&sql, &[&STREAM_FETCH_LIMIT]).await
+        }
+    }
+    .context("Failed to query new close-position events")?;
+
+    for row in rows {
+        *cursor = Some((row.get("event_time"), row.get("event_uid")));
+        let authority: String = row.get("position_authority");
+        match closed_position_item_from_row(closed_position_row_from_pg(&row)) {
+            Ok(item) => {
+                let _ = channel.sender.send(ClosePositionUpdate { authority, item });
+            }
+            Err(error) => eprintln!("Skipping close-position event: {}", error.message),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_idle_tracker_stops_after_grace() {
+        let mut idle = IdleTracker::new(Duration::from_secs(60));
+
+        assert!(!idle.observe(1));
+        assert_eq!(
+            idle.wait(Duration::from_secs(120)),
+            Duration::from_secs(120)
+        );
+
+        assert!(!idle.observe(0));
+        assert_eq!(idle.wait(Duration::from_secs(120)), Duration::from_secs(60));
+        tokio::time::advance(Duration::from_secs(59)).await;
+        assert!(!idle.observe(0));
+
+        // A returning subscriber restarts the grace period.
+        assert!(!idle.observe(2));
+        assert!(!idle.observe(0));
+        tokio::time::advance(Duration::from_secs(60)).await;
+        assert!(idle.observe(0));
+    }
+}
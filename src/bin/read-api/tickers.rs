@@ -0,0 +1,313 @@
+//! Rolling 24h ticker statistics per market: `/v1/markets/{market_id}/ticker`
+//! and `/v1/tickers`.
+//!
+//! Prices come from the 1-minute candles and volume from the raw market
+//! updates. All markets are computed in one query and the result is kept for
+//! [`TICKER_CACHE_TTL`], so the batch endpoint and per-market lookups share one
+//! read per TTL however often clients poll.
+
+use anyhow::{Context, Result, anyhow};
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::header,
+    response::IntoResponse,
+};
+use chrono::{DateTime, SecondsFormat, Utc};
+use deadpool_postgres::Pool;
+use rust_decimal::Decimal;
+use std::{sync::Arc, time::Duration};
+use tokio::{sync::Mutex, time::Instant};
+use twob_keepers::api::{ErrorResponse, TickerItem, TickerListResponse};
+
+use crate::{ApiError, AppState};
+
+const TICKER_CACHE_TTL: Duration = Duration::from_secs(10);
+const TICKER_CACHE_CONTROL: &str = "public, max-age=10, stale-while-revalidate=10";
+const TICKER_WINDOW_SECS: i64 = 24 * 60 * 60;
+
+/// Tickers of every market and when they were computed.
+type TickerSnapshot = (Instant, Arc<Vec<TickerItem>>);
+
+/// Last computed tickers for every market.
+#[derive(Clone, Default)]
+pub(crate) struct TickerCache {
+    entry: Arc<Mutex<Option<TickerSnapshot>>>,
+}
+
+impl TickerCache {
+    /// Return the cached tickers, recomputing them once the TTL has passed.
+    /// Concurrent callers wait for a single recomputation.
+    pub(crate) async fn get(&self, state: &AppState) -> Result<Arc<Vec<TickerItem>>> {
+        let mut entry = self.entry.lock().await;
+        if let Some((computed_at, tickers)) = entry.as_ref() {
+            if computed_at.elapsed() < TICKER_CACHE_TTL {
+                return Ok(tickers.clone());
+            }
+        }
+
+        let tickers = Arc::new(
+            query_tickers(
+                &state.pool,
+                &state.config.candles_1m_table,
+                &state.config.market_updates_table,
+            )
+            .await?,
+        );
+        *entry = Some((Instant::now(), tickers.clone()));
+        Ok(tickers)
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/tickers",
+    responses((status = 200, body = TickerListResponse))
+)]
+pub(crate) async fn list_tickers(
+    State(state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, ApiError> {
+    let items = state
+        .tickers
+        .get(&state)
+        .await
+        .map_err(|error| ApiError::internal(error.context("Failed to compute tickers")))?;
+
+    Ok((
+        [(header::CACHE_CONTROL, TICKER_CACHE_CONTROL)],
+        Json(TickerListResponse {
+            points: items.len(),
+            items: items.as_ref().clone(),
+        }),
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/markets/{market_id}/ticker",
+    params(("market_id" = u64, Path, description = "Market id")),
+    responses(
+        (status = 200, body = TickerItem),
+        (status = 404, body = ErrorResponse),
+    )
+)]
+pub(crate) async fn get_market_ticker(
+    State(state): State<Arc<AppState>>,
+    Path(market_id): Path<u64>,
+) -> Result<impl IntoResponse, ApiError> {
+    let items = state
+        .tickers
+        .get(&state)
+        .await
+        .map_err(|error| ApiError::internal(error.context("Failed to compute tickers")))?;
+    let ticker = items
+        .iter()
+        .find(|ticker| ticker.market_id == market_id)
+        .cloned()
+        .ok_or_else(|| ApiError::not_found(format!("No config for market_id={market_id}")))?;
+
+    Ok((
+        [(header::CACHE_CONTROL, TICKER_CACHE_CONTROL)],
+        Json(ticker),
+    ))
+}
+
+async fn query_tickers(
+    pool: &Pool,
+    candles_1m_table: &str,
+    market_updates_table: &str,
+) -> Result<Vec<TickerItem>> {
+    // The flow active when the window opens comes from the last update before
+    // it. Its slots up to the next update are clipped to the window by the
+    // share of that span's time that falls inside it.
+    let sql = format!(
+        "WITH bounds AS ( \
+            SELECT now() AS as_of, now() - $1::bigint * interval '1 second' AS since, \
+                   (SELECT max(slot) FROM {updates}) AS latest_slot \
+         ) \
+         SELECT mc.market_id, mc.base_ticker, mc.quote_ticker, b.as_of, b.since, \
+                prev.close AS prev_close, win.open, win.high, win.low, win.close, \
+                CASE WHEN mc.base_decimals IS NOT NULL \
+                     THEN vol.base_traded / power(10::numeric, mc.base_decimals::numeric) \
+                END AS base_volume, \
+                CASE WHEN mc.quote_decimals IS NOT NULL \
+                     THEN vol.quote_traded / power(10::numeric, mc.quote_decimals::numeric) \
+                END AS quote_volume, \
+                vol.update_count \
+         FROM market_configs mc \
+         CROSS JOIN bounds b \
+         LEFT JOIN LATERAL ( \
+            SELECT c.close FROM {candles} c \
+            WHERE c.market_id = mc.market_id AND c.bucket_start < b.since \
+            ORDER BY c.bucket_start DESC LIMIT 1 \
+         ) prev ON true \
+         LEFT JOIN LATERAL ( \
+            SELECT (array_agg(c.open ORDER BY c.bucket_start))[1] AS open, \
+                   max(c.high) AS high, \
+                   min(c.low) AS low, \
+                   (array_agg(c.close ORDER BY c.bucket_start DESC))[1] AS close \
+            FROM {candles} c \
+            WHERE c.market_id = mc.market_id AND c.bucket_start >= b.since \
+         ) win ON true \
+         LEFT JOIN LATERAL ( \
+            SELECT coalesce(sum(seg.base_flow::numeric * seg.slots), 0) AS base_traded, \
+                   coalesce(sum(seg.quote_flow::numeric * seg.slots), 0) AS quote_traded, \
+                   count(*) FILTER (WHERE seg.event_time >= b.since) AS update_count \
+            FROM ( \
+                SELECT u.base_flow, u.quote_flow, u.event_time, \
+                       greatest(coalesce(lead(u.slot) OVER w, b.latest_slot) - u.slot, 0) \
+                         * CASE WHEN u.event_time >= b.since THEN 1 \
+                                ELSE coalesce( \
+                                    extract(epoch FROM lead(u.event_time) OVER w - b.since) \
+                                      / nullif(extract(epoch FROM lead(u.event_time) OVER w \
+                                                              - u.event_time), 0), \
+                                    0) \
+                           END AS slots \
+                FROM ( \
+                    (SELECT r.slot, r.event_index, r.event_time, r.base_flow, r.quote_flow \
+                     FROM {updates} r \
+                     WHERE r.market_id = mc.market_id AND r.event_time < b.since \
+                       AND r.event_uid NOT LIKE 'debug:%' \
+                     ORDER BY r.event_time DESC, r.slot DESC, r.event_index DESC LIMIT 1) \
+                    UNION ALL \
+                    (SELECT r.slot, r.event_index, r.event_time, r.base_flow, r.quote_flow \
+                     FROM {updates} r \
+                     WHERE r.market_id = mc.market_id AND r.event_time >= b.since \
+                       AND r.event_uid NOT LIKE 'debug:%') \
+                ) u \
+                WINDOW w AS (ORDER BY u.slot, u.event_index) \
+            ) seg \
+         ) vol ON true \
+         ORDER BY mc.market_id ASC",
+        candles = candles_1m_table,
+        updates = market_updates_table,
+    );
+
+    let client = pool.get().await.context("Failed to get DB connection")?;
+    let rows = client
+        .query(&sql, &[&TICKER_WINDOW_SECS])
+        .await
+        .context("Failed to query tickers")?;
+
+    rows.iter()
+        .map(|row| {
+            let market_id: i64 = row.get("market_id");
+            let as_of: DateTime<Utc> = row.get("as_of");
+            let since: DateTime<Utc> = row.get("since");
+            let update_count: i64 = row.get("update_count");
+            let stats = WindowStats {
+                prev_close: row.get("prev_close"),
+                open: row.get("open"),
+                high: row.get("high"),
+                low: row.get("low"),
+                close: row.get("close"),
+            }
+            .resolve();
+
+            Ok(TickerItem {
+                market_id: u64::try_from(market_id)
+                    .map_err(|_| anyhow!("market_id out of range: {market_id}"))?,
+                base_ticker: row.get("base_ticker"),
+                quote_ticker: row.get("quote_ticker"),
+                open: stats.open,
+                high: stats.high,
+                low: stats.low,
+                last: stats.last,
+                change_percent: stats.change_percent,
+                base_volume: row.get("base_volume"),
+                quote_volume: row.get("quote_volume"),
+                update_count: update_count.max(0) as u64,
+                window_start: since.to_rfc3339_opts(SecondsFormat::Secs, true),
+                as_of: as_of.to_rfc3339_opts(SecondsFormat::Secs, true),
+            })
+        })
+        .collect()
+}
+
+/// Candle aggregates of one market: the close before the window and the
+/// OHLC inside it.
+struct WindowStats {
+    prev_close: Option<Decimal>,
+    open: Option<Decimal>,
+    high: Option<Decimal>,
+    low: Option<Decimal>,
+    close: Option<Decimal>,
+}
+
+#[derive(Debug, PartialEq)]
+struct TickerPrices {
+    open: Option<Decimal>,
+    high: Option<Decimal>,
+    low: Option<Decimal>,
+    last: Option<Decimal>,
+    change_percent: Option<Decimal>,
+}
+
+impl WindowStats {
+    /// A market without candles in the window is flat at its last close.
+    fn resolve(self) -> TickerPrices {
+        let open = self.prev_close.or(self.open);
+        let last = self.close.or(self.prev_close);
+        let change_percent = match (open, last) {
+            (Some(open), Some(last)) if !open.is_zero() => {
+                Some(((last - open) / open * Decimal::ONE_HUNDRED).round_dp(4))
+            }
+            _ => None,
+        };
+
+        TickerPrices {
+            open,
+            high: self.high.or(self.prev_close),
+            low: self.low.or(self.prev_close),
+            last,
+            change_percent,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_uses_close_before_window_as_open() {
+        let prices = WindowStats {
+            prev_close: Some(Decimal::from(4)),
+            open: Some(Decimal::from(5)),
+            high: Some(Decimal::from(6)),
+            low: Some(Decimal::from(3)),
+            close: Some(Decimal::from(5)),
+        }
+        .resolve();
+
+        assert_eq!(prices.open, Some(Decimal::from(4)));
+        assert_eq!(prices.last, Some(Decimal::from(5)));
+        assert_eq!(prices.change_percent, Some(Decimal::from(25)));
+    }
+
+    #[test]
+    fn test_resolve_idle_and_new_markets() {
+        let idle = WindowStats {
+            prev_close: Some(Decimal::from(2)),
+            open: None,
+            high: None,
+            low: None,
+            close: None,
+        }
+        .resolve();
+        assert_eq!(idle.high, Some(Decimal::from(2)));
+        assert_eq!(idle.low, Some(Decimal::from(2)));
+        assert_eq!(idle.change_percent, Some(Decimal::ZERO));
+
+        let empty = WindowStats {
+            prev_close: None,
+            open: None,
+            high: None,
+            low: None,
+            close: None,
+        }
+        .resolve();
+        assert_eq!(empty.last, None);
+        assert_eq!(empty.change_percent, None);
+    }
+}
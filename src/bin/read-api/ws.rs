@@ -0,0 +1,399 @@
+//! `/v1/ws`: one WebSocket carrying many live subscriptions.
+//!
+//! Client → server (one JSON object per text frame):
+//!
+//! ```json
+//! {"op": "subscribe", "channel": "price", "market_id": 1}
+//! {"op": "subscribe", "channel": "candles", "market_id": 1, "interval": "1m"}
+//! {"op": "subscribe", "channel": "market_updates", "market_id": 1}
+//! {"op": "subscribe", "channel": "close_positions", "market_id": 1}
+//! {"op": "subscribe", "channel": "close_positions", "authority": "<pubkey>"}
+//! {"op": "unsubscribe", "channel": "price", "market_id": 1}
+//! {"op": "ping"}
+//! ```
+//!
+//! Server → client messages carry a `type`: `subscribed`/`unsubscribed` echo
+//! the subscription, data arrives as `price_update`, `candle_update`,
+//! `market_update` or `close_position`, and `error`/`pong` answer requests.
+//! Data payloads match the REST item shapes.
+
+use axum::{
+    Extension,
+    extract::{
+        State,
+        ws::{Message, WebSocket, WebSocketUpgrade},
+    },
+    response::Response,
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, sync::Arc};
+use tokio::{
+    sync::{broadcast, mpsc},
+    task::JoinHandle,
+};
+
+use crate::{
+    ApiError, AppState, CandleInterval, CandleItem, ClosedPositionItem, LatestPriceResponse,
+    MarketHistoryItem, access::StreamPermit,
+};
+
+/// Subscriptions a single connection may hold at once.
+const MAX_SUBSCRIPTIONS_PER_CONNECTION: usize = 64;
+/// Outgoing messages buffered per connection before forwarders wait.
+const OUTGOING_BUFFER: usize = 1024;
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq, Hash)]
+#[serde(tag = "channel", rename_all = "snake_case")]
+enum Subscription {
+    Price {
+        market_id: u64,
+    },
+    Candles {
+        market_id: u64,
+        interval: String,
+    },
+    MarketUpdates {
+        market_id: u64,
+    },
+    ClosePositions {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        market_id: Option<u64>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        authority: Option<String>,
+    },
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum ClientMessage {
+    Subscribe(Subscription),
+    Unsubscribe(Subscription),
+    Ping,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ServerMessage<'a> {
+    Subscribed {
+        subscription: &'a Subscription,
+    },
+    Unsubscribed {
+        subscription: &'a Subscription,
+    },
+    PriceUpdate {
+        market_id: u64,
+        data: &'a LatestPriceResponse,
+    },
+    CandleUpdate {
+        market_id: u64,
+        interval: &'static str,
+        data: &'a CandleItem,
+    },
+    MarketUpdate {
+        market_id: u64,
+        data: &'a MarketHistoryItem,
+    },
+    ClosePosition {
+        authority: &'a str,
+        data: &'a ClosedPositionItem,
+    },
+    Error {
+        message: String,
+    },
+    Pong,
+}
+
+impl Subscription {
+    /// Validate the request and canonicalise it so an `unsubscribe` matches
+    /// the `subscribe` it undoes.
+    fn normalize(self) -> Result<Self, String> {
+        match self {
+            Self::Candles {
+                market_id,
+                interval,
+            } => {
+                let interval =
+                    CandleInterval::parse(Some(&interval)).map_err(|error| error.to_string())?;
+                Ok(Self::Candles {
+                    market_id,
+                    interval: interval.as_str().to_string(),
+                })
+            }
+            Self::ClosePositions {
+                market_id,
+                authority,
+            } => {
+                let authority = authority
+                    .map(|authority| authority.trim().to_string())
+                    .filter(|authority| !authority.is_empty());
+                if market_id.is_none() && authority.is_none() {
+                    return Err("close_positions needs 'market_id' or 'authority'".to_string());
+                }
+                Ok(Self::ClosePositions {
+                    market_id,
+                    authority,
+                })
+            }
+            other => Ok(other),
+        }
+    }
+}
+
+pub(crate) async fn market_socket(
+    State(state): State<Arc<AppState>>,
+    permit: Option<Extension<StreamPermit>>,
+    ws: WebSocketUpgrade,
+) -> Response {
+    ws.on_upgrade(move |socket| async move {
+        handle_socket(socket, state).await;
+        // The connection counts as an open stream until it closes.
+        drop(permit);
+    })
+}
+
+async fn handle_socket(mut socket: WebSocket, state: Arc<AppState>) {
+    let (outgoing_sender, mut outgoing) = mpsc::channel::<String>(OUTGOING_BUFFER);
+    let mut subscriptions: HashMap<Subscription, JoinHandle<()>> = HashMap::new();
+
+    loop {
+        tokio::select! {
+            incoming = socket.recv() => {
+                let text = match incoming {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Close(_))) | None | Some(Err(_)) => break,
+                    Some(Ok(_)) => continue,
+                };
+                let reply = handle_client_message(
+                    &state,
+                    &text,
+                    &mut subscriptions,
+                    &outgoing_sender,
+                )
+                .await;
+                if socket.send(Message::Text(reply.into())).await.is_err() {
+                    break;
+                }
+            }
+            Some(message) = outgoing.recv() => {
+                if socket.send(Message::Text(message.into())).await.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+
+    for (_, forwarder) in subscriptions {
+        forwarder.abort();
+    }
+}
+
+async fn handle_client_message(
+    state: &AppState,
+    text: &str,
+    subscriptions: &mut HashMap<Subscription, JoinHandle<()>>,
+    outgoing: &mpsc::Sender<String>,
+) -> String {
+    let message = match serde_json::from_str::<ClientMessage>(text) {
+        Ok(message) => message,
+        Err(error) => return error_message(format!("Invalid message: {error}")),
+    };
+
+    match message {
+        ClientMessage::Ping => encode(&ServerMessage::Pong),
+        ClientMessage::Subscribe(subscription) => {
+            let subscription = match subscription.normalize() {
+                Ok(subscription) => subscription,
+                Err(message) => return error_message(message),
+            };
+            if !subscriptions.contains_key(&subscription) {
+                if subscriptions.len() >= MAX_SUBSCRIPTIONS_PER_CONNECTION {
+                    return error_message(format!(
+                        "At most {MAX_SUBSCRIPTIONS_PER_CONNECTION} subscriptions per connection"
+                    ));
+                }
+                let forwarder = match spawn_forwarder(state, &subscription, outgoing.clone()).await
+                {
+                    Ok(forwarder) => forwarder,
+                    Err(error) => return error_message(error.message),
+                };
+                subscriptions.insert(subscription.clone(), forwarder);
+            }
+            encode(&ServerMessage::Subscribed {
+                subscription: &subscription,
+            })
+        }
+        ClientMessage::Unsubscribe(subscription) => {
+            let subscription = match subscription.normalize() {
+                Ok(subscription) => subscription,
+                Err(message) => return error_message(message),
+            };
+            match subscriptions.remove(&subscription) {
+                Some(forwarder) => {
+                    forwarder.abort();
+                    encode(&ServerMessage::Unsubscribed {
+                        subscription: &subscription,
+                    })
+                }
+                None => error_message("Not subscribed".to_string()),
+            }
+        }
+    }
+}
+
+/// Spawn a task that relays one subscription's broadcast into the
+/// connection's outgoing queue.
+async fn spawn_forwarder(
+    state: &AppState,
+    subscription: &Subscription,
+    outgoing: mpsc::Sender<String>,
+) -> Result<JoinHandle<()>, ApiError> {
+    let streams = &state.market_streams;
+    let forwarder = match subscription.clone() {
+        Subscription::Price { market_id } => {
+            let receiver = streams.subscribe_prices(market_id).await?;
+            tokio::spawn(forward(receiver, outgoing, move |snapshot| {
+                Some(encode(&ServerMessage::PriceUpdate {
+                    market_id,
+                    data: snapshot,
+                }))
+            }))
+        }
+        Subscription::Candles {
+            market_id,
+            interval,
+        } => {
+            // `normalize` already accepted the interval.
+            let interval = CandleInterval::parse(Some(&interval)).unwrap_or(CandleInterval::M1);
+            let receiver = streams.subscribe_candles(market_id, interval).await?;
+            tokio::spawn(forward(receiver, outgoing, move |update| {
+                (update.interval == interval).then(|| {
+                    encode(&ServerMessage::CandleUpdate {
+                        market_id,
+                        interval: interval.as_str(),
+                        data: &update.candle,
+                    })
+                })
+            }))
+        }
+        Subscription::MarketUpdates { market_id } => {
+            let receiver = streams.subscribe_updates(market_id).await?;
+            tokio::spawn(forward(receiver, outgoing, move |item| {
+                Some(encode(&ServerMessage::MarketUpdate {
+                    market_id,
+                    data: item,
+                }))
+            }))
+        }
+        Subscription::ClosePositions {
+            market_id,
+            authority,
+        } => {
+            let receiver = streams.subscribe_close_positions().await;
+            tokio::spawn(forward(receiver, outgoing, move |update| {
+                let market_matches =
+                    market_id.is_none_or(|market_id| update.item.market_id == market_id);
+                let authority_matches = authority
+                    .as_deref()
+                    .is_none_or(|authority| update.authority == authority);
+                (market_matches && authority_matches).then(|| {
+                    encode(&ServerMessage::ClosePosition {
+                        authority: &update.authority,
+                        data: &update.item,
+                    })
+                })
+            }))
+        }
+    };
+    Ok(forwarder)
+}
+
+async fn forward<T, F>(
+    mut receiver: broadcast::Receiver<T>,
+    outgoing: mpsc::Sender<String>,
+    render: F,
+) where
+    T: Clone,
+    F: Fn(&T) -> Option<String>,
+{
+    loop {
+        let message = match receiver.recv().await {
+            Ok(value) => match render(&value) {
+                Some(message) => message,
+                None => continue,
+            },
+            Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                error_message(format!("Subscriber lagged; skipped {skipped} message(s)"))
+            }
+            Err(broadcast::error::RecvError::Closed) => return,
+        };
+        if outgoing.send(message).await.is_err() {
+            return;
+        }
+    }
+}
+
+fn error_message(message: String) -> String {
+    encode(&ServerMessage::Error { message })
+}
+
+fn encode(message: &ServerMessage<'_>) -> String {
+    serde_json::to_string(message).unwrap_or_else(|error| {
+        format!(r#"{{"type":"error","message":"Failed to encode message: {error}"}}"#)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_subscribe_messages() {
+        let message: ClientMessage = serde_json::from_str(
+            r#"{"op":"subscribe","channel":"candles","market_id":7,"interval":"5m"}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            message,
+            ClientMessage::Subscribe(Subscription::Candles {
+                market_id: 7,
+                interval: "5m".to_string(),
+            })
+        );
+
+        let message: ClientMessage = serde_json::from_str(
+            r#"{"op":"unsubscribe","channel":"close_positions","authority":"abc"}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            message,
+            ClientMessage::Unsubscribe(Subscription::ClosePositions {
+                market_id: None,
+                authority: Some("abc".to_string()),
+            })
+        );
+
+        let message: ClientMessage = serde_json::from_str(r#"{"op":"ping"}"#).unwrap();
+        assert_eq!(message, ClientMessage::Ping);
+    }
+
+    #[test]
+    fn test_normalize_rejects_unfiltered_close_positions() {
+        let subscription = Subscription::ClosePositions {
+            market_id: None,
+            authority: Some("  ".to_string()),
+        };
+        assert!(subscription.normalize().is_err());
+
+        let subscription = Subscription::Candles {
+            market_id: 1,
+            interval: " 1h ".to_string(),
+        };
+        assert_eq!(
+            subscription.normalize().unwrap(),
+            Subscription::Candles {
+                market_id: 1,
+                interval: "1h".to_string(),
+            }
+        );
+    }
+}
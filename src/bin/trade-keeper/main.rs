@@ -7,7 +7,7 @@ use anchor_client::{
 use anchor_lang::prelude::*;
 use anchor_spl::{associated_token::spl_associated_token_account, token::spl_token};
 
-use std::{env, sync::Arc, u64};
+use std::{env, sync::Arc};
 use twob_keepers::{ARRAY_LENGTH, AccountResolver};
 
 use tokio::time::{Duration, sleep};
@@ -97,10 +97,10 @@ async fn main() -> anyhow::Result<()> {
                         .accounts(accounts::PublicClosePosition {
                             signer: payer.pubkey(),
                             position_authority: position_account.authority,
-                            base_mint: base_mint,
-                            quote_mint: quote_mint,
+                            base_mint,
+                            quote_mint,
                             authority_base_token_account: tmp_pubkey,
-                            authority_quote_token_account: authority_quote_token_account,
+                            authority_quote_token_account,
                             market: market_pda.address(),
                             trade_position: *position_address,
                             base_vault: base_vault_address,
@@ -117,9 +117,7 @@ async fn main() -> anyhow::Result<()> {
                             associated_token_program: spl_associated_token_account::ID,
                             system_program: system_program::ID,
                         })
-                        .args(args::PublicClosePosition {
-                            reference_index: reference_index,
-                        })
+                        .args(args::PublicClosePosition { reference_index })
                         .instructions()?
                         .remove(0);
 
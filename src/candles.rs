@@ -0,0 +1,185 @@
+//! Candle gap-filling for the plain-Postgres storage backend.
+//!
+//! Timescale answers candle queries with `time_bucket_gapfill` + `locf`. Stock
+//! Postgres has neither, so this module reads the stored 1-minute candles and
+//! rebuilds the same bucket rows in Rust.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use tokio_postgres::Client;
+
+/// One stored candle row.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CandleRow {
+    pub bucket_start: DateTime<Utc>,
+    pub open: Decimal,
+    pub high: Decimal,
+    pub low: Decimal,
+    pub close: Decimal,
+}
+
+/// One output bucket, shaped like a `time_bucket_gapfill` row: the OHLC
+/// aggregates are `None` for empty buckets, and `carried_close` is the `locf`
+/// close (`None` until the first known close).
+#[derive(Clone, Debug, PartialEq)]
+pub struct GapfilledCandle {
+    pub bucket_start: DateTime<Utc>,
+    pub open: Option<Decimal>,
+    pub high: Option<Decimal>,
+    pub low: Option<Decimal>,
+    pub close: Option<Decimal>,
+    pub carried_close: Option<Decimal>,
+}
+
+/// Read candles in `[from, to)` from `table` and gap-fill them into
+/// `step_seconds` buckets aligned to the Unix epoch, as `time_bucket` does.
+pub async fn query_gapfilled_candles(
+    client: &Client,
+    table: &str,
+    market_id: i64,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    step_seconds: i64,
+) -> Result<Vec<GapfilledCandle>> {
+    let seed_sql = format!(
+        "SELECT close FROM {table} \
+         WHERE market_id = $1 AND bucket_start < $2 \
+         ORDER BY bucket_start DESC LIMIT 1"
+    );
+    let seed_close: Option<Decimal> = client
+        .query_opt(&seed_sql, &[&market_id, &from])
+        .await
+        .context("Failed to query seed candle")?
+        .map(|row| row.get(0));
+
+    let rows_sql = format!(
+        "SELECT bucket_start, open, high, low, close FROM {table} \
+         WHERE market_id = $1 AND bucket_start >= $2 AND bucket_start < $3 \
+         ORDER BY bucket_start"
+    );
+    let rows = client
+        .query(&rows_sql, &[&market_id, &from, &to])
+        .await
+        .context("Failed to query candles")?
+        .into_iter()
+        .map(|row| CandleRow {
+            bucket_start: row.get(0),
+            open: row.get(1),
+            high: row.get(2),
+            low: row.get(3),
+            close: row.get(4),
+        })
+        .collect::<Vec<_>>();
+
+    Ok(gapfill_candles(&rows, seed_close, from, to, step_seconds))
+}
+
+/// Aggregate `rows` (sorted by `bucket_start`) into every `step_seconds`
+/// bucket from the one containing `from` up to `to`, carrying the last close
+/// forward through empty buckets starting from `seed_close`.
+pub fn gapfill_candles(
+    rows: &[CandleRow],
+    seed_close: Option<Decimal>,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    step_seconds: i64,
+) -> Vec<GapfilledCandle> {
+    if step_seconds <= 0 || to <= from {
+        return Vec::new();
+    }
+
+    let to_s = to.timestamp();
+    let mut bucket_s = from.timestamp().div_euclid(step_seconds) * step_seconds;
+    let mut rows = rows.iter().peekable();
+    let mut carried_close = seed_close;
+    let mut buckets = Vec::new();
+
+    while bucket_s < to_s || (bucket_s == to_s && to.timestamp_subsec_nanos() > 0) {
+        let bucket_end_s = bucket_s + step_seconds;
+        let mut aggregate: Option<(Decimal, Decimal, Decimal, Decimal)> = None;
+
+        while let Some(row) = rows.next_if(|row| row.bucket_start.timestamp() < bucket_end_s) {
+            aggregate = Some(match aggregate {
+                None => (row.open, row.high, row.low, row.close),
+                Some((open, high, low, _)) => {
+                    (open, high.max(row.high), low.min(row.low), row.close)
+                }
+            });
+        }
+
+        if let Some((_, _, _, close)) = aggregate {
+            carried_close = Some(close);
+        }
+
+        let Some(bucket_start) = DateTime::from_timestamp(bucket_s, 0) else {
+            break;
+        };
+        buckets.push(GapfilledCandle {
+            bucket_start,
+            open: aggregate.map(|(open, _, _, _)| open),
+            high: aggregate.map(|(_, high, _, _)| high),
+            low: aggregate.map(|(_, _, low, _)| low),
+            close: aggregate.map(|(_, _, _, close)| close),
+            carried_close,
+        });
+
+        bucket_s = bucket_end_s;
+    }
+
+    buckets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(seconds: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(seconds, 0).unwrap()
+    }
+
+    fn row(seconds: i64, open: i64, high: i64, low: i64, close: i64) -> CandleRow {
+        CandleRow {
+            bucket_start: at(seconds),
+            open: Decimal::from(open),
+            high: Decimal::from(high),
+            low: Decimal::from(low),
+            close: Decimal::from(close),
+        }
+    }
+
+    #[test]
+    fn test_gapfill_aggregates_and_carries_forward() {
+        let rows = vec![row(60, 10, 12, 9, 11), row(120, 11, 15, 8, 14)];
+        let buckets = gapfill_candles(&rows, Some(Decimal::from(7)), at(0), at(600), 300);
+
+        assert_eq!(buckets.len(), 2);
+        assert_eq!(buckets[0].bucket_start, at(0));
+        assert_eq!(buckets[0].open, Some(Decimal::from(10)));
+        assert_eq!(buckets[0].high, Some(Decimal::from(15)));
+        assert_eq!(buckets[0].low, Some(Decimal::from(8)));
+        assert_eq!(buckets[0].close, Some(Decimal::from(14)));
+        assert_eq!(buckets[1].open, None);
+        assert_eq!(buckets[1].carried_close, Some(Decimal::from(14)));
+    }
+
+    #[test]
+    fn test_gapfill_seeds_leading_gap_and_aligns_to_epoch() {
+        let rows = vec![row(180, 5, 5, 5, 6)];
+        let buckets = gapfill_candles(&rows, Some(Decimal::from(4)), at(90), at(240), 60);
+
+        let starts: Vec<_> = buckets.iter().map(|bucket| bucket.bucket_start).collect();
+        assert_eq!(starts, vec![at(60), at(120), at(180)]);
+        assert_eq!(buckets[0].close, None);
+        assert_eq!(buckets[0].carried_close, Some(Decimal::from(4)));
+        assert_eq!(buckets[2].close, Some(Decimal::from(6)));
+    }
+
+    #[test]
+    fn test_gapfill_without_seed_leaves_leading_buckets_empty() {
+        let buckets = gapfill_candles(&[], None, at(0), at(120), 60);
+
+        assert_eq!(buckets.len(), 2);
+        assert!(buckets.iter().all(|bucket| bucket.carried_close.is_none()));
+    }
+}
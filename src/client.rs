@@ -0,0 +1,360 @@
+//! Typed async client for `read-api`.
+//!
+//! Covers the JSON endpoints described by [`crate::api`]; streams, WebSocket
+//! and file exports are not wrapped, and any `format` in a query is dropped.
+//! Wallet addresses are percent-encoded into the path.
+//! Non-2xx responses become errors carrying the status and the API's `error`
+//! message.
+
+use anyhow::{Context, Result, anyhow};
+use chrono::{DateTime, Utc};
+use reqwest::{Client, Response, StatusCode};
+use serde::{Serialize, de::DeserializeOwned};
+
+use crate::api::{
+    AggregatorLiquidityItem, AggregatorLiquidityQuery, AggregatorPairItem, AggregatorTickerItem,
+    AuthorityExecutionQuery, AuthorityExecutionResponse, AuthorityLiquidityQuery,
+    AuthorityOpenPositionsQuery, CandleQuery, CandleResponse, ClosedPositionMiniChartQuery,
+    ClosedPositionMiniChartResponse, ClosedPositionsQuery, ClosedPositionsResponse, ErrorResponse,
+    FeesQuery, FeesResponse, FlowScheduleResponse, HealthResponse, HistoricalTradesQuery,
+    HistoricalTradesResponse, LatestPriceResponse, LiquidityPositionsResponse, MarketConfig,
+    MarketConfigListResponse, MarketExecutionQuery, MarketExecutionResponse, MarketHistoryQuery,
+    MarketHistoryResponse, MarketLiquidityQuery, MarketOpenPositionsQuery, MarketStateQuery,
+    MarketStateResponse, MarketUpdatesQuery, MarketUpdatesResponse, OpenPositionsResponse,
+    ReadinessResponse, SlotTimeQuery, SlotTimeResponse, StorageStatusResponse, SummaryQuery,
+    SummaryResponse, TickerItem, TickerListResponse, TimeSlotQuery, UsageResponse,
+};
+
+#[derive(Clone, Debug)]
+pub struct ReadApiClient {
+    http: Client,
+    base_url: String,
+}
+
+impl ReadApiClient {
+    /// `base_url` is the server root, e.g. `http://read-api:8080`.
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self::with_client(Client::new(), base_url)
+    }
+
+    /// Use a preconfigured `reqwest` client (timeouts, proxies, headers).
+    pub fn with_client(http: Client, base_url: impl Into<String>) -> Self {
+        let base_url = base_url.into().trim_end_matches('/').to_string();
+        Self { http, base_url }
+    }
+
+    pub async fn health(&self) -> Result<HealthResponse> {
+        self.get("/healthz", &()).await
+    }
+
+    /// The readiness report, whether or not the instance is ready; check
+    /// `status`.
+    pub async fn readiness(&self) -> Result<ReadinessResponse> {
+        let url = format!("{}/readyz", self.base_url);
+        let response = self
+            .http
+            .get(&url)
+            .send()
+            .await
+            .with_context(|| format!("GET {url} failed"))?;
+        if response.status() == StatusCode::SERVICE_UNAVAILABLE {
+            return response
+                .json()
+                .await
+                .with_context(|| format!("GET {url}: failed to decode readiness report"));
+        }
+        decode(response)
+            .await
+            .with_context(|| format!("GET {url} failed"))
+    }
+
+    pub async fn storage_status(&self) -> Result<StorageStatusResponse> {
+        self.get("/v1/status/storage", &()).await
+    }
+
+    /// Estimated time of `slot`.
+    pub async fn slot_time(&self, slot: u64) -> Result<SlotTimeResponse> {
+        self.get("/v1/time/slot", &SlotTimeQuery { slot }).await
+    }
+
+    /// Estimated slot at `ts`.
+    pub async fn time_slot(&self, ts: DateTime<Utc>) -> Result<SlotTimeResponse> {
+        self.get("/v1/time/at", &TimeSlotQuery { ts }).await
+    }
+
+    pub async fn markets(&self) -> Result<MarketConfigListResponse> {
+        self.get("/v1/markets", &()).await
+    }
+
+    pub async fn market_config(&self, market_id: u64) -> Result<MarketConfig> {
+        self.get(&format!("/v1/markets/{market_id}/config"), &())
+            .await
+    }
+
+    pub async fn latest_price(&self, market_id: u64) -> Result<LatestPriceResponse> {
+        self.get(&format!("/v1/markets/{market_id}/price"), &())
+            .await
+    }
+
+    pub async fn candles(&self, market_id: u64, query: &CandleQuery) -> Result<CandleResponse> {
+        let json_only = CandleQuery {
+            format: None,
+            ..query.clone()
+        };
+        self.get(&format!("/v1/markets/{market_id}/candles"), &json_only)
+            .await
+    }
+
+    /// One page of history; follow `next_cursor` for the rest of the range.
+    pub async fn market_history(
+        &self,
+        market_id: u64,
+        query: &MarketHistoryQuery,
+    ) -> Result<MarketHistoryResponse> {
+        let json_only = MarketHistoryQuery {
+            format: None,
+            ..query.clone()
+        };
+        self.get(&format!("/v1/markets/{market_id}/history"), &json_only)
+            .await
+    }
+
+    pub async fn market_updates(
+        &self,
+        market_id: u64,
+        query: &MarketUpdatesQuery,
+    ) -> Result<MarketUpdatesResponse> {
+        let json_only = MarketUpdatesQuery {
+            format: None,
+            ..query.clone()
+        };
+        self.get(&format!("/v1/markets/{market_id}/updates"), &json_only)
+            .await
+    }
+
+    pub async fn market_state(
+        &self,
+        market_id: u64,
+        query: &MarketStateQuery,
+    ) -> Result<MarketStateResponse> {
+        self.get(&format!("/v1/markets/{market_id}/state"), query)
+            .await
+    }
+
+    pub async fn closed_position_mini_chart(
+        &self,
+        market_id: u64,
+        query: &ClosedPositionMiniChartQuery,
+    ) -> Result<ClosedPositionMiniChartResponse> {
+        self.get(
+            &format!("/v1/markets/{market_id}/closed-position-mini-chart"),
+            query,
+        )
+        .await
+    }
+
+    pub async fn closed_positions(
+        &self,
+        authority: &str,
+        query: &ClosedPositionsQuery,
+    ) -> Result<ClosedPositionsResponse> {
+        let json_only = ClosedPositionsQuery {
+            format: None,
+            ..query.clone()
+        };
+        self.get(&authority_path(authority, "closed-positions"), &json_only)
+            .await
+    }
+
+    pub async fn open_positions(
+        &self,
+        authority: &str,
+        query: &AuthorityOpenPositionsQuery,
+    ) -> Result<OpenPositionsResponse> {
+        self.get(&authority_path(authority, "open-positions"), query)
+            .await
+    }
+
+    pub async fn liquidity_positions(
+        &self,
+        authority: &str,
+        query: &AuthorityLiquidityQuery,
+    ) -> Result<LiquidityPositionsResponse> {
+        self.get(&authority_path(authority, "liquidity-positions"), query)
+            .await
+    }
+
+    pub async fn authority_summary(
+        &self,
+        authority: &str,
+        query: &SummaryQuery,
+    ) -> Result<SummaryResponse> {
+        self.get(&authority_path(authority, "summary"), query).await
+    }
+
+    pub async fn authority_execution(
+        &self,
+        authority: &str,
+        query: &AuthorityExecutionQuery,
+    ) -> Result<AuthorityExecutionResponse> {
+        self.get(&authority_path(authority, "execution"), query)
+            .await
+    }
+
+    pub async fn market_open_positions(
+        &self,
+        market_id: u64,
+        query: &MarketOpenPositionsQuery,
+    ) -> Result<OpenPositionsResponse> {
+        self.get(&format!("/v1/markets/{market_id}/open-positions"), query)
+            .await
+    }
+
+    pub async fn market_liquidity_positions(
+        &self,
+        market_id: u64,
+        query: &MarketLiquidityQuery,
+    ) -> Result<LiquidityPositionsResponse> {
+        self.get(
+            &format!("/v1/markets/{market_id}/liquidity-positions"),
+            query,
+        )
+        .await
+    }
+
+    pub async fn market_execution(
+        &self,
+        market_id: u64,
+        query: &MarketExecutionQuery,
+    ) -> Result<MarketExecutionResponse> {
+        self.get(&format!("/v1/markets/{market_id}/execution-quality"), query)
+            .await
+    }
+
+    pub async fn flow_schedule(&self, market_id: u64) -> Result<FlowScheduleResponse> {
+        self.get(&format!("/v1/markets/{market_id}/flow-schedule"), &())
+            .await
+    }
+
+    pub async fn market_ticker(&self, market_id: u64) -> Result<TickerItem> {
+        self.get(&format!("/v1/markets/{market_id}/ticker"), &())
+            .await
+    }
+
+    pub async fn tickers(&self) -> Result<TickerListResponse> {
+        self.get("/v1/tickers", &()).await
+    }
+
+    pub async fn fees(&self, query: &FeesQuery) -> Result<FeesResponse> {
+        self.get("/v1/fees", query).await
+    }
+
+    /// Counters of the API key the client sends; configure it on the
+    /// `reqwest` client passed to [`Self::with_client`].
+    pub async fn usage(&self) -> Result<UsageResponse> {
+        self.get("/v1/usage", &()).await
+    }
+
+    pub async fn aggregator_pairs(&self) -> Result<Vec<AggregatorPairItem>> {
+        self.get("/v1/aggregator/pairs", &()).await
+    }
+
+    pub async fn aggregator_tickers(&self) -> Result<Vec<AggregatorTickerItem>> {
+        self.get("/v1/aggregator/tickers", &()).await
+    }
+
+    pub async fn aggregator_historical_trades(
+        &self,
+        query: &HistoricalTradesQuery,
+    ) -> Result<HistoricalTradesResponse> {
+        self.get("/v1/aggregator/historical_trades", query).await
+    }
+
+    pub async fn aggregator_liquidity(
+        &self,
+        query: &AggregatorLiquidityQuery,
+    ) -> Result<Vec<AggregatorLiquidityItem>> {
+        self.get("/v1/aggregator/liquidity", query).await
+    }
+
+    async fn get<Q, T>(&self, path: &str, query: &Q) -> Result<T>
+    where
+        Q: Serialize + ?Sized,
+        T: DeserializeOwned,
+    {
+        let url = format!("{}{path}", self.base_url);
+        let response = self
+            .http
+            .get(&url)
+            .query(query)
+            .send()
+            .await
+            .with_context(|| format!("GET {url} failed"))?;
+        decode(response)
+            .await
+            .with_context(|| format!("GET {url} failed"))
+    }
+}
+
+/// `/v1/authorities/{authority}/{endpoint}` with `authority` percent-encoded,
+/// so an address containing `/`, `?` or `#` cannot change the route.
+fn authority_path(authority: &str, endpoint: &str) -> String {
+    let mut segment = String::with_capacity(authority.len());
+    for byte in authority.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_' | b'~') {
+            segment.push(char::from(byte));
+        } else {
+            segment.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    format!("/v1/authorities/{segment}/{endpoint}")
+}
+
+async fn decode<T: DeserializeOwned>(response: Response) -> Result<T> {
+    let status = response.status();
+    if status.is_success() {
+        return response.json().await.context("Failed to decode response");
+    }
+    let body = response.text().await.unwrap_or_default();
+    Err(anyhow!("{status}: {}", error_message(body)))
+}
+
+/// The `error` field of an [`ErrorResponse`], or the raw body when a proxy or
+/// the framework answered instead of read-api.
+fn error_message(body: String) -> String {
+    serde_json::from_str::<ErrorResponse>(&body)
+        .map(|error| error.error)
+        .unwrap_or(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_message_prefers_api_error() {
+        assert_eq!(
+            error_message(r#"{"error":"No config for market_id=9"}"#.to_string()),
+            "No config for market_id=9"
+        );
+        assert_eq!(error_message("Bad Gateway".to_string()), "Bad Gateway");
+    }
+
+    #[test]
+    fn test_authority_path_encodes_segment() {
+        assert_eq!(
+            authority_path("4Nd1mYw5", "summary"),
+            "/v1/authorities/4Nd1mYw5/summary"
+        );
+        assert_eq!(
+            authority_path("a/b?c#d é", "open-positions"),
+            "/v1/authorities/a%2Fb%3Fc%23d%20%C3%A9/open-positions"
+        );
+    }
+
+    #[test]
+    fn test_base_url_trailing_slash() {
+        let client = ReadApiClient::new("http://read-api:8080/");
+        assert_eq!(client.base_url, "http://read-api:8080");
+    }
+}
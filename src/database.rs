@@ -1,17 +1,31 @@
-use anyhow::{Context, Result};
-use chrono::Utc;
+use anyhow::{Context, Result, anyhow};
+use chrono::{DateTime, Utc};
 use deadpool_postgres::{Manager, ManagerConfig, Pool, RecyclingMethod};
+use futures_util::{StreamExt, stream};
 use native_tls::TlsConnector;
 use postgres_native_tls::MakeTlsConnector;
 use std::sync::{
     Arc, Mutex,
     atomic::{AtomicU64, Ordering},
 };
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    sync::mpsc,
+};
+use tokio_postgres::{AsyncMessage, Client, Connection, NoTls, Notification, config::SslMode};
 
 use crate::sink::{
     ClosePositionEventRecord, EventSink, MarketUpdateEventRecord, SinkFuture, SinkMetricsSnapshot,
 };
 
+/// Postgres `NOTIFY` channel for new market-update events (payload: market id).
+pub const MARKET_UPDATES_CHANNEL: &str = "twob_market_updates";
+/// Postgres `NOTIFY` channel for new close-position events (payload: market id).
+pub const CLOSE_POSITIONS_CHANNEL: &str = "twob_close_positions";
+
+const TIMESCALE_SCHEMA_SQL: &str = include_str!("../docs/timescale-schema.sql");
+const POSTGRES_SCHEMA_SQL: &str = include_str!("../docs/postgres-schema.sql");
+
 /// Insert the raw market-update event and, in the same statement, recompute the
 /// affected 1-minute candle.
 ///
@@ -28,6 +42,10 @@ use crate::sink::{
 /// If the raw insert hits `ON CONFLICT DO NOTHING` (duplicate) or the market has
 /// no `market_configs` row, the candle CTE simply produces no row and the candle
 /// is left untouched.
+///
+/// Every newly inserted event also sends `NOTIFY` on [`MARKET_UPDATES_CHANNEL`]
+/// with the market id as payload, delivered on commit once the candle is
+/// written. read-api listens on it to push prices without polling.
 const INSERT_MARKET_UPDATE_SQL: &str = "\
 WITH ev AS ( \
     INSERT INTO raw_market_update_events \
@@ -47,7 +65,8 @@ p AS ( \
     WHERE ev.base_flow <> 0 \
       AND mc.base_decimals IS NOT NULL \
       AND mc.quote_decimals IS NOT NULL \
-) \
+), \
+candle AS ( \
 INSERT INTO market_candles_1m (market_id, bucket_start, open, high, low, close, updated_at) \
 SELECT \
     p.market_id, \
@@ -63,39 +82,811 @@ ON CONFLICT (market_id, bucket_start) DO UPDATE SET \
     high  = GREATEST(market_candles_1m.high, EXCLUDED.close), \
     low   = LEAST(market_candles_1m.low,  EXCLUDED.close), \
     close = EXCLUDED.close, \
-    updated_at = now()";
+    updated_at = now() \
+RETURNING 1 \
+) \
+SELECT pg_notify('twob_market_updates', ev.market_id::text) FROM ev";
 
-const INSERT_CLOSE_POSITION_SQL: &str = "\
-INSERT INTO raw_close_position_events \
-    (event_uid, signature, event_index, slot, position_authority, market_id, start_slot, \
-     end_slot, deposit_amount, swapped_amount, remaining_amount, fee_amount, is_buy, event_time) \
-VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14) \
+/// Runs of minutes that have priced raw events but no candle, one row per run.
+///
+/// Candles are only written while a `market_configs` row with decimals exists,
+/// so events that arrive before the config, or while the keeper could not
+/// write candles, leave holes anywhere in the series. Missing minutes that
+/// share the same preceding candle form one run. Only events after the window
+/// start (`$1` candle retention, `$2` lookback, the later one wins) are
+/// considered, so candles that retention dropped on purpose are not rebuilt.
+/// `$3` narrows that to events since a previous pass and `$4` to some markets;
+/// both are optional.
+const MISSING_CANDLE_RANGES_SQL: &str = "\
+WITH missing AS ( \
+    SELECT DISTINCT r.market_id, date_trunc('minute', r.event_time) AS bucket_start \
+    FROM raw_market_update_events r \
+    JOIN market_configs mc ON mc.market_id = r.market_id \
+    WHERE r.base_flow <> 0 \
+      AND mc.base_decimals IS NOT NULL \
+      AND mc.quote_decimals IS NOT NULL \
+      AND r.event_time >= COALESCE( \
+          GREATEST(now() - $1::text::interval, now() - $2::text::interval), \
+          '-infinity') \
+      AND r.event_time >= COALESCE($3::timestamptz, '-infinity') \
+      AND ($4::bigint[] IS NULL OR r.market_id = ANY($4)) \
+      AND NOT EXISTS ( \
+          SELECT 1 FROM market_candles_1m c \
+          WHERE c.market_id = r.market_id \
+            AND c.bucket_start = date_trunc('minute', r.event_time)) \
+) \
+SELECT m.market_id, min(m.bucket_start) AS gap_start, max(m.bucket_start) AS gap_end \
+FROM missing m \
+LEFT JOIN LATERAL ( \
+    SELECT max(c.bucket_start) AS previous_bucket \
+    FROM market_candles_1m c \
+    WHERE c.market_id = m.market_id AND c.bucket_start < m.bucket_start \
+) p ON TRUE \
+GROUP BY m.market_id, p.previous_bucket \
+ORDER BY m.market_id, gap_start";
+
+/// Rebuild a market's 1-minute candles from raw events for the run of missing
+/// minutes `[$2, $3]`, plus the first existing candle after it.
+///
+/// Produces the same candles the keeper would have written: `open` carries the
+/// previous bucket's close (the candle before the run, or the bucket's first
+/// price), `high`/`low` span every price, and `close` is the latest event by
+/// `(event_time, slot, event_index)`. Buckets in the run are plain inserts; the
+/// next existing candle only gets its `open` re-seeded and `high`/`low`
+/// widened, because its `close` is still owned by the live keeper.
+const BACKFILL_CANDLE_RANGE_SQL: &str = "\
+WITH bounds AS ( \
+    SELECT \
+        (SELECT close FROM market_candles_1m \
+         WHERE market_id = $1 AND bucket_start < $2 \
+         ORDER BY bucket_start DESC \
+         LIMIT 1) AS previous_close, \
+        (SELECT min(bucket_start) FROM market_candles_1m \
+         WHERE market_id = $1 AND bucket_start > $3) AS next_bucket \
+), \
+prices AS ( \
+    SELECT \
+        date_trunc('minute', r.event_time) AS bucket_start, \
+        r.event_time, r.slot, r.event_index, \
+        (r.quote_flow::numeric * power(10::numeric, mc.base_decimals::numeric)) \
+            / (r.base_flow::numeric * power(10::numeric, mc.quote_decimals::numeric)) AS price \
+    FROM raw_market_update_events r \
+    JOIN market_configs mc ON mc.market_id = r.market_id \
+    CROSS JOIN bounds \
+    WHERE r.market_id = $1 \
+      AND r.base_flow <> 0 \
+      AND mc.base_decimals IS NOT NULL \
+      AND mc.quote_decimals IS NOT NULL \
+      AND r.event_time >= $2 \
+      AND r.event_time < COALESCE(bounds.next_bucket, $3) + INTERVAL '1 minute' \
+), \
+buckets AS ( \
+    SELECT \
+        bucket_start, \
+        max(price) AS high, \
+        min(price) AS low, \
+        (array_agg(price ORDER BY event_time, slot, event_index))[1] AS first_price, \
+        (array_agg(price ORDER BY event_time DESC, slot DESC, event_index DESC))[1] AS close \
+    FROM prices \
+    GROUP BY bucket_start \
+), \
+candles AS ( \
+    SELECT \
+        bucket_start, \
+        COALESCE( \
+            lag(close) OVER (ORDER BY bucket_start), \
+            (SELECT previous_close FROM bounds), \
+            first_price) AS open, \
+        high, low, close \
+    FROM buckets \
+), \
+written AS ( \
+    INSERT INTO market_candles_1m (market_id, bucket_start, open, high, low, close, updated_at) \
+    SELECT $1, bucket_start, open, high, low, close, now() FROM candles \
+    ON CONFLICT (market_id, bucket_start) DO UPDATE SET \
+        open  = EXCLUDED.open, \
+        high  = GREATEST(market_candles_1m.high, EXCLUDED.high), \
+        low   = LEAST(market_candles_1m.low, EXCLUDED.low), \
+        updated_at = now() \
+    RETURNING 1 \
+) \
+SELECT count(*) FROM written";
+
+/// `u128` values exceed `numeric`'s Rust mapping, so they are bound as text.
+const INSERT_MARKET_STATE_SNAPSHOT_SQL: &str = "\
+INSERT INTO market_state_snapshots \
+    (market_id, snapshot_time, slot, is_paused, fee_bps, unhealthy_liquidity_fee_bps, \
+     open_positions, accumulated_base_fees, accumulated_quote_fees, base_flow, quote_flow, \
+     end_slot_interval, base_per_quote, quote_per_base, slots_without_trade, \
+     bookkeeping_last_update_slot) \
+VALUES ($1, $2, $3, $4, $5, $6, $7, $8::text::numeric, $9::text::numeric, \
+        $10::text::numeric, $11::text::numeric, $12, $13::text::numeric, \
+        $14::text::numeric, $15, $16) \
 ON CONFLICT DO NOTHING";
 
-/// Build a TLS-enabled connection pool for Tiger Cloud (Timescale).
+/// A read never replaces a row observed at a later slot, so a slow
+/// `getProgramAccounts` scan cannot roll back a subscription update.
+const UPSERT_TRADE_POSITION_SQL: &str = "\
+INSERT INTO trade_positions \
+    (address, market_id, authority, position_id, is_buy, amount, start_slot, end_slot, \
+     observed_slot) \
+VALUES ($1, $2, $3, $4::text::numeric, $5, $6::text::numeric, $7, $8, $9) \
+ON CONFLICT (address) DO UPDATE SET \
+    market_id     = EXCLUDED.market_id, \
+    authority     = EXCLUDED.authority, \
+    position_id   = EXCLUDED.position_id, \
+    is_buy        = EXCLUDED.is_buy, \
+    amount        = EXCLUDED.amount, \
+    start_slot    = EXCLUDED.start_slot, \
+    end_slot      = EXCLUDED.end_slot, \
+    observed_slot = EXCLUDED.observed_slot, \
+    updated_at    = now() \
+WHERE trade_positions.observed_slot <= EXCLUDED.observed_slot";
+
+/// Rows a full scan at slot `$2` did not return were closed before that slot,
+/// unless a subscription update has seen them since.
+const PRUNE_TRADE_POSITIONS_SQL: &str = "\
+DELETE FROM trade_positions WHERE observed_slot < $2 AND NOT (address = ANY($1))";
+
+/// Same slot guard as [`UPSERT_TRADE_POSITION_SQL`]; `u128` balances and
+/// snapshots are bound as text.
+const UPSERT_LIQUIDITY_POSITION_SQL: &str = "\
+INSERT INTO liquidity_positions \
+    (address, market_id, authority, base_balance, quote_balance, base_per_quote_snapshot, \
+     quote_per_base_snapshot, slots_without_trade_snapshot, base_flow, quote_flow, base_debt, \
+     quote_debt, last_update_slot, observed_slot) \
+VALUES ($1, $2, $3, $4::text::numeric, $5::text::numeric, $6::text::numeric, \
+        $7::text::numeric, $8, $9::text::numeric, $10::text::numeric, $11::text::numeric, \
+        $12::text::numeric, $13, $14) \
+ON CONFLICT (address) DO UPDATE SET \
+    market_id                    = EXCLUDED.market_id, \
+    authority                    = EXCLUDED.authority, \
+    base_balance                 = EXCLUDED.base_balance, \
+    quote_balance                = EXCLUDED.quote_balance, \
+    base_per_quote_snapshot      = EXCLUDED.base_per_quote_snapshot, \
+    quote_per_base_snapshot      = EXCLUDED.quote_per_base_snapshot, \
+    slots_without_trade_snapshot = EXCLUDED.slots_without_trade_snapshot, \
+    base_flow                    = EXCLUDED.base_flow, \
+    quote_flow                   = EXCLUDED.quote_flow, \
+    base_debt                    = EXCLUDED.base_debt, \
+    quote_debt                   = EXCLUDED.quote_debt, \
+    last_update_slot             = EXCLUDED.last_update_slot, \
+    observed_slot                = EXCLUDED.observed_slot, \
+    updated_at                   = now() \
+WHERE liquidity_positions.observed_slot <= EXCLUDED.observed_slot";
+
+const PRUNE_LIQUIDITY_POSITIONS_SQL: &str = "\
+DELETE FROM liquidity_positions WHERE observed_slot < $2 AND NOT (address = ANY($1))";
+
+const DELETE_MARKET_EXITS_SQL: &str = "DELETE FROM market_exits WHERE market_id = $1";
+
+/// `u128` flows are bound as text.
+const INSERT_MARKET_EXIT_SQL: &str = "\
+INSERT INTO market_exits \
+    (market_id, exit_slot, reference_index, base_exit, quote_exit, observed_slot) \
+VALUES ($1, $2, $3, $4::text::numeric, $5::text::numeric, $6)";
+
+/// Insert the raw close-position event and, in the same statement, add its fee
+/// to the market's `market_fees_1d` row for the event's UTC day.
 ///
-/// Tiger Cloud requires TLS, so connections go through a native-TLS connector.
-/// Use `?sslmode=require` in the connection string.
+/// Newly inserted events `NOTIFY` on [`CLOSE_POSITIONS_CHANNEL`] with the market
+/// id as payload; duplicates notify nothing and leave the fee rollup untouched.
+const INSERT_CLOSE_POSITION_SQL: &str = "\
+WITH ev AS ( \
+    INSERT INTO raw_close_position_events \
+        (event_uid, signature, event_index, slot, position_authority, market_id, start_slot, \
+         end_slot, deposit_amount, swapped_amount, remaining_amount, fee_amount, is_buy, \
+         event_time) \
+    VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14) \
+    ON CONFLICT DO NOTHING \
+    RETURNING market_id, fee_amount, is_buy, event_time \
+), \
+fees AS ( \
+    INSERT INTO market_fees_1d \
+        (market_id, bucket_start, base_fees, quote_fees, positions, updated_at) \
+    SELECT \
+        ev.market_id, \
+        date_trunc('day', ev.event_time AT TIME ZONE 'UTC') AT TIME ZONE 'UTC', \
+        CASE WHEN ev.is_buy THEN ev.fee_amount ELSE 0 END, \
+        CASE WHEN ev.is_buy THEN 0 ELSE ev.fee_amount END, \
+        1, now() \
+    FROM ev \
+    ON CONFLICT (market_id, bucket_start) DO UPDATE SET \
+        base_fees  = market_fees_1d.base_fees + EXCLUDED.base_fees, \
+        quote_fees = market_fees_1d.quote_fees + EXCLUDED.quote_fees, \
+        positions  = market_fees_1d.positions + EXCLUDED.positions, \
+        updated_at = now() \
+    RETURNING 1 \
+) \
+SELECT pg_notify('twob_close_positions', ev.market_id::text) FROM ev";
+
+/// Recompute `market_fees_1d` from the raw close-position events.
+///
+/// The live rollup only ever lags the raw table (events written before it
+/// existed), so a day is rewritten when the raw events count more positions
+/// than its row. Days whose raw events were already dropped by retention keep
+/// their rollup.
+const BACKFILL_FEE_ROLLUP_SQL: &str = "\
+WITH days AS ( \
+    SELECT \
+        market_id, \
+        date_trunc('day', event_time AT TIME ZONE 'UTC') AT TIME ZONE 'UTC' AS bucket_start, \
+        COALESCE(sum(fee_amount) FILTER (WHERE is_buy), 0) AS base_fees, \
+        COALESCE(sum(fee_amount) FILTER (WHERE NOT is_buy), 0) AS quote_fees, \
+        count(*) AS positions \
+    FROM raw_close_position_events \
+    GROUP BY 1, 2 \
+), \
+written AS ( \
+    INSERT INTO market_fees_1d \
+        (market_id, bucket_start, base_fees, quote_fees, positions, updated_at) \
+    SELECT market_id, bucket_start, base_fees, quote_fees, positions, now() FROM days \
+    ON CONFLICT (market_id, bucket_start) DO UPDATE SET \
+        base_fees  = EXCLUDED.base_fees, \
+        quote_fees = EXCLUDED.quote_fees, \
+        positions  = EXCLUDED.positions, \
+        updated_at = now() \
+    WHERE market_fees_1d.positions < EXCLUDED.positions \
+    RETURNING 1 \
+) \
+SELECT count(*) FROM written";
+
+/// Upsert a market config discovered on-chain.
+///
+/// Mints and decimals always follow the chain. Tickers are only filled when the
+/// row has none, so hand-curated tickers are never overwritten by token
+/// metadata. Unchanged rows are not rewritten.
+const UPSERT_MARKET_CONFIG_SQL: &str = "\
+INSERT INTO market_configs \
+    (market_id, base_mint, quote_mint, base_decimals, quote_decimals, base_ticker, quote_ticker) \
+VALUES ($1, $2, $3, $4, $5, $6, $7) \
+ON CONFLICT (market_id) DO UPDATE SET \
+    base_mint      = EXCLUDED.base_mint, \
+    quote_mint     = EXCLUDED.quote_mint, \
+    base_decimals  = EXCLUDED.base_decimals, \
+    quote_decimals = EXCLUDED.quote_decimals, \
+    base_ticker    = COALESCE(market_configs.base_ticker, EXCLUDED.base_ticker), \
+    quote_ticker   = COALESCE(market_configs.quote_ticker, EXCLUDED.quote_ticker) \
+WHERE market_configs.base_mint IS DISTINCT FROM EXCLUDED.base_mint \
+   OR market_configs.quote_mint IS DISTINCT FROM EXCLUDED.quote_mint \
+   OR market_configs.base_decimals IS DISTINCT FROM EXCLUDED.base_decimals \
+   OR market_configs.quote_decimals IS DISTINCT FROM EXCLUDED.quote_decimals \
+   OR (market_configs.base_ticker IS NULL AND EXCLUDED.base_ticker IS NOT NULL) \
+   OR (market_configs.quote_ticker IS NULL AND EXCLUDED.quote_ticker IS NOT NULL)";
+
+/// Which flavour of Postgres the tables live in, from `STORAGE_BACKEND`.
+///
+/// `timescale` (the default) uses hypertables, `time_bucket_gapfill` and the
+/// storage policies. `postgres` runs on a stock Postgres with plain tables;
+/// candle gap-filling then happens in Rust (see [`crate::candles`]).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StorageBackend {
+    Timescale,
+    Postgres,
+}
+
+impl StorageBackend {
+    pub fn from_env() -> Result<Self> {
+        match std::env::var("STORAGE_BACKEND") {
+            Ok(value) if !value.trim().is_empty() => Self::parse(&value),
+            _ => Ok(Self::Timescale),
+        }
+    }
+
+    pub fn parse(value: &str) -> Result<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "timescale" | "timescaledb" => Ok(Self::Timescale),
+            "postgres" | "postgresql" => Ok(Self::Postgres),
+            other => Err(anyhow!(
+                "Unsupported STORAGE_BACKEND '{other}'. Use 'timescale' or 'postgres'"
+            )),
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Timescale => "timescale",
+            Self::Postgres => "postgres",
+        }
+    }
+
+    /// DDL for this backend (`docs/timescale-schema.sql` or
+    /// `docs/postgres-schema.sql`).
+    pub fn schema_sql(self) -> &'static str {
+        match self {
+            Self::Timescale => TIMESCALE_SCHEMA_SQL,
+            Self::Postgres => POSTGRES_SCHEMA_SQL,
+        }
+    }
+}
+
+/// Build a pool for `database_url`. TLS is negotiated unless the URL sets
+/// `sslmode=disable`, which is what a local development database usually needs.
 pub fn connect_pool(database_url: &str, max_size: usize) -> Result<Pool> {
     let config: tokio_postgres::Config = database_url
         .parse()
         .context("Failed to parse database URL")?;
 
-    let tls_connector = TlsConnector::builder()
-        .build()
-        .context("Failed to build native TLS connector")?;
-    let tls = MakeTlsConnector::new(tls_connector);
-
     let manager_config = ManagerConfig {
         recycling_method: RecyclingMethod::Fast,
     };
-    let manager = Manager::from_config(config, tls, manager_config);
+    let manager = if config.get_ssl_mode() == SslMode::Disable {
+        Manager::from_config(config, NoTls, manager_config)
+    } else {
+        let tls_connector = TlsConnector::builder()
+            .build()
+            .context("Failed to build native TLS connector")?;
+        let tls = MakeTlsConnector::new(tls_connector);
+        Manager::from_config(config, tls, manager_config)
+    };
     Pool::builder(manager)
         .max_size(max_size)
         .build()
         .context("Failed to create connection pool")
 }
 
+/// Open a dedicated connection that `LISTEN`s on every channel in `channels`.
+///
+/// Notifications arrive on the returned receiver, which closes when the
+/// connection drops. Keep the returned client alive for as long as the
+/// listener is needed; dropping it closes the connection.
+pub async fn connect_listener(
+    database_url: &str,
+    channels: &[&str],
+) -> Result<(Client, mpsc::UnboundedReceiver<Notification>)> {
+    if let Some(channel) = channels.iter().find(|channel| !is_safe_identifier(channel)) {
+        return Err(anyhow!("Unsafe LISTEN channel: {channel}"));
+    }
+
+    let config: tokio_postgres::Config = database_url
+        .parse()
+        .context("Failed to parse database URL")?;
+    let (sender, receiver) = mpsc::unbounded_channel();
+
+    let client = if config.get_ssl_mode() == SslMode::Disable {
+        let (client, connection) = config
+            .connect(NoTls)
+            .await
+            .context("Failed to open LISTEN connection")?;
+        drive_listener_connection(connection, sender);
+        client
+    } else {
+        let tls_connector = TlsConnector::builder()
+            .build()
+            .context("Failed to build native TLS connector")?;
+        let (client, connection) = config
+            .connect(MakeTlsConnector::new(tls_connector))
+            .await
+            .context("Failed to open LISTEN connection")?;
+        drive_listener_connection(connection, sender);
+        client
+    };
+
+    for channel in channels {
+        client
+            .batch_execute(&format!("LISTEN {channel}"))
+            .await
+            .with_context(|| format!("Failed to LISTEN on {channel}"))?;
+    }
+    Ok((client, receiver))
+}
+
+fn drive_listener_connection<S, T>(
+    mut connection: Connection<S, T>,
+    sender: mpsc::UnboundedSender<Notification>,
+) where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    tokio::spawn(async move {
+        let mut messages = stream::poll_fn(move |cx| connection.poll_message(cx));
+        while let Some(message) = messages.next().await {
+            match message {
+                Ok(AsyncMessage::Notification(notification)) => {
+                    if sender.send(notification).is_err() {
+                        break;
+                    }
+                }
+                Ok(_) => {}
+                Err(error) => {
+                    eprintln!("LISTEN connection error: {error}");
+                    break;
+                }
+            }
+        }
+    });
+}
+
+/// Validate a table identifier read from configuration before it is
+/// interpolated into SQL.
+pub fn validate_table_name(table: &str) -> Result<String> {
+    let trimmed = table.trim();
+    if trimmed.is_empty() {
+        return Err(anyhow!("Table name must not be empty"));
+    }
+    if !is_safe_identifier(trimmed) {
+        return Err(anyhow!("Unsafe table identifier: {trimmed}"));
+    }
+    Ok(trimmed.to_string())
+}
+
+fn is_safe_identifier(value: &str) -> bool {
+    value
+        .chars()
+        .all(|character| character.is_ascii_alphanumeric() || character == '_' || character == '.')
+}
+
+/// Market token configuration read from the TwoB `Market` account and its mints.
+#[derive(Clone, Debug)]
+pub struct MarketConfigRecord {
+    pub market_id: u64,
+    pub base_mint: String,
+    pub quote_mint: String,
+    pub base_decimals: u8,
+    pub quote_decimals: u8,
+    pub base_ticker: Option<String>,
+    pub quote_ticker: Option<String>,
+}
+
+/// Insert or refresh a `market_configs` row. Returns `true` if the row changed.
+pub async fn upsert_market_config(pool: &Pool, record: &MarketConfigRecord) -> Result<bool> {
+    let market_id = i64::try_from(record.market_id).context("market_id out of range")?;
+    let client = pool.get().await.context("Failed to get connection")?;
+    let changed = client
+        .execute(
+            UPSERT_MARKET_CONFIG_SQL,
+            &[
+                &market_id,
+                &record.base_mint,
+                &record.quote_mint,
+                &i16::from(record.base_decimals),
+                &i16::from(record.quote_decimals),
+                &record.base_ticker,
+                &record.quote_ticker,
+            ],
+        )
+        .await
+        .context("Failed to upsert market config")?;
+    Ok(changed > 0)
+}
+
+/// How far back [`backfill_missing_candles`] looks for holes. Both bounds are
+/// Postgres intervals before now; the later one wins, and with neither set the
+/// whole event history is checked.
+#[derive(Clone, Debug, Default)]
+pub struct CandleBackfillWindow {
+    /// `CANDLES_1M_RETENTION`: candles older than this are dropped on purpose.
+    pub candle_retention: Option<String>,
+    /// Keeps periodic runs to recent, uncompressed chunks.
+    pub lookback: Option<String>,
+    /// Only events at or after this are checked, so a periodic run can skip
+    /// what its previous pass already covered.
+    pub since: Option<DateTime<Utc>>,
+    /// Only these markets are checked.
+    pub market_ids: Option<Vec<u64>>,
+}
+
+/// Candles written for one run of missing minutes by
+/// [`backfill_missing_candles`].
+#[derive(Clone, Debug)]
+pub struct CandleBackfill {
+    pub market_id: u64,
+    pub gap_start: DateTime<Utc>,
+    pub gap_end: DateTime<Utc>,
+    pub candles_written: u64,
+}
+
+/// Find minutes inside `window` with raw events but no candle (typically
+/// because the market's `market_configs` row arrived late) and rebuild them.
+pub async fn backfill_missing_candles(
+    pool: &Pool,
+    window: &CandleBackfillWindow,
+) -> Result<Vec<CandleBackfill>> {
+    let market_ids = window
+        .market_ids
+        .as_ref()
+        .map(|market_ids| {
+            market_ids
+                .iter()
+                .map(|&market_id| i64::try_from(market_id).context("market_id out of range"))
+                .collect::<Result<Vec<_>>>()
+        })
+        .transpose()?;
+    let client = pool.get().await.context("Failed to get connection")?;
+    let gaps: Vec<(i64, DateTime<Utc>, DateTime<Utc>)> = client
+        .query(
+            MISSING_CANDLE_RANGES_SQL,
+            &[
+                &window.candle_retention,
+                &window.lookback,
+                &window.since,
+                &market_ids,
+            ],
+        )
+        .await
+        .context("Failed to find missing candles")?
+        .iter()
+        .map(|row| {
+            (
+                row.get("market_id"),
+                row.get("gap_start"),
+                row.get("gap_end"),
+            )
+        })
+        .collect();
+
+    let mut backfills = Vec::with_capacity(gaps.len());
+    for (market_id, gap_start, gap_end) in gaps {
+        let candles_written: i64 = client
+            .query_one(
+                BACKFILL_CANDLE_RANGE_SQL,
+                &[&market_id, &gap_start, &gap_end],
+            )
+            .await
+            .with_context(|| {
+                format!("Failed to backfill candles for market_id={market_id} from {gap_start}")
+            })?
+            .get(0);
+        backfills.push(CandleBackfill {
+            market_id: u64::try_from(market_id).context("market_id out of range")?,
+            gap_start,
+            gap_end,
+            candles_written: candles_written.max(0) as u64,
+        });
+    }
+
+    Ok(backfills)
+}
+
+/// Rebuild `market_fees_1d` days that are missing or short of positions from
+/// the raw close-position events. Returns the number of days written.
+pub async fn backfill_fee_rollup(pool: &Pool) -> Result<u64> {
+    let client = pool.get().await.context("Failed to get connection")?;
+    let days_written: i64 = client
+        .query_one(BACKFILL_FEE_ROLLUP_SQL, &[])
+        .await
+        .context("Failed to backfill fee rollup")?
+        .get(0);
+    Ok(days_written.max(0) as u64)
+}
+
+/// Point-in-time copy of a TwoB `Market` account (plus its bookkeeping).
+#[derive(Clone, Debug)]
+pub struct MarketStateSnapshotRecord {
+    pub market_id: u64,
+    pub snapshot_time: DateTime<Utc>,
+    pub slot: u64,
+    pub is_paused: bool,
+    pub fee_bps: u8,
+    pub unhealthy_liquidity_fee_bps: u8,
+    pub open_positions: u64,
+    pub accumulated_base_fees: u64,
+    pub accumulated_quote_fees: u64,
+    pub base_flow: u128,
+    pub quote_flow: u128,
+    pub end_slot_interval: u64,
+    pub bookkeeping: Option<BookkeepingSnapshot>,
+}
+
+#[derive(Clone, Debug)]
+pub struct BookkeepingSnapshot {
+    pub base_per_quote: u128,
+    pub quote_per_base: u128,
+    pub slots_without_trade: u64,
+    pub last_update_slot: u64,
+}
+
+pub async fn insert_market_state_snapshot(
+    pool: &Pool,
+    record: &MarketStateSnapshotRecord,
+) -> Result<()> {
+    let bookkeeping = record.bookkeeping.as_ref();
+    let client = pool.get().await.context("Failed to get connection")?;
+    client
+        .execute(
+            INSERT_MARKET_STATE_SNAPSHOT_SQL,
+            &[
+                &(record.market_id as i64),
+                &record.snapshot_time,
+                &(record.slot as i64),
+                &record.is_paused,
+                &i16::from(record.fee_bps),
+                &i16::from(record.unhealthy_liquidity_fee_bps),
+                &(record.open_positions as i64),
+                &record.accumulated_base_fees.to_string(),
+                &record.accumulated_quote_fees.to_string(),
+                &record.base_flow.to_string(),
+                &record.quote_flow.to_string(),
+                &(record.end_slot_interval as i64),
+                &bookkeeping.map(|bookkeeping| bookkeeping.base_per_quote.to_string()),
+                &bookkeeping.map(|bookkeeping| bookkeeping.quote_per_base.to_string()),
+                &bookkeeping.map(|bookkeeping| bookkeeping.slots_without_trade as i64),
+                &bookkeeping.map(|bookkeeping| bookkeeping.last_update_slot as i64),
+            ],
+        )
+        .await
+        .context("Failed to insert market state snapshot")?;
+    Ok(())
+}
+
+/// Current state of one on-chain `TradePosition` account.
+#[derive(Clone, Debug)]
+pub struct TradePositionRecord {
+    pub address: String,
+    pub market_id: u64,
+    pub authority: String,
+    pub position_id: u64,
+    pub is_buy: bool,
+    pub amount: u64,
+    pub start_slot: u64,
+    pub end_slot: u64,
+    /// Slot the account was read at.
+    pub observed_slot: u64,
+}
+
+/// Insert or refresh an open trade position. Returns `false` when the stored
+/// row was observed at a later slot and was left alone.
+pub async fn upsert_trade_position(pool: &Pool, record: &TradePositionRecord) -> Result<bool> {
+    let client = pool.get().await.context("Failed to get connection")?;
+    let changed = client
+        .execute(
+            UPSERT_TRADE_POSITION_SQL,
+            &[
+                &record.address,
+                &(record.market_id as i64),
+                &record.authority,
+                &record.position_id.to_string(),
+                &record.is_buy,
+                &record.amount.to_string(),
+                &(record.start_slot as i64),
+                &(record.end_slot as i64),
+                &(record.observed_slot as i64),
+            ],
+        )
+        .await
+        .context("Failed to upsert trade position")?;
+    Ok(changed > 0)
+}
+
+/// Remove every trade position that a full account scan at `slot` did not
+/// return. Returns the number of rows deleted.
+pub async fn prune_trade_positions(
+    pool: &Pool,
+    open_addresses: &[String],
+    slot: u64,
+) -> Result<u64> {
+    let client = pool.get().await.context("Failed to get connection")?;
+    client
+        .execute(
+            PRUNE_TRADE_POSITIONS_SQL,
+            &[&open_addresses, &(slot as i64)],
+        )
+        .await
+        .context("Failed to prune closed trade positions")
+}
+
+/// Current state of one on-chain `LiquidityPosition` account.
+#[derive(Clone, Debug)]
+pub struct LiquidityPositionRecord {
+    pub address: String,
+    pub market_id: u64,
+    pub authority: String,
+    /// Balances at `last_update_slot`, scaled by the bookkeeping precision.
+    pub base_balance: u128,
+    pub quote_balance: u128,
+    pub base_per_quote_snapshot: u128,
+    pub quote_per_base_snapshot: u128,
+    pub slots_without_trade_snapshot: u64,
+    pub base_flow: u64,
+    pub quote_flow: u64,
+    pub base_debt: u64,
+    pub quote_debt: u64,
+    pub last_update_slot: u64,
+    /// Slot the account was read at.
+    pub observed_slot: u64,
+}
+
+/// Insert or refresh a liquidity position. Returns `false` when the stored row
+/// was observed at a later slot and was left alone.
+pub async fn upsert_liquidity_position(
+    pool: &Pool,
+    record: &LiquidityPositionRecord,
+) -> Result<bool> {
+    let client = pool.get().await.context("Failed to get connection")?;
+    let changed = client
+        .execute(
+            UPSERT_LIQUIDITY_POSITION_SQL,
+            &[
+                &record.address,
+                &(record.market_id as i64),
+                &record.authority,
+                &record.base_balance.to_string(),
+                &record.quote_balance.to_string(),
+                &record.base_per_quote_snapshot.to_string(),
+                &record.quote_per_base_snapshot.to_string(),
+                &(record.slots_without_trade_snapshot as i64),
+                &record.base_flow.to_string(),
+                &record.quote_flow.to_string(),
+                &record.base_debt.to_string(),
+                &record.quote_debt.to_string(),
+                &(record.last_update_slot as i64),
+                &(record.observed_slot as i64),
+            ],
+        )
+        .await
+        .context("Failed to upsert liquidity position")?;
+    Ok(changed > 0)
+}
+
+/// Remove every liquidity position that a full account scan at `slot` did not
+/// return. Returns the number of rows deleted.
+pub async fn prune_liquidity_positions(
+    pool: &Pool,
+    open_addresses: &[String],
+    slot: u64,
+) -> Result<u64> {
+    let client = pool.get().await.context("Failed to get connection")?;
+    client
+        .execute(
+            PRUNE_LIQUIDITY_POSITIONS_SQL,
+            &[&open_addresses, &(slot as i64)],
+        )
+        .await
+        .context("Failed to prune closed liquidity positions")
+}
+
+/// Flow that leaves a market at one slot because trade positions end there,
+/// from an on-chain `Exits` account.
+#[derive(Clone, Debug)]
+pub struct MarketExitRecord {
+    pub exit_slot: u64,
+    pub reference_index: u64,
+    pub base_exit: u128,
+    pub quote_exit: u128,
+}
+
+/// Replace a market's upcoming exits with the ones read at `observed_slot`.
+pub async fn replace_market_exits(
+    pool: &Pool,
+    market_id: u64,
+    observed_slot: u64,
+    exits: &[MarketExitRecord],
+) -> Result<()> {
+    let mut client = pool.get().await.context("Failed to get connection")?;
+    let transaction = client
+        .transaction()
+        .await
+        .context("Failed to start transaction")?;
+    transaction
+        .execute(DELETE_MARKET_EXITS_SQL, &[&(market_id as i64)])
+        .await
+        .context("Failed to delete market exits")?;
+    let insert = transaction
+        .prepare_cached(INSERT_MARKET_EXIT_SQL)
+        .await
+        .context("Failed to prepare market exit insert")?;
+    for exit in exits {
+        transaction
+            .execute(
+                &insert,
+                &[
+                    &(market_id as i64),
+                    &(exit.exit_slot as i64),
+                    &(exit.reference_index as i64),
+                    &exit.base_exit.to_string(),
+                    &exit.quote_exit.to_string(),
+                    &(observed_slot as i64),
+                ],
+            )
+            .await
+            .context("Failed to insert market exit")?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit market exits")
+}
+
 pub struct TimescaleSink {
     pool: Pool,
     metrics: Arc<DatabaseMetrics>,
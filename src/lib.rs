@@ -4,12 +4,17 @@
 //! This library provides utilities for the bookkeeper, liquidity-keeper, and trade-keeper binaries.
 
 pub mod accounts;
+pub mod api;
+pub mod candles;
+pub mod client;
 pub mod database;
+pub mod policies;
 pub mod sink;
 
 // Re-export commonly used types
 pub use accounts::{AccountResolver, PdaResult};
-pub use database::TimescaleSink;
+pub use database::{StorageBackend, TimescaleSink};
+pub use policies::StoragePolicies;
 pub use sink::{
     ClosePositionEventRecord, EventSink, FanoutSink, MarketUpdateEventRecord, SinkMetricsSnapshot,
 };
@@ -23,3 +28,10 @@ pub fn program_id() -> anchor_lang::prelude::Pubkey {
 }
 
 pub const ARRAY_LENGTH: u64 = 10;
+
+/// Default scale of the program's bookkeeping values: `Bookkeeping` rates and
+/// `LiquidityPosition` balances are token atoms times the program's
+/// `BOOKKEEPING_PRECISION_FACTOR`. The IDL (`idls/twob_anchor.json`, program
+/// 0.1.0) names that constant but does not export its value, so this is only a
+/// default; read-api takes the deployed value from `BOOKKEEPING_PRECISION_FACTOR`.
+pub const BOOKKEEPING_PRECISION_FACTOR: u128 = 1_000_000_000_000;
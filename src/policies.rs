@@ -0,0 +1,564 @@
+//! Timescale compression, retention and downsampling policies.
+//!
+//! The hypertables in `docs/timescale-schema.sql` grow without bound unless
+//! policies are attached to them. This module describes the desired policy set
+//! for a deployment (read from the environment) and reconciles the database
+//! towards it, so policies are managed by the project instead of by hand.
+//!
+//! Reconciliation is declarative: applying a policy set replaces any existing
+//! compression/retention job on the table, and a disabled policy removes it.
+//! Disabling the hourly rollup unschedules its refresh and retention jobs but
+//! keeps the view and the candles already in it.
+
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::env;
+use tokio_postgres::Client;
+use utoipa::ToSchema;
+
+use crate::database::validate_table_name;
+
+const DEFAULT_MARKET_UPDATES_TABLE: &str = "raw_market_update_events";
+const DEFAULT_CLOSE_POSITION_EVENTS_TABLE: &str = "raw_close_position_events";
+const DEFAULT_CANDLES_1M_TABLE: &str = "market_candles_1m";
+const DEFAULT_CANDLES_1H_TABLE: &str = "market_candles_1h";
+
+const DEFAULT_RAW_COMPRESS_AFTER: &str = "7 days";
+const DEFAULT_CANDLES_COMPRESS_AFTER: &str = "30 days";
+
+/// Desired compression/retention settings for one hypertable.
+#[derive(Clone, Debug)]
+pub struct TablePolicy {
+    pub table: String,
+    /// Columns used for `timescaledb.compress_segmentby`.
+    pub segment_by: &'static str,
+    /// Columns used for `timescaledb.compress_orderby`.
+    pub order_by: &'static str,
+    /// Compress chunks older than this interval; `None` removes the policy.
+    pub compress_after: Option<String>,
+    /// Drop chunks older than this interval; `None` removes the policy.
+    pub retain_for: Option<String>,
+}
+
+/// Hourly candle rollup maintained as a continuous aggregate over the 1m table.
+///
+/// Lets deployments keep long-range charts while applying a short retention to
+/// `market_candles_1m`.
+#[derive(Clone, Debug)]
+pub struct CandleRollupPolicy {
+    pub view: String,
+    pub source_table: String,
+    pub refresh_start_offset: String,
+    pub refresh_end_offset: String,
+    pub refresh_schedule: String,
+    pub retain_for: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct StoragePolicies {
+    pub tables: Vec<TablePolicy>,
+    pub candle_rollup: Option<CandleRollupPolicy>,
+    /// View of the hourly rollup, also when it is disabled so its jobs can be
+    /// removed.
+    pub candle_rollup_view: String,
+}
+
+impl StoragePolicies {
+    /// Read the policy set from the environment.
+    ///
+    /// Every interval variable accepts a Postgres interval literal (`"7 days"`)
+    /// or `off` to remove the policy. Compression defaults to 7 days for raw
+    /// event tables and 30 days for candles; retention is off unless set.
+    pub fn from_env() -> Result<Self> {
+        let market_updates_table = validate_table_name(&env_or(
+            "MARKET_UPDATES_TABLE",
+            DEFAULT_MARKET_UPDATES_TABLE,
+        ))?;
+        let close_position_events_table = validate_table_name(&env_or(
+            "CLOSE_POSITION_EVENTS_TABLE",
+            DEFAULT_CLOSE_POSITION_EVENTS_TABLE,
+        ))?;
+        let candles_1m_table =
+            validate_table_name(&env_or("CANDLES_1M_TABLE", DEFAULT_CANDLES_1M_TABLE))?;
+
+        let tables = vec![
+            TablePolicy {
+                table: market_updates_table,
+                segment_by: "market_id",
+                order_by: "event_time DESC, slot DESC",
+                compress_after: interval_env(
+                    "MARKET_UPDATES_COMPRESS_AFTER",
+                    Some(DEFAULT_RAW_COMPRESS_AFTER),
+                )?,
+                retain_for: interval_env("MARKET_UPDATES_RETENTION", None)?,
+            },
+            TablePolicy {
+                table: close_position_events_table,
+                segment_by: "market_id",
+                order_by: "event_time DESC, slot DESC",
+                compress_after: interval_env(
+                    "CLOSE_POSITION_EVENTS_COMPRESS_AFTER",
+                    Some(DEFAULT_RAW_COMPRESS_AFTER),
+                )?,
+                retain_for: interval_env("CLOSE_POSITION_EVENTS_RETENTION", None)?,
+            },
+            TablePolicy {
+                table: candles_1m_table.clone(),
+                segment_by: "market_id",
+                order_by: "bucket_start DESC",
+                compress_after: interval_env(
+                    "CANDLES_1M_COMPRESS_AFTER",
+                    Some(DEFAULT_CANDLES_COMPRESS_AFTER),
+                )?,
+                retain_for: candles_1m_retention_from_env()?,
+            },
+        ];
+
+        let candle_rollup_view =
+            validate_table_name(&env_or("CANDLES_1H_TABLE", DEFAULT_CANDLES_1H_TABLE))?;
+        let candle_rollup = if env_flag("CANDLES_1H_ROLLUP")? {
+            Some(CandleRollupPolicy {
+                view: candle_rollup_view.clone(),
+                source_table: candles_1m_table,
+                refresh_start_offset: "3 days".to_string(),
+                refresh_end_offset: "1 hour".to_string(),
+                refresh_schedule: "30 minutes".to_string(),
+                retain_for: interval_env("CANDLES_1H_RETENTION", None)?,
+            })
+        } else {
+            None
+        };
+
+        Ok(Self {
+            tables,
+            candle_rollup,
+            candle_rollup_view,
+        })
+    }
+
+    /// Reconcile the database towards this policy set.
+    ///
+    /// Returns a human-readable line per action taken.
+    pub async fn apply(&self, client: &Client) -> Result<Vec<String>> {
+        let mut actions = Vec::new();
+
+        for policy in &self.tables {
+            apply_table_policy(client, policy, &mut actions)
+                .await
+                .with_context(|| format!("Failed to apply policies for {}", policy.table))?;
+        }
+
+        match &self.candle_rollup {
+            Some(rollup) => apply_candle_rollup(client, rollup, &mut actions)
+                .await
+                .with_context(|| format!("Failed to apply candle rollup {}", rollup.view))?,
+            None => disable_candle_rollup(client, &self.candle_rollup_view, &mut actions)
+                .await
+                .with_context(|| {
+                    format!(
+                        "Failed to disable candle rollup {}",
+                        self.candle_rollup_view
+                    )
+                })?,
+        }
+
+        Ok(actions)
+    }
+}
+
+async fn apply_table_policy(
+    client: &Client,
+    policy: &TablePolicy,
+    actions: &mut Vec<String>,
+) -> Result<()> {
+    let table = policy.table.as_str();
+
+    match &policy.compress_after {
+        Some(compress_after) => {
+            if !compression_enabled(client, table).await? {
+                // DDL options cannot be bound as parameters; the column lists are
+                // compile-time constants and the table name is validated.
+                client
+                    .batch_execute(&format!(
+                        "ALTER TABLE {table} SET ( \
+                            timescaledb.compress, \
+                            timescaledb.compress_segmentby = '{}', \
+                            timescaledb.compress_orderby = '{}')",
+                        policy.segment_by, policy.order_by
+                    ))
+                    .await
+                    .context("Failed to enable compression")?;
+                actions.push(format!("{table}: enabled compression"));
+            }
+
+            client
+                .execute(
+                    "SELECT remove_compression_policy($1::text::regclass, if_exists => true)",
+                    &[&table],
+                )
+                .await
+                .context("Failed to remove previous compression policy")?;
+            client
+                .execute(
+                    "SELECT add_compression_policy($1::text::regclass, $2::text::interval)",
+                    &[&table, compress_after],
+                )
+                .await
+                .context("Failed to add compression policy")?;
+            actions.push(format!("{table}: compress after {compress_after}"));
+        }
+        None => {
+            client
+                .execute(
+                    "SELECT remove_compression_policy($1::text::regclass, if_exists => true)",
+                    &[&table],
+                )
+                .await
+                .context("Failed to remove compression policy")?;
+            actions.push(format!("{table}: compression policy off"));
+        }
+    }
+
+    apply_retention(client, table, policy.retain_for.as_deref(), actions).await
+}
+
+async fn apply_candle_rollup(
+    client: &Client,
+    rollup: &CandleRollupPolicy,
+    actions: &mut Vec<String>,
+) -> Result<()> {
+    let view = rollup.view.as_str();
+
+    // Same column layout as the 1m table so read-api can query either source
+    // with the same gap-fill statement. Real-time aggregation keeps the
+    // not-yet-materialized tail (the current hour) visible.
+    client
+        .batch_execute(&format!(
+            "CREATE MATERIALIZED VIEW IF NOT EXISTS {view} \
+             WITH (timescaledb.continuous, timescaledb.materialized_only = false) AS \
+             SELECT \
+                 market_id, \
+                 time_bucket(INTERVAL '1 hour', bucket_start) AS bucket_start, \
+                 first(open, bucket_start) AS open, \
+                 max(high) AS high, \
+                 min(low) AS low, \
+                 last(close, bucket_start) AS close \
+             FROM {} \
+             GROUP BY market_id, time_bucket(INTERVAL '1 hour', bucket_start) \
+             WITH NO DATA",
+            rollup.source_table
+        ))
+        .await
+        .context("Failed to create continuous aggregate")?;
+
+    client
+        .execute(
+            "SELECT remove_continuous_aggregate_policy($1::text::regclass, if_exists => true)",
+            &[&view],
+        )
+        .await
+        .context("Failed to remove previous refresh policy")?;
+    client
+        .execute(
+            "SELECT add_continuous_aggregate_policy($1::text::regclass, \
+                start_offset => $2::text::interval, \
+                end_offset => $3::text::interval, \
+                schedule_interval => $4::text::interval)",
+            &[
+                &view,
+                &rollup.refresh_start_offset,
+                &rollup.refresh_end_offset,
+                &rollup.refresh_schedule,
+            ],
+        )
+        .await
+        .context("Failed to add refresh policy")?;
+    actions.push(format!(
+        "{view}: hourly rollup of {} refreshed every {}",
+        rollup.source_table, rollup.refresh_schedule
+    ));
+
+    apply_retention(client, view, rollup.retain_for.as_deref(), actions).await
+}
+
+/// Stop maintaining a rollup created by an earlier run. The view itself is left
+/// in place: dropping it would lose candles the 1m retention may have removed.
+async fn disable_candle_rollup(
+    client: &Client,
+    view: &str,
+    actions: &mut Vec<String>,
+) -> Result<()> {
+    let exists: bool = client
+        .query_one("SELECT to_regclass($1) IS NOT NULL", &[&view])
+        .await
+        .context("Failed to look up rollup view")?
+        .get(0);
+    if !exists {
+        return Ok(());
+    }
+
+    client
+        .execute(
+            "SELECT remove_continuous_aggregate_policy($1::text::regclass, if_exists => true)",
+            &[&view],
+        )
+        .await
+        .context("Failed to remove refresh policy")?;
+    client
+        .execute(
+            "SELECT remove_retention_policy($1::text::regclass, if_exists => true)",
+            &[&view],
+        )
+        .await
+        .context("Failed to remove retention policy")?;
+    actions.push(format!(
+        "{view}: hourly rollup off, refresh and retention policies removed"
+    ));
+    Ok(())
+}
+
+async fn apply_retention(
+    client: &Client,
+    relation: &str,
+    retain_for: Option<&str>,
+    actions: &mut Vec<String>,
+) -> Result<()> {
+    client
+        .execute(
+            "SELECT remove_retention_policy($1::text::regclass, if_exists => true)",
+            &[&relation],
+        )
+        .await
+        .context("Failed to remove previous retention policy")?;
+
+    match retain_for {
+        Some(retain_for) => {
+            client
+                .execute(
+                    "SELECT add_retention_policy($1::text::regclass, $2::text::interval)",
+                    &[&relation, &retain_for],
+                )
+                .await
+                .context("Failed to add retention policy")?;
+            actions.push(format!("{relation}: retain {retain_for}"));
+        }
+        None => actions.push(format!("{relation}: retention policy off")),
+    }
+
+    Ok(())
+}
+
+async fn compression_enabled(client: &Client, table: &str) -> Result<bool> {
+    let (schema, name) = split_relation(table);
+    let row = client
+        .query_opt(
+            "SELECT compression_enabled FROM timescaledb_information.hypertables \
+             WHERE hypertable_schema = $1 AND hypertable_name = $2",
+            &[&schema, &name],
+        )
+        .await
+        .context("Failed to read hypertable metadata")?
+        .ok_or_else(|| anyhow!("{table} is not a hypertable"))?;
+    Ok(row.get(0))
+}
+
+/// Chunk and compression statistics for one hypertable.
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct HypertableStats {
+    pub schema: String,
+    pub table: String,
+    pub compression_enabled: bool,
+    pub total_chunks: i64,
+    pub compressed_chunks: i64,
+    pub total_bytes: Option<i64>,
+    pub before_compression_bytes: Option<i64>,
+    pub after_compression_bytes: Option<i64>,
+    pub oldest_chunk_start: Option<String>,
+    pub newest_chunk_end: Option<String>,
+    pub compress_after: Option<String>,
+    pub retain_for: Option<String>,
+}
+
+/// Collect chunk/compression statistics and active policy settings for every
+/// hypertable in the database.
+pub async fn storage_stats(client: &Client) -> Result<Vec<HypertableStats>> {
+    let rows = client
+        .query(
+            "SELECT \
+                h.hypertable_schema::text AS schema, \
+                h.hypertable_name::text AS name, \
+                h.compression_enabled, \
+                count(c.chunk_name) AS total_chunks, \
+                count(c.chunk_name) FILTER (WHERE c.is_compressed) AS compressed_chunks, \
+                to_char(min(c.range_start) AT TIME ZONE 'UTC', 'YYYY-MM-DD\"T\"HH24:MI:SS\"Z\"') AS oldest_chunk_start, \
+                to_char(max(c.range_end) AT TIME ZONE 'UTC', 'YYYY-MM-DD\"T\"HH24:MI:SS\"Z\"') AS newest_chunk_end, \
+                hypertable_size(format('%I.%I', h.hypertable_schema, h.hypertable_name)::regclass) AS total_bytes, \
+                (SELECT j.config->>'compress_after' FROM timescaledb_information.jobs j \
+                  WHERE j.proc_name = 'policy_compression' \
+                    AND j.hypertable_schema = h.hypertable_schema \
+                    AND j.hypertable_name = h.hypertable_name LIMIT 1) AS compress_after, \
+                (SELECT j.config->>'drop_after' FROM timescaledb_information.jobs j \
+                  WHERE j.proc_name = 'policy_retention' \
+                    AND j.hypertable_schema = h.hypertable_schema \
+                    AND j.hypertable_name = h.hypertable_name LIMIT 1) AS retain_for \
+             FROM timescaledb_information.hypertables h \
+             LEFT JOIN timescaledb_information.chunks c \
+               ON c.hypertable_schema = h.hypertable_schema \
+              AND c.hypertable_name = h.hypertable_name \
+             GROUP BY h.hypertable_schema, h.hypertable_name, h.compression_enabled \
+             ORDER BY h.hypertable_schema, h.hypertable_name",
+            &[],
+        )
+        .await
+        .context("Failed to query hypertable chunk stats")?;
+
+    let mut stats = Vec::with_capacity(rows.len());
+    for row in rows {
+        let schema: String = row.get("schema");
+        let table: String = row.get("name");
+        let compression_enabled: bool = row.get("compression_enabled");
+
+        let (before_compression_bytes, after_compression_bytes) = if compression_enabled {
+            let relation = format!("{schema}.{table}");
+            let compression_row = client
+                .query_opt(
+                    "SELECT before_compression_total_bytes, after_compression_total_bytes \
+                     FROM hypertable_compression_stats($1::text::regclass)",
+                    &[&relation],
+                )
+                .await
+                .with_context(|| format!("Failed to query compression stats for {relation}"))?;
+            match compression_row {
+                Some(row) => (row.get(0), row.get(1)),
+                None => (None, None),
+            }
+        } else {
+            (None, None)
+        };
+
+        stats.push(HypertableStats {
+            schema,
+            table,
+            compression_enabled,
+            total_chunks: row.get("total_chunks"),
+            compressed_chunks: row.get("compressed_chunks"),
+            total_bytes: row.get("total_bytes"),
+            before_compression_bytes,
+            after_compression_bytes,
+            oldest_chunk_start: row.get("oldest_chunk_start"),
+            newest_chunk_end: row.get("newest_chunk_end"),
+            compress_after: row.get("compress_after"),
+            retain_for: row.get("retain_for"),
+        });
+    }
+
+    Ok(stats)
+}
+
+fn split_relation(relation: &str) -> (&str, &str) {
+    relation.split_once('.').unwrap_or(("public", relation))
+}
+
+fn env_or(key: &str, default_value: &str) -> String {
+    env::var(key).unwrap_or_else(|_| default_value.to_string())
+}
+
+fn env_flag(key: &str) -> Result<bool> {
+    match env::var(key) {
+        Ok(raw) => match raw.trim().to_ascii_lowercase().as_str() {
+            "" | "0" | "false" | "off" | "no" => Ok(false),
+            "1" | "true" | "on" | "yes" => Ok(true),
+            other => Err(anyhow!("{key} must be a boolean, got '{other}'")),
+        },
+        Err(env::VarError::NotPresent) => Ok(false),
+        Err(error) => Err(anyhow!("Failed to read {key}: {error}")),
+    }
+}
+
+/// `CANDLES_1M_RETENTION`, which also bounds candle backfills.
+pub fn candles_1m_retention_from_env() -> Result<Option<String>> {
+    interval_env("CANDLES_1M_RETENTION", None)
+}
+
+/// An interval variable in the grammar of [`parse_interval`]; `off` gives
+/// `None`.
+pub fn interval_env(key: &str, default_value: Option<&str>) -> Result<Option<String>> {
+    let raw = match env::var(key) {
+        Ok(raw) if !raw.trim().is_empty() => raw,
+        Ok(_) | Err(env::VarError::NotPresent) => {
+            return Ok(default_value.map(str::to_string));
+        }
+        Err(error) => return Err(anyhow!("Failed to read {key}: {error}")),
+    };
+
+    parse_interval(&raw).with_context(|| format!("{key} is not a valid interval"))
+}
+
+/// Accept `off`/`none` or a simple `<count> <unit>` interval such as `7 days`.
+///
+/// Intervals are bound as parameters, but restricting the grammar keeps typos
+/// from turning into surprising Postgres interval arithmetic.
+fn parse_interval(raw: &str) -> Result<Option<String>> {
+    let trimmed = raw.trim().to_ascii_lowercase();
+    if matches!(trimmed.as_str(), "off" | "none" | "disabled") {
+        return Ok(None);
+    }
+
+    let (count, unit) = trimmed
+        .split_once(char::is_whitespace)
+        .ok_or_else(|| anyhow!("expected '<count> <unit>', got '{raw}'"))?;
+    let count: u32 = count
+        .parse()
+        .map_err(|_| anyhow!("interval count must be a positive integer, got '{count}'"))?;
+    if count == 0 {
+        return Err(anyhow!("interval count must be greater than 0"));
+    }
+
+    let unit = unit.trim();
+    let unit = match unit.strip_suffix('s').unwrap_or(unit) {
+        "hour" => "hours",
+        "day" => "days",
+        "week" => "weeks",
+        "month" => "months",
+        "year" => "years",
+        other => return Err(anyhow!("unsupported interval unit '{other}'")),
+    };
+
+    Ok(Some(format!("{count} {unit}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_interval_normalizes_units() {
+        assert_eq!(parse_interval("7 days").unwrap().as_deref(), Some("7 days"));
+        assert_eq!(parse_interval("1 Day").unwrap().as_deref(), Some("1 days"));
+        assert_eq!(
+            parse_interval(" 12  hours ").unwrap().as_deref(),
+            Some("12 hours")
+        );
+        assert_eq!(parse_interval("off").unwrap(), None);
+    }
+
+    #[test]
+    fn test_parse_interval_rejects_malformed_values() {
+        assert!(parse_interval("7").is_err());
+        assert!(parse_interval("0 days").is_err());
+        assert!(parse_interval("7 fortnights").is_err());
+        assert!(parse_interval("1 day; DROP TABLE x").is_err());
+    }
+
+    #[test]
+    fn test_split_relation_defaults_to_public_schema() {
+        assert_eq!(
+            split_relation("market_candles_1m"),
+            ("public", "market_candles_1m")
+        );
+        assert_eq!(
+            split_relation("analytics.candles"),
+            ("analytics", "candles")
+        );
+    }
+}
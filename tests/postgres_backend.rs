@@ -0,0 +1,216 @@
+//! End-to-end pipeline check against a stock Postgres (`STORAGE_BACKEND=postgres`).
+//!
+//! Needs a throwaway database; the test drops and recreates every table in it:
+//!
+//! ```bash
+//! TEST_DATABASE_URL=postgres://postgres@localhost/twob_test?sslmode=disable \
+//!     cargo test --test postgres_backend -- --ignored
+//! ```
+
+use chrono::{Duration, DurationRound, Utc};
+use rust_decimal::Decimal;
+use twob_keepers::{
+    ClosePositionEventRecord, EventSink, MarketUpdateEventRecord, StorageBackend, TimescaleSink,
+    candles::query_gapfilled_candles,
+    database::{
+        CandleBackfillWindow, MARKET_UPDATES_CHANNEL, MarketConfigRecord, backfill_fee_rollup,
+        backfill_missing_candles, connect_listener, connect_pool, upsert_market_config,
+    },
+};
+
+const DROP_TABLES_SQL: &str = "\
+DROP TABLE IF EXISTS market_configs, raw_market_update_events, raw_close_position_events, \
+    market_candles_1m, market_state_snapshots, trade_positions, liquidity_positions, \
+    market_exits, market_fees_1d CASCADE";
+
+fn close_position(signature: &str, fee_amount: u64, is_buy: bool) -> ClosePositionEventRecord {
+    ClosePositionEventRecord {
+        signature: signature.to_string(),
+        event_index: 0,
+        slot: 1,
+        position_authority: "authority".to_string(),
+        market_id: 1,
+        start_slot: 0,
+        end_slot: 1,
+        deposit_amount: 1_000,
+        swapped_amount: 900,
+        remaining_amount: 0,
+        fee_amount,
+        is_buy: u8::from(is_buy),
+    }
+}
+
+fn market_update(signature: &str, base_flow: u64, quote_flow: u64) -> MarketUpdateEventRecord {
+    MarketUpdateEventRecord {
+        signature: signature.to_string(),
+        event_index: 0,
+        slot: 1,
+        market_id: 1,
+        base_flow,
+        quote_flow,
+    }
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL pointing at a throwaway Postgres database"]
+async fn test_pipeline_on_plain_postgres() {
+    let database_url = std::env::var("TEST_DATABASE_URL").expect("TEST_DATABASE_URL must be set");
+
+    let pool = connect_pool(&database_url, 2).unwrap();
+    {
+        let client = pool.get().await.unwrap();
+        client.batch_execute(DROP_TABLES_SQL).await.unwrap();
+        client
+            .batch_execute(StorageBackend::Postgres.schema_sql())
+            .await
+            .unwrap();
+    }
+
+    let (_listener, mut notifications) = connect_listener(&database_url, &[MARKET_UPDATES_CHANNEL])
+        .await
+        .unwrap();
+
+    // An event that arrives before its market config produces no candle...
+    let sink = TimescaleSink::connect(&database_url).await.unwrap();
+    sink.insert_market_update_event(market_update("sig-1", 1_000_000, 2_000_000))
+        .await
+        .unwrap();
+
+    // ...but still notifies listeners.
+    let notification =
+        tokio::time::timeout(std::time::Duration::from_secs(5), notifications.recv())
+            .await
+            .expect("no NOTIFY within 5s")
+            .unwrap();
+    assert_eq!(notification.payload(), "1");
+
+    let changed = upsert_market_config(
+        &pool,
+        &MarketConfigRecord {
+            market_id: 1,
+            base_mint: "base".to_string(),
+            quote_mint: "quote".to_string(),
+            base_decimals: 6,
+            quote_decimals: 6,
+            base_ticker: None,
+            quote_ticker: None,
+        },
+    )
+    .await
+    .unwrap();
+    assert!(changed);
+
+    // ...until the backfill rebuilds it.
+    let window = CandleBackfillWindow::default();
+    let backfills = backfill_missing_candles(&pool, &window).await.unwrap();
+    assert_eq!(backfills.len(), 1);
+    assert_eq!(backfills[0].market_id, 1);
+
+    // Holes between existing candles are found too, but not outside the window.
+    {
+        let client = pool.get().await.unwrap();
+        client
+            .batch_execute(
+                "INSERT INTO market_candles_1m \
+                     (market_id, bucket_start, open, high, low, close, updated_at) \
+                 VALUES (1, date_trunc('minute', now()) - INTERVAL '2 hours', 1, 1, 1, 1, now()); \
+                 INSERT INTO raw_market_update_events \
+                     (event_uid, signature, event_index, slot, market_id, base_flow, quote_flow, \
+                      event_time) \
+                 VALUES \
+                     ('gap', 'gap', 0, 1, 1, 1000000, 4000000, now() - INTERVAL '1 hour'), \
+                     ('old', 'old', 0, 1, 1, 1000000, 5000000, now() - INTERVAL '3 days')",
+            )
+            .await
+            .unwrap();
+    }
+    let recent = CandleBackfillWindow {
+        candle_retention: Some("1 days".to_string()),
+        ..CandleBackfillWindow::default()
+    };
+    // A later pass only sees events since the previous one, and only the
+    // markets it is given.
+    for narrowed in [
+        CandleBackfillWindow {
+            since: Some(Utc::now() - Duration::minutes(30)),
+            ..recent.clone()
+        },
+        CandleBackfillWindow {
+            market_ids: Some(vec![2]),
+            ..recent.clone()
+        },
+    ] {
+        assert!(
+            backfill_missing_candles(&pool, &narrowed)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+    let backfills = backfill_missing_candles(&pool, &recent).await.unwrap();
+    assert_eq!(backfills.len(), 1);
+    assert_eq!(backfills[0].candles_written, 2);
+    assert!(backfills[0].gap_start > Utc::now() - Duration::hours(2));
+    assert!(
+        backfill_missing_candles(&pool, &recent)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+
+    sink.insert_market_update_event(market_update("sig-2", 1_000_000, 3_000_000))
+        .await
+        .unwrap();
+
+    let now = Utc::now();
+    let from = now.duration_trunc(Duration::minutes(1)).unwrap() - Duration::minutes(2);
+    let to = from + Duration::minutes(4);
+    let client = pool.get().await.unwrap();
+    let candles = query_gapfilled_candles(&client, "market_candles_1m", 1, from, to, 60)
+        .await
+        .unwrap();
+
+    assert_eq!(candles.len(), 4);
+    // Both events usually land in one minute, but may straddle a boundary.
+    let filled: Vec<_> = candles
+        .iter()
+        .filter(|candle| candle.close.is_some())
+        .collect();
+    assert!(!filled.is_empty() && filled.len() <= 2);
+    assert_eq!(filled[0].low, Some(Decimal::from(2)));
+    assert_eq!(filled.last().unwrap().high, Some(Decimal::from(3)));
+    assert_eq!(filled.last().unwrap().close, Some(Decimal::from(3)));
+    // The bucket after the last update carries its close forward.
+    assert_eq!(
+        candles.last().unwrap().carried_close,
+        Some(Decimal::from(3))
+    );
+
+    // Close-position fees roll up per UTC day, in the token each side receives.
+    sink.insert_close_position_event(close_position("sig-3", 10, true))
+        .await
+        .unwrap();
+    sink.insert_close_position_event(close_position("sig-4", 7, false))
+        .await
+        .unwrap();
+    // Recomputing from raw events matches the live rollup, so nothing is rewritten.
+    assert_eq!(backfill_fee_rollup(&pool).await.unwrap(), 0);
+    let fees = client
+        .query(
+            "SELECT base_fees, quote_fees, positions FROM market_fees_1d WHERE market_id = 1",
+            &[],
+        )
+        .await
+        .unwrap();
+    let totals: Vec<(Decimal, Decimal, i64)> = fees
+        .iter()
+        .map(|row| (row.get(0), row.get(1), row.get(2)))
+        .collect();
+    // Both events usually land on one day, but may straddle midnight.
+    let base_fees: Decimal = totals.iter().map(|(base, _, _)| *base).sum();
+    let quote_fees: Decimal = totals.iter().map(|(_, quote, _)| *quote).sum();
+    let positions: i64 = totals.iter().map(|(_, _, positions)| *positions).sum();
+    assert_eq!(base_fees, Decimal::from(10));
+    assert_eq!(quote_fees, Decimal::from(7));
+    assert_eq!(positions, 2);
+}